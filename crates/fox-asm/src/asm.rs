@@ -1,12 +1,50 @@
-use std::collections::HashMap;
-use crate::parser::Stmt;
+use std::collections::{HashMap, HashSet};
+use crate::parser::{Stmt, StackEffect, Relax, Field};
+use crate::expr::{self, Expr, Op};
+use crate::span::{Span, Spanned, Source};
+use crate::object::{self, Object, Address, Size, Location};
+use crate::diagnostic::{Diagnostic, Diagnostics};
 use fox_bytecode::*;
-use fox_bytecode::memory::RESET_VECTOR;
+use fox_bytecode::memory::{screen, RESET_VECTOR, MEM_SIZE, STACK_BASE, RSTACK_BASE, LOCALS_BASE, CONSOLE_BASE};
+use fox_bytecode::symbols::Symbols;
+use fox_bytecode::lines::{LineTable, LineEntry};
 
+/// Parts of the address space bytes can't be emitted in.
+const REGIONS: &[(usize, usize, &str)] = &[
+    (0, RESET_VECTOR as usize, "before the reset vector, programs are loaded from 00000100"),
+    (LOCALS_BASE as usize, RSTACK_BASE as usize, "in the locals at the top of memory, which are overwritten while running"),
+    (RSTACK_BASE as usize, STACK_BASE as usize, "in the return stack at the top of memory, which is overwritten while running"),
+    (STACK_BASE as usize, MEM_SIZE as usize, "in the stack at the top of memory, which is overwritten while running"),
+    (MEM_SIZE as usize, CONSOLE_BASE as usize, "past the end of memory"),
+    (CONSOLE_BASE as usize, screen::LAYER0 as usize, "in device space, only labels of ports can be placed there"),
+    (screen::LAYER0 as usize, usize::MAX, "in the screen layers, only labels can be placed there"),
+];
+
+/// Declared stack effect of a label.
+#[derive(Debug)]
+pub struct Declaration {
+    pub label: String,
+    pub addr: u32,
+    pub effect: StackEffect,
+    pub span: Span,
+}
+
+/// A value that is filled in once all labels are known.
 #[derive(Debug)]
 struct Reference {
-    label: String,
+    expr: Spanned<Expr>,
+    /// Label local names are relative to.
+    scope: String,
     index: usize,
+    size: Size,
+    /// Statement of a `LITB` literal and how it was relaxed, to check it fits.
+    literal: Option<(usize, Relax)>,
+}
+
+#[derive(Debug)]
+struct Constant {
+    expr: Spanned<Expr>,
+    scope: String,
 }
 
 pub struct Assembler {
@@ -14,8 +52,35 @@ pub struct Assembler {
     index: usize,
     length: usize,
     labels: HashMap<String, u32>,
+    /// Named values that aren't addresses, like the size of a binary file.
+    /// These can be referenced like labels, but aren't symbols.
+    constants: HashMap<String, Constant>,
+    /// Fields of each `?struct`, for its records.
+    structs: HashMap<String, Vec<Field>>,
     references: Vec<Reference>,
     current_label: String,
+    /// Full name of the last defined label, global or local.
+    last_label: String,
+    declarations: Vec<Declaration>,
+    /// Emitted byte ranges and the statement they came from.
+    lines: Vec<(usize, usize, Span)>,
+    /// `?relax` mode of each file.
+    relax: HashMap<u32, Relax>,
+    /// Statements of literals that didn't fit in a `LITB`, these stay `LITW` in later passes.
+    long: HashSet<usize>,
+    /// Assembling an object, where labels move when linking and unknown labels come from other objects.
+    relocatable: bool,
+    /// References filled in when linking.
+    relocations: Vec<Reference>,
+    exports: Vec<(String, Span)>,
+    /// Address, span and value of references to labels and constants, for the listing.
+    resolved: Vec<(usize, Span, u32)>,
+    /// Literals made `LITB`.
+    short: usize,
+    /// Constants defined before the source, like with `-D` on the command line.
+    defines: Vec<(String, u32)>,
+    diagnostics: Diagnostics,
+    warnings: Diagnostics,
 }
 
 impl Assembler {
@@ -25,113 +90,710 @@ impl Assembler {
             index: RESET_VECTOR as _,
             length: 0,
             labels: HashMap::new(),
+            constants: HashMap::new(),
+            structs: HashMap::new(),
             references: Vec::new(),
             current_label: "on-reset".to_string(),
+            last_label: "on-reset".to_string(),
+            declarations: Vec::new(),
+            lines: Vec::new(),
+            relax: HashMap::new(),
+            long: HashSet::new(),
+            relocatable: false,
+            relocations: Vec::new(),
+            exports: Vec::new(),
+            resolved: Vec::new(),
+            short: 0,
+            defines: Vec::new(),
+            diagnostics: Vec::new(),
+            warnings: Vec::new(),
         }
     }
 
-    pub fn assemble(&mut self, ast: &[Stmt]) {
-        self.parse(ast);
+    /// Assemble an object to link later, see `object`.
+    pub fn relocatable() -> Self {
+        Self {
+            relocatable: true,
+            ..Self::new()
+        }
+    }
+
+    pub fn assemble(&mut self, ast: &[Spanned<Stmt>]) -> Result<(), Diagnostics> {
+        // Literals start out as `LITB` and are made `LITW` when they don't fit, until the layout settles.
+        // Literals only ever grow, so this ends.
+        loop {
+            let long = std::mem::take(&mut self.long);
+            *self = Self {
+                long,
+                relocatable: self.relocatable,
+                defines: std::mem::take(&mut self.defines),
+                ..Self::new()
+            };
+            self.parse(ast);
+
+            let long: Vec<usize> = self.references
+                .iter()
+                .filter_map(|reference| {
+                    let (stmt, relax) = reference.literal?;
+                    // Values that move when linking aren't known yet
+                    let moves = self.relocatable && self.relocation_count(&reference.expr, &reference.scope) != Some(0);
+                    let fits = !(moves || relax == Relax::Numbers && self.uses_labels(&reference.expr, &reference.scope))
+                        && self.evaluate(&reference.expr, &reference.scope, false).is_ok_and(|value| value <= 0xFF);
+                    (!fits).then_some(stmt)
+                })
+                .collect();
+
+            if long.is_empty() {
+                break;
+            }
+            self.long.extend(long);
+        }
+        self.short = self.references.iter().filter(|reference| reference.literal.is_some()).count();
+
+        self.check_layout();
 
         // Resolve references
-        for reference in &self.references {
-            let index = match self.labels.get(&reference.label) {
-                Some(index) => index,
-                None => panic!("Unknown label {}", &reference.label),
+        for reference in std::mem::take(&mut self.references) {
+            // Outside of memory, already reported by `check_layout`
+            let width = match reference.size {
+                Size::Byte => 1,
+                Size::Word => 4,
+            };
+            if reference.index + width > self.data.len() {
+                continue;
+            }
+
+            if self.relocatable && self.relocation_count(&reference.expr, &reference.scope) != Some(0) {
+                self.relocations.push(reference);
+                continue;
+            }
+
+            let value = match self.evaluate(&reference.expr, &reference.scope, false) {
+                Ok(value) => value,
+                Err(diagnostic) => {
+                    self.diagnostics.push(diagnostic);
+                    continue;
+                },
             };
 
-            let [a,b,c,d] = index.to_le_bytes();
-            self.data[reference.index + 0] = a;
-            self.data[reference.index + 1] = b;
-            self.data[reference.index + 2] = c;
-            self.data[reference.index + 3] = d;
+            let mut named = false;
+            Expr::visit_names(&reference.expr, &mut |_, _| named = true);
+            if named {
+                self.resolved.push((reference.index, reference.expr.span, value));
+            }
+
+            match reference.size {
+                Size::Byte => match u8::try_from(value) {
+                    Ok(byte) => self.data[reference.index] = byte,
+                    Err(_) => {
+                        let message = format!("Byte out of range `{:x}`", value);
+                        self.diagnostics.push(Diagnostic::new(message, reference.expr.span));
+                    },
+                },
+                Size::Word => {
+                    self.data[reference.index..reference.index + 4].copy_from_slice(&value.to_le_bytes());
+                },
+            }
         }
 
-        eprintln!("Assembled in {} bytes, {} labels", self.length, self.labels.len());
+        for (name, span) in &self.exports {
+            if !self.labels.contains_key(name) && !self.constants.contains_key(name) {
+                self.diagnostics.push(Diagnostic::new(format!("Can't export `{}`, it isn't defined", name), *span));
+            }
+        }
+
+        if !self.diagnostics.is_empty() {
+            return Err(std::mem::take(&mut self.diagnostics));
+        }
+
+        Ok(())
+    }
+
+    /// Define constant `name` as `value`, before assembling the source.
+    pub fn define(&mut self, name: String, value: u32) {
+        self.defines.push((name, value));
     }
 
-    fn parse(&mut self, ast: &[Stmt]) {
-        for stmt in ast {
-            match stmt {
+    fn parse(&mut self, ast: &[Spanned<Stmt>]) {
+        for (name, value) in self.defines.clone() {
+            self.constants.insert(name, Constant {
+                expr: Spanned::new(Expr::Number(value), Span::default()),
+                scope: self.current_label.clone(),
+            });
+        }
+
+        for (index, stmt) in ast.iter().enumerate() {
+            let start = self.index;
+
+            match &stmt.value {
                 Stmt::OriginAbsolute(value) => {
-                    self.index = *value as _;
+                    match self.evaluate_origin(value) {
+                        Ok(value) => self.index = value as _,
+                        Err(diagnostic) => self.diagnostics.push(diagnostic),
+                    }
+                    continue;
                 },
                 Stmt::LiteralWord(value) => {
-                    self.push_u8(OP_LITW);
-                    self.push_u32(*value);
+                    self.push_literal(index, value.clone(), false);
                 },
                 Stmt::LabelAbsolute(value) => {
                     self.current_label = value.to_string();
-                    self.labels.insert(value.to_string(), self.index as _);
+                    self.define_label(value.to_string(), stmt.span);
                 },
                 Stmt::LocalLabelAbsolute(value) => {
                     let value = format!("{}/{}", self.current_label, value);
-                    self.labels.insert(value.to_string(), self.index as _);
+                    self.define_label(value, stmt.span);
                 },
                 Stmt::LocalReferenceAbsolute(value) => {
-                    let value = format!("{}/{}", self.current_label, value);
-
-                    self.push_u8(OP_LITW);
-
-                    self.references.push(Reference {
-                        label: value.to_string(),
-                        index: self.index,
-                    });
-
-                    self.push_u32(0);
+                    self.push_literal(index, Spanned::new(Expr::Local(value.to_string()), stmt.span), true);
                 },
                 Stmt::ReferenceAbsolute(value) => {
-                    self.push_u8(OP_LITW);
-
-                    self.references.push(Reference {
-                        label: value.to_string(),
-                        index: self.index,
-                    });
-
-                    self.push_u32(0);
+                    self.push_literal(index, Spanned::new(Expr::Name(value.to_string()), stmt.span), true);
                 },
                 Stmt::RawReferenceAbsolute(value) => {
-                    self.references.push(Reference {
-                        label: value.to_string(),
-                        index: self.index,
-                    });
-
-                    self.push_u32(0);
+                    self.push_value(Spanned::new(Expr::Name(value.to_string()), stmt.span), Size::Word);
+                },
+                Stmt::RawLocalReferenceAbsolute(value) => {
+                    self.push_value(Spanned::new(Expr::Local(value.to_string()), stmt.span), Size::Word);
                 },
                 Stmt::Operation(value) => {
                     self.push_u8(*value as _);
                 },
                 Stmt::String(value) => {
-                    for ch in value.bytes() {
-                        self.push_u8(ch);
+                    for byte in value {
+                        self.push_u8(*byte);
                     }
                 },
+                Stmt::Binary(label, data) => {
+                    self.current_label = label.to_string();
+                    self.define_label(label.to_string(), stmt.span);
+                    let size = Spanned::new(Expr::Number(data.len() as _), stmt.span);
+                    self.define_constant(format!("{}/size", label), size, stmt.span);
+
+                    for byte in data {
+                        self.push_u8(*byte);
+                    }
+                },
+                Stmt::Relax(relax) => {
+                    self.relax.insert(stmt.span.file, *relax);
+                },
+                Stmt::Export(name) => {
+                    self.exports.push((name.to_string(), stmt.span));
+                },
+                Stmt::Constant(name, value) => {
+                    self.define_constant(name.to_string(), value.clone(), stmt.span);
+                },
+                Stmt::Struct(name, fields) => {
+                    self.define_struct(name, fields, stmt.span);
+                },
+                Stmt::Record { kind, label, values } => {
+                    // Each field is its own line, records can span several
+                    self.push_record(kind, label, values, stmt.span);
+                    continue;
+                },
                 Stmt::RawByte(value) => {
-                    self.push_u8(*value);
+                    self.push_value(value.clone(), Size::Byte);
                 },
                 Stmt::RawWord(value) => {
-                    self.push_u32(*value);
+                    self.push_value(value.clone(), Size::Word);
                 },
                 Stmt::OriginRelative(value) => {
-                    self.index += *value as usize;
-                }
+                    match self.evaluate_origin(value) {
+                        Ok(value) => self.index += value as usize,
+                        Err(diagnostic) => self.diagnostics.push(diagnostic),
+                    }
+                    continue;
+                },
+                Stmt::StackEffect(effect) => {
+                    self.declarations.push(Declaration {
+                        label: self.last_label.clone(),
+                        addr: self.index as _,
+                        effect: *effect,
+                        span: stmt.span,
+                    });
+                },
+            }
+
+            if self.index > start {
+                self.lines.push((start, self.index, stmt.span));
+            }
+        }
+    }
+
+    /// Report `name` if it's already a label or constant.
+    fn is_duplicate(&mut self, name: &str, span: Span) -> bool {
+        let message = if self.defines.iter().any(|(define, _)| define == name) {
+            format!("`{}` is already defined before assembling", name)
+        } else if self.labels.contains_key(name) || self.constants.contains_key(name) {
+            format!("Duplicate label `{}`", name)
+        } else {
+            return false;
+        };
+
+        self.diagnostics.push(Diagnostic::new(message, span));
+        true
+    }
+
+    fn define_label(&mut self, label: String, span: Span) {
+        if self.is_duplicate(&label, span) {
+            return;
+        }
+
+        self.last_label = label.clone();
+        self.labels.insert(label, self.index as _);
+    }
+
+    fn define_constant(&mut self, name: String, expr: Spanned<Expr>, span: Span) {
+        if self.is_duplicate(&name, span) {
+            return;
+        }
+
+        self.constants.insert(name, Constant {
+            expr,
+            scope: self.current_label.clone(),
+        });
+    }
+
+    /// Define `name.field` as the offset of each field, `name.field/size` as its size, and `name/size` as the size of a record.
+    fn define_struct(&mut self, name: &str, fields: &[Field], span: Span) {
+        if self.structs.contains_key(name) {
+            self.diagnostics.push(Diagnostic::new(format!("Duplicate struct `{}`", name), span));
+            return;
+        }
+
+        let mut offset: u32 = 0;
+        let mut unique: Vec<Field> = Vec::new();
+        for field in fields {
+            if unique.iter().any(|other| other.name.value == field.name.value) {
+                self.diagnostics.push(Diagnostic::new(format!("Duplicate field `{}`", field.name.value), field.name.span));
+                continue;
             }
+            if field.size > MEM_SIZE - offset {
+                let message = format!("Field `{}` ends past the end of memory, structs can't be larger than 0x{:x} bytes", field.name.value, MEM_SIZE);
+                self.diagnostics.push(Diagnostic::new(message, field.name.span));
+                continue;
+            }
+            unique.push(field.clone());
+
+            let number = |value| Spanned::new(Expr::Number(value), field.name.span);
+            self.define_constant(format!("{}.{}", name, field.name.value), number(offset), field.name.span);
+            self.define_constant(format!("{}.{}/size", name, field.name.value), number(field.size), field.name.span);
+            offset += field.size;
+        }
+
+        self.define_constant(format!("{}/size", name), Spanned::new(Expr::Number(offset), span), span);
+        self.structs.insert(name.to_string(), unique);
+    }
+
+    /// Emit a record of struct `kind`, labeled `label` and `label.field` for each field.
+    /// Fields are filled in with their value, or zeros if they don't have one.
+    fn push_record(&mut self, kind: &Spanned<String>, label: &str, values: &[(Spanned<String>, Spanned<Expr>)], span: Span) {
+        let Some(fields) = self.structs.get(&kind.value).cloned() else {
+            let message = format!("Unknown struct `{}`, structs are declared with `?struct` before their records", kind.value);
+            self.diagnostics.push(Diagnostic::new(message, kind.span));
+            return;
+        };
+
+        for (index, (name, _)) in values.iter().enumerate() {
+            let message = if !fields.iter().any(|field| field.name.value == name.value) {
+                format!("`{}` has no field `{}`", kind.value, name.value)
+            } else if values[..index].iter().any(|(other, _)| other.value == name.value) {
+                format!("Field `{}` is given more than once", name.value)
+            } else {
+                continue;
+            };
+            self.diagnostics.push(Diagnostic::new(message, name.span));
+        }
+
+        self.current_label = label.to_string();
+        self.define_label(label.to_string(), span);
+
+        for field in &fields {
+            let start = self.index;
+            self.define_label(format!("{}.{}", label, field.name.value), span);
+
+            let value = values.iter().find(|(name, _)| name.value == field.name.value);
+            match (value, field.size) {
+                (Some((_, value)), 1) => self.push_value(value.clone(), Size::Byte),
+                (Some((_, value)), 4) => self.push_value(value.clone(), Size::Word),
+                (value, size) => {
+                    if let Some((name, _)) = value {
+                        let message = format!("`{}` is {} bytes, only fields of 1 or 4 bytes can have a value", name.value, size);
+                        self.diagnostics.push(Diagnostic::new(message, name.span));
+                    }
+                    self.push_zeros(size);
+                },
+            }
+
+            let span = value.map_or(span, |(name, value)| name.span.to(value.span));
+            if self.index > start {
+                self.lines.push((start, self.index, span));
+            }
+        }
+    }
+
+    /// Whether `name` is a label, constant or number, used to hint at subtractions without spaces.
+    fn is_defined(&self, name: &str) -> bool {
+        self.labels.contains_key(name) || self.constants.contains_key(name) || expr::parse_number(name).is_some()
+    }
+
+    /// Origins are needed right away, and can't move when linking.
+    fn evaluate_origin(&self, expr: &Spanned<Expr>) -> Result<u32, Diagnostic> {
+        let value = self.evaluate(expr, &self.current_label, true)?;
+
+        if self.relocatable && self.relocation_count(expr, &self.current_label) != Some(0) {
+            return Err(Diagnostic::new("Origins in an object can't depend on where it's linked", expr.span));
         }
+
+        Ok(value)
+    }
+
+    /// Whether `addr` is in the emitted bytes, or right after them.
+    /// Labels there move with the section when linking.
+    fn in_section(&self, addr: u32) -> bool {
+        let start = RESET_VECTOR as usize;
+        (start..=start + self.length).contains(&(addr as usize))
+    }
+
+    /// How many times the start of the section is added to `expr`, `Some(0)` if it doesn't move when linking.
+    /// `None` if that can't be worked out, like `[ label * 2 ]` or labels from other objects.
+    fn relocation_count(&self, expr: &Spanned<Expr>, scope: &str) -> Option<i32> {
+        self.relocation_count_in(expr, scope, &mut Vec::new())
+    }
+
+    fn relocation_count_in(&self, expr: &Spanned<Expr>, scope: &str, constants: &mut Vec<String>) -> Option<i32> {
+        match &expr.value {
+            Expr::Number(_) => Some(0),
+            Expr::Name(name) | Expr::Local(name) => {
+                let local = matches!(expr.value, Expr::Local(_));
+                let name = if local { format!("{}/{}", scope, name) } else { name.to_string() };
+
+                if let Some(addr) = self.labels.get(&name) {
+                    return Some(if self.in_section(*addr) { 1 } else { 0 });
+                }
+
+                let constant = self.constants.get(&name).filter(|_| !constants.contains(&name))?;
+                constants.push(name);
+                let count = self.relocation_count_in(&constant.expr, &constant.scope, constants);
+                constants.pop();
+                count
+            },
+            Expr::Negate(value) => self.relocation_count_in(value, scope, constants).map(|count| -count),
+            Expr::Binary(op, a, b) => {
+                let a = self.relocation_count_in(a, scope, constants)?;
+                let b = self.relocation_count_in(b, scope, constants)?;
+                match op {
+                    Op::Add => Some(a + b),
+                    Op::Sub => Some(a - b),
+                    _ if a == 0 && b == 0 => Some(0),
+                    _ => None,
+                }
+            },
+        }
+    }
+
+    /// Whether `expr` depends on labels, directly or through constants.
+    /// Unknown names are assumed to be labels.
+    fn uses_labels(&self, expr: &Spanned<Expr>, scope: &str) -> bool {
+        self.uses_labels_in(expr, scope, &mut Vec::new())
+    }
+
+    fn uses_labels_in(&self, expr: &Spanned<Expr>, scope: &str, constants: &mut Vec<String>) -> bool {
+        let mut uses = false;
+        Expr::visit_names(expr, &mut |name, local| {
+            let name = if local { format!("{}/{}", scope, name) } else { name.to_string() };
+
+            uses |= match self.constants.get(&name) {
+                // Defined in terms of itself, which is an error later on
+                Some(_) if constants.contains(&name) => false,
+                Some(constant) => {
+                    constants.push(name);
+                    let uses = self.uses_labels_in(&constant.expr, &constant.scope, constants);
+                    constants.pop();
+                    uses
+                },
+                None => true,
+            };
+        });
+        uses
+    }
+
+    /// Value of `expr`, with local names relative to `scope`.
+    /// If `early` the value is needed right away, so labels defined later can't be used.
+    fn evaluate(&self, expr: &Spanned<Expr>, scope: &str, early: bool) -> Result<u32, Diagnostic> {
+        self.evaluate_in(expr, scope, early, &mut Vec::new())
+    }
+
+    /// `constants` are the constants being evaluated, to catch constants defined in terms of themselves.
+    fn evaluate_in(&self, expr: &Spanned<Expr>, scope: &str, early: bool, constants: &mut Vec<String>) -> Result<u32, Diagnostic> {
+        Expr::eval(expr, &mut |name, local, span| {
+            let name = if local { format!("{}/{}", scope, name) } else { name.to_string() };
+
+            if let Some(value) = self.labels.get(&name) {
+                return Ok(*value);
+            }
+
+            if let Some(constant) = self.constants.get(&name) {
+                if constants.contains(&name) {
+                    return Err(Diagnostic::new(format!("Constant `{}` is defined in terms of itself", name), span));
+                }
+
+                constants.push(name);
+                let value = self.evaluate_in(&constant.expr, &constant.scope, early, constants);
+                constants.pop();
+                return value;
+            }
+
+            let message = if early {
+                format!("Unknown label `{}`, origins can only use labels defined before them", name)
+            } else if name.contains('-') && name.split('-').all(|part| self.is_defined(part)) {
+                format!("Unknown label `{}`, use spaces around `-` to subtract", name)
+            } else {
+                format!("Unknown label `{}`", name)
+            };
+            Err(Diagnostic::new(message, span))
+        })
     }
 
     pub fn data(&self) -> &[u8] {
         let start = RESET_VECTOR as usize;
         let end = start + self.length;
-        &self.data[start..end]
+        // Nothing was emitted, like for a file of only constants
+        self.data.get(start..end).unwrap_or(&[])
+    }
+
+    /// Source lines of the emitted bytes, `sources` are the files the spans refer to.
+    pub fn lines(&self, sources: &[Source]) -> LineTable {
+        let entries = self.lines.iter().map(|(start, end, span)| {
+            LineEntry {
+                start: *start as _,
+                end: *end as _,
+                file: sources[span.file as usize].name.clone(),
+                line: span.line,
+                column: span.column,
+            }
+        });
+
+        LineTable::new(entries.collect())
+    }
+
+    /// The assembled relocatable object, `sources` are the files the spans refer to.
+    pub fn object(&self, sources: &[Source]) -> Object {
+        let start = RESET_VECTOR;
+        let location = |span: Span| Location {
+            file: sources[span.file as usize].name.clone(),
+            line: span.line,
+            column: span.column,
+            length: span.length,
+        };
+
+        let labels = self.labels.iter().map(|(label, addr)| {
+            let addr = if self.in_section(*addr) { Address::Relative(addr - start) } else { Address::Absolute(*addr) };
+            (label.clone(), addr)
+        });
+
+        let constants = self.constants.iter().map(|(name, constant)| object::Constant {
+            name: name.clone(),
+            scope: constant.scope.clone(),
+            expr: constant.expr.clone(),
+        });
+
+        let relocations = self.relocations.iter().map(|reference| object::Relocation {
+            offset: reference.index as u32 - start,
+            size: reference.size,
+            scope: reference.scope.clone(),
+            expr: reference.expr.clone(),
+            location: location(reference.expr.span),
+        });
+
+        let lines = self.lines.iter().map(|(line_start, end, span)| object::Line {
+            start: *line_start as u32 - start,
+            end: *end as u32 - start,
+            location: location(*span),
+        });
+
+        let mut object = Object {
+            data: self.data().to_vec(),
+            labels: labels.collect(),
+            constants: constants.collect(),
+            exports: self.exports.iter().map(|(name, span)| (name.clone(), location(*span))).collect(),
+            relocations: relocations.collect(),
+            lines: lines.collect(),
+        };
+
+        // Keep objects the same between runs
+        object.labels.sort_by(|a, b| a.0.cmp(&b.0));
+        object.constants.sort_by(|a, b| a.name.cmp(&b.name));
+        object
+    }
+
+    /// Number of literals assembled as `LITB`.
+    pub fn short_literals(&self) -> usize {
+        self.short
+    }
+
+    /// Likely mistakes found while assembling, stack checks are done by `verify`.
+    pub fn warnings(&self) -> &[Diagnostic] {
+        &self.warnings
+    }
+
+    /// All of memory as assembled, including bytes before the reset vector.
+    pub fn memory(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Emitted byte ranges in the order they were emitted, and the statement they came from.
+    pub fn statements(&self) -> &[(usize, usize, Span)] {
+        &self.lines
+    }
+
+    /// Address, span and value of every reference to a label or constant.
+    pub fn resolved(&self) -> &[(usize, Span, u32)] {
+        &self.resolved
+    }
+
+    pub fn labels(&self) -> &HashMap<String, u32> {
+        &self.labels
+    }
+
+    pub fn declarations(&self) -> &[Declaration] {
+        &self.declarations
+    }
+
+    /// Span of the statement that emitted the byte at `addr`.
+    pub fn span_at(&self, addr: u32) -> Option<Span> {
+        let addr = addr as usize;
+        self.lines.iter().rev().find(|(start, end, _)| *start <= addr && addr < *end).map(|(_, _, span)| *span)
+    }
+
+    pub fn symbols(&self) -> Symbols {
+        Symbols::new(self.labels.clone())
+    }
+
+    /// A `LITB` or `LITW` of `expr`, the literal of statement `stmt`.
+    /// References to labels are only `LITB` with `?relax labels`.
+    fn push_literal(&mut self, stmt: usize, expr: Spanned<Expr>, reference: bool) {
+        let relax = self.relax.get(&expr.span.file).copied().unwrap_or_default();
+        let short = match relax {
+            Relax::Off => false,
+            Relax::Numbers => !reference,
+            Relax::Labels => true,
+        };
+
+        if short && !self.long.contains(&stmt) {
+            self.push_u8(OP_LITB);
+            self.push_reference(expr, Size::Byte, Some((stmt, relax)));
+        } else {
+            self.push_u8(OP_LITW);
+            self.push_value(expr, Size::Word);
+        }
+    }
+
+    /// Reserve space for `expr`, which is filled in after assembling.
+    fn push_value(&mut self, expr: Spanned<Expr>, size: Size) {
+        self.push_reference(expr, size, None);
+    }
+
+    fn push_reference(&mut self, expr: Spanned<Expr>, size: Size, literal: Option<(usize, Relax)>) {
+        self.references.push(Reference {
+            expr,
+            scope: self.current_label.clone(),
+            index: self.index,
+            size,
+            literal,
+        });
+
+        match size {
+            Size::Byte => self.push_u8(0),
+            Size::Word => self.push_u32(0),
+        }
     }
 
     fn push_u8(&mut self, value: u8) {
-        if self.data.len() < self.index + 1 {
-            self.data.resize(self.index + 1, 0);
+        // Bytes outside of memory aren't kept, `check_layout` reports them
+        if self.index < MEM_SIZE as usize {
+            if self.data.len() < self.index + 1 {
+                self.data.resize(self.index + 1, 0);
+            }
+            self.data[self.index] = value;
         }
-        self.data[self.index] = value;
         self.index += 1;
-        self.length = self.index - RESET_VECTOR as usize;
+
+        // Origins can go back over emitted bytes, or before the reset vector
+        let end = self.index.min(MEM_SIZE as usize);
+        self.length = self.length.max(end.saturating_sub(RESET_VECTOR as usize));
+    }
+
+    /// Same as `push_u8(0)` `count` times.
+    fn push_zeros(&mut self, count: u32) {
+        let start = self.index;
+        self.index += count as usize;
+
+        let end = self.index.min(MEM_SIZE as usize);
+        if self.data.len() < end {
+            self.data.resize(end, 0);
+        }
+        self.data[start.min(end)..end].fill(0);
+        self.length = self.length.max(end.saturating_sub(RESET_VECTOR as usize));
+    }
+
+    /// Warn about bytes emitted more than once, and report bytes where the program can't be loaded or is overwritten while running.
+    fn check_layout(&mut self) {
+        // Bytes emitted after each other, until an origin moves away, in the order they were emitted
+        let mut runs: Vec<(usize, usize, usize)> = Vec::new();
+        for (index, (start, end, _)) in self.lines.iter().enumerate() {
+            match runs.last_mut() {
+                Some(run) if run.1 == *start => run.1 = *end,
+                _ => runs.push((*start, *end, index)),
+            }
+        }
+
+        // First statement of `run` that emitted bytes in `start..end`
+        let first_in = |run: usize, start: usize, end: usize| {
+            let to = runs.get(run + 1).map_or(self.lines.len(), |next| next.2);
+            self.lines[runs[run].2..to]
+                .iter()
+                .find(|(line_start, line_end, _)| *line_start < end && *line_end > start)
+                .map(|(_, _, span)| *span)
+                .unwrap_or_default()
+        };
+
+        let mut sorted: Vec<usize> = (0..runs.len()).collect();
+        sorted.sort_by_key(|run| runs[*run].0);
+
+        // Run reaching the furthest so far
+        let mut last: Option<usize> = None;
+        let mut overlaps = Vec::new();
+        for run in sorted {
+            let (start, end, _) = runs[run];
+            if let Some(other) = last.filter(|other| start < runs[*other].1) {
+                let (earlier, later) = (run.min(other), run.max(other));
+                let end = end.min(runs[other].1);
+                overlaps.push((later, first_in(earlier, start, end), first_in(later, start, end), start, end));
+            }
+
+            if last.is_none_or(|other| end > runs[other].1) {
+                last = Some(run);
+            }
+        }
+
+        overlaps.sort_by_key(|overlap| overlap.0);
+        for (_, earlier, later, start, end) in overlaps {
+            let file = if earlier.file == later.file { "" } else { " of another file" };
+            let message = format!("Overwrites {:08x}..{:08x}, already emitted on line {}{}", start, end, earlier.line, file);
+            self.warnings.push(Diagnostic::warning(message, later));
+        }
+
+        // Only the first statement in each region, a misplaced origin would report every statement after it
+        let mut reported = HashSet::new();
+        for (start, end, span) in &self.lines {
+            for (region_start, region_end, description) in REGIONS {
+                if *start < *region_end && *end > *region_start && reported.insert(description) {
+                    let message = format!("Bytes at {:08x} are {}", start.max(region_start), description);
+                    self.diagnostics.push(Diagnostic::new(message, *span));
+                }
+            }
+        }
     }
 
     fn push_u32(&mut self, value: u32) {
@@ -0,0 +1,97 @@
+use crate::span::{Span, Expansion, Source};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Severity {
+    /// Nothing is written when there are errors.
+    Error,
+    /// Likely mistakes that still assemble.
+    Warning,
+}
+
+/// An error or warning in the assembled source.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+    pub span: Span,
+}
+
+pub type Diagnostics = Vec<Diagnostic>;
+
+impl Diagnostic {
+    pub fn new(message: impl Into<String>, span: Span) -> Self {
+        Self {
+            severity: Severity::Error,
+            message: message.into(),
+            span,
+        }
+    }
+
+    pub fn warning(message: impl Into<String>, span: Span) -> Self {
+        Self {
+            severity: Severity::Warning,
+            ..Self::new(message, span)
+        }
+    }
+
+    /// Render with the offending source line underlined, e.g.
+    ///
+    /// ```text
+    /// error: Unknown instruction `FOO`
+    ///  --> test.fox:3:5
+    ///   |
+    /// 3 |     FOO
+    ///   |     ^^^
+    /// ```
+    ///
+    /// Errors in a macro body are followed by the macro call they were expanded from.
+    pub fn render(&self, sources: &[Source], expansions: &[Expansion]) -> String {
+        let severity = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+
+        let mut out = format!("{}: {}\n{}", severity, self.message, snippet(sources, self.span));
+
+        let mut span = self.span;
+        let mut last = None;
+        while let Some(expansion) = span.expansion.and_then(|index| expansions.get(index as usize)) {
+            span = expansion.call;
+
+            // Recursive macros would repeat the same call many times
+            let call = (span.line, span.column);
+            if last != Some(call) {
+                out.push_str(&format!("\nnote: in expansion of macro `{}`\n{}", expansion.name, snippet(sources, span)));
+            }
+            last = Some(call);
+        }
+
+        out
+    }
+}
+
+fn snippet(sources: &[Source], span: Span) -> String {
+    let (file, text) = match sources.get(span.file as usize) {
+        Some(source) => (source.name.as_str(), source.text.as_str()),
+        None => ("?", ""),
+    };
+    let line = text.lines().nth(span.line as usize - 1).unwrap_or("");
+    let number = span.line.to_string();
+    let gutter = " ".repeat(number.len());
+
+    // Keep tabs so the carets line up with the source
+    let indent: String = line
+        .chars()
+        .take(span.column as usize - 1)
+        .map(|ch| if ch == '\t' { '\t' } else { ' ' })
+        .collect();
+    let carets = "^".repeat(span.length.max(1) as _);
+
+    format!(
+        "{}--> {}:{}:{}\n{} |\n{} | {}\n{} | {}{}",
+        gutter, file, span.line, span.column,
+        gutter,
+        number, line,
+        gutter, indent, carets,
+    )
+}
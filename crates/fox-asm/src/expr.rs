@@ -0,0 +1,237 @@
+use std::iter::Peekable;
+use crate::tokenizer::Token;
+use crate::span::{Span, Spanned};
+use crate::diagnostic::Diagnostic;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Op {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    And,
+    Or,
+    ShiftLeft,
+    ShiftRight,
+}
+
+impl Op {
+    fn from_token(token: &Token) -> Option<Self> {
+        match token {
+            Token::Plus => Some(Op::Add),
+            Token::Minus => Some(Op::Sub),
+            Token::Star => Some(Op::Mul),
+            Token::Slash => Some(Op::Div),
+            Token::Ampersand => Some(Op::And),
+            Token::Pipe => Some(Op::Or),
+            Token::ShiftLeft => Some(Op::ShiftLeft),
+            Token::ShiftRight => Some(Op::ShiftRight),
+            _ => None,
+        }
+    }
+
+    pub fn symbol(self) -> &'static str {
+        match self {
+            Op::Add => "+",
+            Op::Sub => "-",
+            Op::Mul => "*",
+            Op::Div => "/",
+            Op::And => "&",
+            Op::Or => "|",
+            Op::ShiftLeft => "<<",
+            Op::ShiftRight => ">>",
+        }
+    }
+
+    pub fn from_symbol(symbol: &str) -> Option<Self> {
+        [Op::Add, Op::Sub, Op::Mul, Op::Div, Op::And, Op::Or, Op::ShiftLeft, Op::ShiftRight]
+            .into_iter()
+            .find(|op| op.symbol() == symbol)
+    }
+
+    /// Higher binds tighter, like in C.
+    fn precedence(self) -> u8 {
+        match self {
+            Op::Or => 1,
+            Op::And => 2,
+            Op::ShiftLeft | Op::ShiftRight => 3,
+            Op::Add | Op::Sub => 4,
+            Op::Mul | Op::Div => 5,
+        }
+    }
+
+    fn apply(self, a: u32, b: u32) -> Option<u32> {
+        match self {
+            Op::Add => Some(a.wrapping_add(b)),
+            Op::Sub => Some(a.wrapping_sub(b)),
+            Op::Mul => Some(a.wrapping_mul(b)),
+            Op::Div => a.checked_div(b),
+            Op::And => Some(a & b),
+            Op::Or => Some(a | b),
+            Op::ShiftLeft => a.checked_shl(b),
+            Op::ShiftRight => a.checked_shr(b),
+        }
+    }
+}
+
+/// A number computed while assembling, like `[ end - start ]`.
+#[derive(Debug, Clone)]
+pub enum Expr {
+    Number(u32),
+    /// A label or constant.
+    Name(String),
+    /// A local label, `&name`.
+    Local(String),
+    Negate(Box<Spanned<Expr>>),
+    Binary(Op, Box<Spanned<Expr>>, Box<Spanned<Expr>>),
+}
+
+impl Expr {
+    /// Names are looked up with `lookup`, which gets the name and whether it's local.
+    pub fn eval<F>(expr: &Spanned<Expr>, lookup: &mut F) -> Result<u32, Diagnostic>
+    where
+        F: FnMut(&str, bool, Span) -> Result<u32, Diagnostic>,
+    {
+        match &expr.value {
+            Expr::Number(value) => Ok(*value),
+            Expr::Name(name) => lookup(name, false, expr.span),
+            Expr::Local(name) => lookup(name, true, expr.span),
+            Expr::Negate(value) => Ok(Self::eval(value, lookup)?.wrapping_neg()),
+            Expr::Binary(op, a, b) => {
+                let a = Self::eval(a, lookup)?;
+                let b = Self::eval(b, lookup)?;
+                op.apply(a, b).ok_or_else(|| {
+                    let message = match op {
+                        Op::Div => "Division by zero".to_string(),
+                        _ => format!("Shifting by 0x{:x} overflows, shifts have to be less than 0x20", b),
+                    };
+                    Diagnostic::new(message, expr.span)
+                })
+            },
+        }
+    }
+
+    /// Call `f` with every name in `expr` and whether it's local.
+    pub fn visit_names<F: FnMut(&str, bool)>(expr: &Spanned<Expr>, f: &mut F) {
+        match &expr.value {
+            Expr::Number(_) => (),
+            Expr::Name(name) => f(name, false),
+            Expr::Local(name) => f(name, true),
+            Expr::Negate(value) => Self::visit_names(value, f),
+            Expr::Binary(_, a, b) => {
+                Self::visit_names(a, f);
+                Self::visit_names(b, f);
+            },
+        }
+    }
+}
+
+type Tokens<'a> = Peekable<std::slice::Iter<'a, Spanned<Token>>>;
+
+/// Value following the prefix at `prefix`, a number, a character, a name or an expression between brackets.
+/// Names that are valid hexadecimal numbers are numbers.
+pub fn parse_value(it: &mut Tokens, prefix: Span) -> Result<Spanned<Expr>, Diagnostic> {
+    match it.peek() {
+        Some(Spanned { value: Token::IdentifierOrNumber(str), span }) => {
+            let span = *span;
+            it.next();
+            atom(str, span)
+        },
+        Some(Spanned { value: Token::Character(value), span }) => {
+            let span = *span;
+            it.next();
+            Ok(Spanned::new(Expr::Number(*value), span))
+        },
+        Some(Spanned { value: Token::OpenBracket, span }) => {
+            let start = *span;
+            it.next();
+
+            let expr = parse_binary(it, start, 0)?;
+            match it.next() {
+                Some(Spanned { value: Token::CloseBracket, .. }) => Ok(expr),
+                Some(token) => Err(Diagnostic::new("Expected an operator or `]`", token.span)),
+                None => Err(Diagnostic::new("Expected `]` to end the expression", start)),
+            }
+        },
+        Some(Spanned { value: Token::Invalid(message), span }) => {
+            let span = *span;
+            let message = message.clone();
+            it.next();
+            Err(Diagnostic::new(message, span))
+        },
+        Some(token) => Err(Diagnostic::new("Expected a number, name or expression", token.span)),
+        None => Err(Diagnostic::new("Expected a number, name or expression", prefix)),
+    }
+}
+
+/// Value of a number like `ff`, `0xff`, `0n255` or `0y11111111`.
+/// `None` if `str` is a name instead, an error if it looks like a number but isn't valid.
+pub fn parse_number(str: &str) -> Option<Result<u32, String>> {
+    let (radix, digits, kind) = match str.get(..2) {
+        Some("0x") => (16, &str[2..], "hexadecimal"),
+        Some("0n") => (10, &str[2..], "decimal"),
+        Some("0y") => (2, &str[2..], "binary"),
+        _ if str.chars().all(|ch| ch.is_ascii_hexdigit()) => (16, str, "hexadecimal"),
+        _ => return None,
+    };
+
+    Some(u32::from_str_radix(digits, radix).map_err(|_| format!("Invalid {} number `{}`", kind, str)))
+}
+
+fn atom(str: &str, span: Span) -> Result<Spanned<Expr>, Diagnostic> {
+    match parse_number(str) {
+        Some(Ok(number)) => Ok(Spanned::new(Expr::Number(number), span)),
+        Some(Err(message)) => Err(Diagnostic::new(message, span)),
+        None => Ok(Spanned::new(Expr::Name(str.to_string()), span)),
+    }
+}
+
+/// Operators binding tighter than `precedence`, by precedence climbing.
+fn parse_binary(it: &mut Tokens, start: Span, precedence: u8) -> Result<Spanned<Expr>, Diagnostic> {
+    let mut lhs = parse_unary(it, start)?;
+
+    while let Some(op) = it.peek().and_then(|token| Op::from_token(&token.value)) {
+        if op.precedence() <= precedence {
+            break;
+        }
+
+        let span = it.next().unwrap().span;
+        let rhs = parse_binary(it, span, op.precedence())?;
+        let span = lhs.span.to(rhs.span);
+        lhs = Spanned::new(Expr::Binary(op, Box::new(lhs), Box::new(rhs)), span);
+    }
+
+    Ok(lhs)
+}
+
+fn parse_unary(it: &mut Tokens, start: Span) -> Result<Spanned<Expr>, Diagnostic> {
+    let token = match it.next() {
+        Some(token) => token,
+        None => return Err(Diagnostic::new("Expected `]` to end the expression", start)),
+    };
+
+    match &token.value {
+        Token::IdentifierOrNumber(str) => atom(str, token.span),
+        Token::Character(value) => Ok(Spanned::new(Expr::Number(*value), token.span)),
+        Token::Ampersand => match it.next() {
+            Some(Spanned { value: Token::IdentifierOrNumber(name), span }) => {
+                Ok(Spanned::new(Expr::Local(name.to_string()), token.span.to(*span)))
+            },
+            _ => Err(Diagnostic::new("Expected a local label after `&`", token.span)),
+        },
+        Token::Minus => {
+            let value = parse_unary(it, token.span)?;
+            let span = token.span.to(value.span);
+            Ok(Spanned::new(Expr::Negate(Box::new(value)), span))
+        },
+        Token::OpenParen => {
+            let expr = parse_binary(it, token.span, 0)?;
+            match it.next() {
+                Some(Spanned { value: Token::CloseParen, span }) => Ok(Spanned::new(expr.value, token.span.to(*span))),
+                Some(other) => Err(Diagnostic::new("Expected an operator or `)`", other.span)),
+                None => Err(Diagnostic::new("Expected `)`", token.span)),
+            }
+        },
+        _ => Err(Diagnostic::new("Expected a number or name", token.span)),
+    }
+}
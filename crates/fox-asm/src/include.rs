@@ -0,0 +1,190 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use fox_bytecode::headers;
+use crate::tokenizer::{self, Token};
+use crate::span::{Span, Spanned, Source};
+use crate::diagnostic::{Diagnostic, Diagnostics};
+
+/// Reads the assembled file and every file it includes with `~path`.
+/// Includes are looked up next to the including file, then in the include paths,
+/// and finally in the device headers from `fox_bytecode::headers`.
+/// Each file is only included once, including a file that is still being included is an error.
+/// Binary files included with `!path` are read the same way, but can be included any number of times.
+pub struct Loader {
+    include_paths: Vec<PathBuf>,
+    sources: Vec<Source>,
+    included: HashSet<String>,
+    /// Key and name of the files being included.
+    stack: Vec<(String, String)>,
+    diagnostics: Diagnostics,
+}
+
+enum Resolved {
+    File(PathBuf),
+    Header(String),
+}
+
+impl Loader {
+    pub fn new(include_paths: Vec<PathBuf>) -> Self {
+        Self {
+            include_paths,
+            sources: Vec::new(),
+            included: HashSet::new(),
+            stack: Vec::new(),
+            diagnostics: Vec::new(),
+        }
+    }
+
+    /// Every loaded file, spans refer to these by index.
+    pub fn sources(&self) -> &[Source] {
+        &self.sources
+    }
+
+    /// Tokenize `text` read from `path`, replacing includes with the tokens of the included files.
+    pub fn load(&mut self, path: &Path, text: String) -> Result<Vec<Spanned<Token>>, Diagnostics> {
+        let name = path.display().to_string();
+        let tokens = self.tokenize(key(path), name, path.parent().map(Path::to_path_buf), text);
+
+        if self.diagnostics.is_empty() {
+            Ok(tokens)
+        } else {
+            Err(std::mem::take(&mut self.diagnostics))
+        }
+    }
+
+    fn tokenize(&mut self, key: String, name: String, dir: Option<PathBuf>, text: String) -> Vec<Spanned<Token>> {
+        let file = self.sources.len() as u32;
+        let tokens = tokenizer::tokenize(&text, file);
+
+        self.sources.push(Source {
+            name: name.clone(),
+            text,
+        });
+        self.included.insert(key.clone());
+        self.stack.push((key, name));
+
+        let mut out = Vec::new();
+        for token in tokens {
+            match &token.value {
+                Token::Include(path) => {
+                    if let Err(diagnostic) = self.include(path, token.span, dir.as_deref(), &mut out) {
+                        self.diagnostics.push(diagnostic);
+                    }
+                },
+                Token::IncludeBinary(path) => {
+                    match self.include_binary(path, token.span, dir.as_deref()) {
+                        Ok(data) => out.push(Spanned::new(Token::Binary(data), token.span)),
+                        Err(diagnostic) => self.diagnostics.push(diagnostic),
+                    }
+                },
+                _ => out.push(token),
+            }
+        }
+
+        self.stack.pop();
+        out
+    }
+
+    fn include(&mut self, path: &str, span: Span, dir: Option<&Path>, out: &mut Vec<Spanned<Token>>) -> Result<(), Diagnostic> {
+        if path.is_empty() {
+            return Err(Diagnostic::new("Expected a file to include after `~`", span));
+        }
+
+        let resolved = self.resolve(path, dir)
+            .ok_or_else(|| Diagnostic::new(format!("Could not find `{}` to include", path), span))?;
+
+        let (key, name) = match &resolved {
+            Resolved::File(path) => (key(path), path.display().to_string()),
+            Resolved::Header(_) => (format!("<{}>", path), format!("<{}>", path)),
+        };
+
+        if let Some(start) = self.stack.iter().position(|(other, _)| *other == key) {
+            let mut cycle: Vec<_> = self.stack[start..].iter().map(|(_, name)| name.as_str()).collect();
+            cycle.push(&name);
+            return Err(Diagnostic::new(format!("Include cycle {}", cycle.join(" -> ")), span));
+        }
+
+        if self.included.contains(&key) {
+            return Ok(());
+        }
+
+        let tokens = match resolved {
+            Resolved::File(path) => {
+                let text = std::fs::read_to_string(&path)
+                    .map_err(|err| Diagnostic::new(format!("Could not read `{}`: {}", path.display(), err), span))?;
+                self.tokenize(key, name, path.parent().map(Path::to_path_buf), text)
+            },
+            Resolved::Header(text) => self.tokenize(key, name, None, text),
+        };
+
+        out.extend(tokens);
+        Ok(())
+    }
+
+    /// Bytes of `path`, which can end in `:offset` or `:offset:length` to only include part of the file.
+    fn include_binary(&self, path: &str, span: Span, dir: Option<&Path>) -> Result<Vec<u8>, Diagnostic> {
+        if path.is_empty() {
+            return Err(Diagnostic::new("Expected a file to include after `!`", span));
+        }
+
+        let (path, offset, length) = split_range(path);
+
+        let file = self.resolve_file(path, dir)
+            .ok_or_else(|| Diagnostic::new(format!("Could not find `{}` to include", path), span))?;
+        let data = std::fs::read(&file)
+            .map_err(|err| Diagnostic::new(format!("Could not read `{}`: {}", file.display(), err), span))?;
+
+        let offset = offset.unwrap_or(0) as usize;
+        let end = match length {
+            Some(length) => offset + length as usize,
+            None => data.len().max(offset),
+        };
+
+        match data.get(offset..end) {
+            Some(data) => Ok(data.to_vec()),
+            None => {
+                let message = format!("`{}` is {:x} bytes, can't include {:x}..{:x}", file.display(), data.len(), offset, end);
+                Err(Diagnostic::new(message, span))
+            },
+        }
+    }
+
+    fn resolve_file(&self, path: &str, dir: Option<&Path>) -> Option<PathBuf> {
+        dir
+            .into_iter()
+            .chain(self.include_paths.iter().map(PathBuf::as_path))
+            .map(|dir| dir.join(path))
+            .find(|path| path.is_file())
+    }
+
+    fn resolve(&self, path: &str, dir: Option<&Path>) -> Option<Resolved> {
+        match self.resolve_file(path, dir) {
+            Some(file) => Some(Resolved::File(file)),
+            None => headers::header(path).map(Resolved::Header),
+        }
+    }
+}
+
+/// Identifies a file, even when it is included through different paths.
+fn key(path: &Path) -> String {
+    let path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+    path.display().to_string()
+}
+
+/// Split `path:offset:length` into its parts, offset and length are hexadecimal.
+fn split_range(path: &str) -> (&str, Option<u32>, Option<u32>) {
+    // Strip trailing `:number` parts, at most two
+    let mut path = path;
+    let mut numbers = Vec::new();
+    while numbers.len() < 2 {
+        match path.rsplit_once(':').and_then(|(rest, number)| Some((rest, u32::from_str_radix(number, 16).ok()?))) {
+            Some((rest, number)) => {
+                path = rest;
+                numbers.insert(0, number);
+            },
+            None => break,
+        }
+    }
+
+    (path, numbers.first().copied(), numbers.get(1).copied())
+}
@@ -0,0 +1,180 @@
+mod span;
+mod diagnostic;
+mod tokenizer;
+mod include;
+mod macros;
+mod expr;
+mod parser;
+mod asm;
+mod verify;
+mod listing;
+mod link;
+pub mod object;
+
+use std::fmt;
+use std::path::PathBuf;
+use fox_bytecode::symbols::Symbols;
+use fox_bytecode::lines::LineTable;
+
+pub use diagnostic::{Diagnostic, Severity};
+pub use span::{Span, Source, Expansion};
+pub use object::Object;
+pub use expr::parse_number;
+
+/// How to assemble a source.
+#[derive(Debug, Clone)]
+pub struct Options {
+    /// Path of the source, shown in diagnostics and the line table.
+    /// Includes are looked up next to it, the source itself isn't read from it.
+    pub path: PathBuf,
+    /// Directories includes are looked up in, after the directory of the source.
+    pub include_paths: Vec<PathBuf>,
+    /// Check the stacks on every path from `on-reset` and the vectors, see `doc/assembler.md`.
+    pub verify: bool,
+    /// Make a listing, see `Output::listing`.
+    pub listing: bool,
+    /// Constants defined before the source, which can't define them again.
+    pub defines: Vec<(String, u32)>,
+}
+
+impl Options {
+    pub fn new() -> Self {
+        Self {
+            path: PathBuf::from("main.fox"),
+            include_paths: Vec::new(),
+            verify: true,
+            listing: false,
+            defines: Vec::new(),
+        }
+    }
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// An assembled or linked program.
+#[derive(Debug)]
+pub struct Output {
+    /// Bytes to load at the reset vector.
+    pub data: Vec<u8>,
+    pub symbols: Symbols,
+    pub lines: LineTable,
+    /// Address, bytes and source of every statement, if `Options::listing` was set.
+    pub listing: Option<String>,
+    /// Literals assembled as `LITB`.
+    pub short_literals: usize,
+    pub warnings: Diagnostics,
+}
+
+/// Errors or warnings, with the sources they point into so they can be shown.
+#[derive(Debug, Clone, Default)]
+pub struct Diagnostics {
+    pub diagnostics: Vec<Diagnostic>,
+    pub sources: Vec<Source>,
+    pub expansions: Vec<Expansion>,
+}
+
+impl Diagnostics {
+    pub fn is_empty(&self) -> bool {
+        self.diagnostics.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.diagnostics.len()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Diagnostic> {
+        self.diagnostics.iter()
+    }
+}
+
+/// Every diagnostic with its source, followed by an empty line.
+impl fmt::Display for Diagnostics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for diagnostic in &self.diagnostics {
+            writeln!(f, "{}\n", diagnostic.render(&self.sources, &self.expansions))?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for Diagnostics {}
+
+/// Assemble `source` into a program.
+pub fn assemble(source: &str, options: &Options) -> Result<Output, Diagnostics> {
+    let (asm, mut warnings) = run(source, options, asm::Assembler::new())?;
+
+    if options.verify {
+        warnings.diagnostics.extend(verify::verify(&asm));
+    }
+
+    Ok(Output {
+        data: asm.data().to_vec(),
+        symbols: asm.symbols(),
+        lines: asm.lines(&warnings.sources),
+        listing: options.listing.then(|| listing::listing(&asm, &warnings.sources)),
+        short_literals: asm.short_literals(),
+        warnings,
+    })
+}
+
+/// Assemble `source` into a relocatable object to `link` later, and its warnings.
+pub fn compile(source: &str, options: &Options) -> Result<(Object, Diagnostics), Diagnostics> {
+    let (asm, warnings) = run(source, options, asm::Assembler::relocatable())?;
+    Ok((asm.object(&warnings.sources), warnings))
+}
+
+/// Link `objects` into a program, placed in order from the reset vector.
+/// The sources of the objects are read again to show errors, if they can be.
+pub fn link(objects: &[Object]) -> Result<Output, Diagnostics> {
+    let mut linker = link::Linker::new();
+
+    match linker.link(objects) {
+        Ok(program) => Ok(Output {
+            data: program.data,
+            symbols: program.symbols,
+            lines: program.lines,
+            listing: None,
+            short_literals: 0,
+            warnings: Diagnostics::default(),
+        }),
+        Err(diagnostics) => Err(Diagnostics {
+            diagnostics,
+            sources: linker.sources().to_vec(),
+            expansions: Vec::new(),
+        }),
+    }
+}
+
+/// Assemble `source` with `asm`, returning it and its warnings.
+fn run(source: &str, options: &Options, mut asm: asm::Assembler) -> Result<(asm::Assembler, Diagnostics), Diagnostics> {
+    for (name, value) in &options.defines {
+        asm.define(name.to_string(), *value);
+    }
+
+    let mut loader = include::Loader::new(options.include_paths.clone());
+    let mut expansions = Vec::new();
+    let result = loader.load(&options.path, source.to_string())
+        .and_then(|tokens| macros::expand(tokens, &mut expansions))
+        .and_then(|tokens| parser::parse(&tokens))
+        .and_then(|ast| asm.assemble(&ast));
+
+    let diagnostics = Diagnostics {
+        diagnostics: result.err().unwrap_or_default(),
+        sources: loader.sources().to_vec(),
+        expansions,
+    };
+
+    if !diagnostics.is_empty() {
+        return Err(diagnostics);
+    }
+
+    let warnings = Diagnostics {
+        diagnostics: asm.warnings().to_vec(),
+        ..diagnostics
+    };
+    Ok((asm, warnings))
+}
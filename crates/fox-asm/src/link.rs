@@ -0,0 +1,223 @@
+use std::collections::HashMap;
+use crate::object::{self, Object, Address, Size, Location};
+use crate::expr::Expr;
+use crate::span::{Span, Spanned, Source};
+use crate::diagnostic::{Diagnostic, Diagnostics};
+use fox_bytecode::memory::{RESET_VECTOR, LOCALS_BASE};
+use fox_bytecode::symbols::Symbols;
+use fox_bytecode::lines::{LineTable, LineEntry};
+
+/// Linked objects, ready to write to a `.bin`, `.sym` and `.lines` file.
+pub struct Program {
+    pub data: Vec<u8>,
+    pub symbols: Symbols,
+    pub lines: LineTable,
+}
+
+/// An object with the address its section is linked at.
+struct Placed<'a> {
+    object: &'a Object,
+    base: u32,
+    labels: HashMap<&'a str, u32>,
+    constants: HashMap<&'a str, &'a object::Constant>,
+}
+
+/// Places the sections of objects after each other from the reset vector, and fills in their relocations.
+/// Labels of an object are private unless it exports them with `?export`.
+pub struct Linker {
+    /// Files the objects were assembled from, read again to show errors.
+    sources: Vec<Source>,
+}
+
+impl Linker {
+    pub fn new() -> Self {
+        Self {
+            sources: Vec::new(),
+        }
+    }
+
+    /// Files spans in the diagnostics of `link` refer to.
+    pub fn sources(&self) -> &[Source] {
+        &self.sources
+    }
+
+    pub fn link(&mut self, objects: &[Object]) -> Result<Program, Diagnostics> {
+        let mut diagnostics = Vec::new();
+        let mut placed = Vec::new();
+        let mut base = RESET_VECTOR;
+
+        for object in objects {
+            let labels = object.labels.iter().map(|(label, addr)| {
+                let addr = match addr {
+                    Address::Relative(addr) => base + addr,
+                    Address::Absolute(addr) => *addr,
+                };
+                (label.as_str(), addr)
+            });
+            let constants = object.constants.iter().map(|constant| (constant.name.as_str(), constant));
+
+            placed.push(Placed {
+                object,
+                base,
+                labels: labels.collect(),
+                constants: constants.collect(),
+            });
+            base += object.data.len() as u32;
+
+            // Objects are checked on their own, but not whether they fit together
+            if base > LOCALS_BASE && base - object.data.len() as u32 <= LOCALS_BASE {
+                if let Some(line) = object.lines.last() {
+                    let message = format!("Linked program ends at {:08x}, over the locals and stacks at the top of memory", base);
+                    diagnostics.push(Diagnostic::new(message, self.span(&line.location)));
+                }
+            }
+        }
+
+        let mut exports: HashMap<&str, (usize, &Location)> = HashMap::new();
+        for (index, object) in objects.iter().enumerate() {
+            for (name, location) in &object.exports {
+                match exports.get(name.as_str()) {
+                    Some((_, other)) => {
+                        let message = format!("`{}` is already exported from {}:{}:{}", name, other.file, other.line, other.column);
+                        diagnostics.push(Diagnostic::new(message, self.span(location)));
+                    },
+                    None => {
+                        exports.insert(name, (index, location));
+                    },
+                }
+            }
+        }
+        let exports: HashMap<&str, usize> = exports.into_iter().map(|(name, (index, _))| (name, index)).collect();
+
+        let mut data = Vec::new();
+        for (index, object) in placed.iter().enumerate() {
+            let start = data.len();
+            data.extend_from_slice(&object.object.data);
+
+            for relocation in &object.object.relocations {
+                let span = self.span(&relocation.location);
+                let expr = object::respan(&relocation.expr, span);
+
+                let value = match evaluate(&placed, &exports, index, &expr, &relocation.scope, &mut Vec::new()) {
+                    Ok(value) => value,
+                    Err(diagnostic) => {
+                        diagnostics.push(diagnostic);
+                        continue;
+                    },
+                };
+
+                let offset = start + relocation.offset as usize;
+                match relocation.size {
+                    Size::Byte => match u8::try_from(value) {
+                        Ok(byte) => data[offset] = byte,
+                        Err(_) => diagnostics.push(Diagnostic::new(format!("Byte out of range `{:x}`", value), span)),
+                    },
+                    Size::Word => data[offset..offset + 4].copy_from_slice(&value.to_le_bytes()),
+                }
+            }
+        }
+
+        if !diagnostics.is_empty() {
+            return Err(diagnostics);
+        }
+
+        // Exported labels keep their name, private labels only if no other label has it
+        let mut labels = HashMap::new();
+        for (name, index) in &exports {
+            if let Some(addr) = placed[*index].labels.get(name) {
+                labels.insert(name.to_string(), *addr);
+            }
+        }
+        for object in &placed {
+            for (name, addr) in &object.labels {
+                labels.entry(name.to_string()).or_insert(*addr);
+            }
+        }
+
+        let lines = placed.iter().flat_map(|object| {
+            object.object.lines.iter().map(|line| LineEntry {
+                start: object.base + line.start,
+                end: object.base + line.end,
+                file: line.location.file.clone(),
+                line: line.location.line,
+                column: line.location.column,
+            })
+        });
+
+        Ok(Program {
+            data,
+            symbols: Symbols::new(labels),
+            lines: LineTable::new(lines.collect()),
+        })
+    }
+
+    /// Span of `location`, reading the file it's in if it wasn't read yet.
+    /// Files that can't be read anymore are shown without their source.
+    fn span(&mut self, location: &Location) -> Span {
+        let file = match self.sources.iter().position(|source| source.name == location.file) {
+            Some(file) => file,
+            None => {
+                self.sources.push(Source {
+                    name: location.file.clone(),
+                    text: std::fs::read_to_string(&location.file).unwrap_or_default(),
+                });
+                self.sources.len() - 1
+            },
+        };
+
+        Span {
+            file: file as u32,
+            line: location.line,
+            column: location.column,
+            length: location.length,
+            expansion: None,
+        }
+    }
+}
+
+impl Default for Linker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Value of `expr` in object `index`, names are its own labels and constants, then exports of other objects.
+/// `constants` are the constants being evaluated, to catch cycles.
+fn evaluate(
+    placed: &[Placed],
+    exports: &HashMap<&str, usize>,
+    index: usize,
+    expr: &Spanned<Expr>,
+    scope: &str,
+    constants: &mut Vec<(usize, String)>,
+) -> Result<u32, Diagnostic> {
+    Expr::eval(expr, &mut |name, local, span| {
+        let name = if local { format!("{}/{}", scope, name) } else { name.to_string() };
+
+        // Own names first, then the object exporting it
+        let owner = if placed[index].labels.contains_key(name.as_str()) || placed[index].constants.contains_key(name.as_str()) {
+            index
+        } else if let Some(owner) = exports.get(name.as_str()).filter(|_| !local) {
+            *owner
+        } else if placed.iter().any(|object| object.labels.contains_key(name.as_str())) {
+            return Err(Diagnostic::new(format!("Label `{}` is in another object, but isn't exported", name), span));
+        } else {
+            return Err(Diagnostic::new(format!("Unknown label `{}`", name), span));
+        };
+
+        if let Some(addr) = placed[owner].labels.get(name.as_str()) {
+            return Ok(*addr);
+        }
+
+        let key = (owner, name);
+        if constants.contains(&key) {
+            return Err(Diagnostic::new(format!("Constant `{}` is defined in terms of itself", key.1), span));
+        }
+
+        let constant = placed[owner].constants[key.1.as_str()];
+        constants.push(key);
+        let value = evaluate(placed, exports, owner, &object::respan(&constant.expr, span), &constant.scope, constants);
+        constants.pop();
+        value
+    })
+}
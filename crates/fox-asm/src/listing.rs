@@ -0,0 +1,145 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write;
+use crate::asm::Assembler;
+use crate::span::{Span, Source};
+
+/// Bytes shown on each row, longer statements continue on the next rows.
+const ROW_BYTES: usize = 8;
+
+/// Statements on the same source line that emitted bytes right after each other.
+struct Group {
+    start: usize,
+    end: usize,
+    span: Span,
+}
+
+/// A listing of the assembled program, with the address, the emitted bytes and the source line they came from.
+/// Labels are shown where they are defined, and the values of labels and constants after the line using them.
+/// Statements are listed in the order they were emitted, so an origin going back shows up as the address going back,
+/// and bytes written more than once are marked on both statements.
+///
+/// ```text
+/// ( test.fox )
+/// 00000100  10 2d 01 00 00 10 14 01     5  ;hello-world ;print-str call  ( ;hello-world = 12d, ;print-str = 114 )
+/// 00000108  00 00 52
+/// ```
+pub fn listing(asm: &Assembler, sources: &[Source]) -> String {
+    let groups = groups(asm.statements());
+    let memory = asm.memory();
+
+    // Statement that last wrote each byte, to find the ones written over
+    let mut owners: HashMap<usize, usize> = HashMap::new();
+    let mut overwrites: Vec<Vec<usize>> = vec![Vec::new(); groups.len()];
+    let mut overwritten: Vec<Vec<usize>> = vec![Vec::new(); groups.len()];
+    for (index, group) in groups.iter().enumerate() {
+        for addr in group.start..group.end {
+            if let Some(owner) = owners.insert(addr, index) {
+                if !overwrites[index].contains(&owner) {
+                    overwrites[index].push(owner);
+                    overwritten[owner].push(index);
+                }
+            }
+        }
+    }
+
+    let mut labels: Vec<(u32, &str)> = asm.labels().iter().map(|(label, addr)| (*addr, label.as_str())).collect();
+    labels.sort();
+    let mut shown = vec![false; labels.len()];
+    let starts: HashSet<usize> = groups.iter().map(|group| group.start).collect();
+
+    let mut out = String::new();
+    let mut file = None;
+
+    for (index, group) in groups.iter().enumerate() {
+        let source = sources.get(group.span.file as usize);
+        if file != Some(group.span.file) {
+            file = Some(group.span.file);
+            writeln!(out, "( {} )", source.map_or("?", |source| source.name.as_str())).unwrap();
+        }
+
+        // Labels go with the statement starting there, or the one they're in the middle of
+        for (label, shown) in labels.iter().zip(&mut shown) {
+            let addr = label.0 as usize;
+            let here = addr == group.start || (group.start..group.end).contains(&addr) && !starts.contains(&addr);
+            if !*shown && here {
+                writeln!(out, "{:08x}  {}:", label.0, label.1).unwrap();
+                *shown = true;
+            }
+        }
+
+        let line = source.and_then(|source| source.text.lines().nth(group.span.line as usize - 1)).unwrap_or("");
+        let values: Vec<String> = asm.resolved()
+            .iter()
+            .filter(|(addr, _, _)| (group.start..group.end).contains(addr))
+            .map(|(_, span, value)| format!("{} = {:x}", text(sources, *span), value))
+            .collect();
+
+        let bytes = &memory[group.start..group.end];
+        for (row, chunk) in bytes.chunks(ROW_BYTES).enumerate() {
+            let hex: Vec<String> = chunk.iter().map(|byte| format!("{:02x}", byte)).collect();
+            let addr = group.start + row * ROW_BYTES;
+            if row > 0 {
+                writeln!(out, "{:08x}  {}", addr, hex.join(" ")).unwrap();
+                continue;
+            }
+
+            write!(out, "{:08x}  {:<width$} {:>5}  {}", addr, hex.join(" "), group.span.line, line.trim_end(), width = ROW_BYTES * 3 - 1).unwrap();
+            if !values.is_empty() {
+                write!(out, "  ( {} )", values.join(", ")).unwrap();
+            }
+            writeln!(out).unwrap();
+        }
+
+        for other in &overwrites[index] {
+            writeln!(out, "!! overwrites bytes from {}", location(sources, groups[*other].span)).unwrap();
+        }
+        for other in &overwritten[index] {
+            writeln!(out, "!! overwritten by {}, the bytes shown are the new ones", location(sources, groups[*other].span)).unwrap();
+        }
+
+        // Labels right after the bytes, unless the next statement continues there
+        if groups.get(index + 1).is_none_or(|next| next.start != group.end) {
+            for (label, shown) in labels.iter().zip(&mut shown) {
+                if !*shown && label.0 as usize == group.end {
+                    writeln!(out, "{:08x}  {}:", label.0, label.1).unwrap();
+                    *shown = true;
+                }
+            }
+        }
+    }
+
+    out
+}
+
+fn groups(statements: &[(usize, usize, Span)]) -> Vec<Group> {
+    let mut groups: Vec<Group> = Vec::new();
+
+    for (start, end, span) in statements {
+        if let Some(last) = groups.last_mut() {
+            let same_line = last.span.file == span.file && last.span.line == span.line && last.span.expansion == span.expansion;
+            if same_line && last.end == *start {
+                last.end = *end;
+                continue;
+            }
+        }
+
+        groups.push(Group {
+            start: *start,
+            end: *end,
+            span: *span,
+        });
+    }
+
+    groups
+}
+
+/// Source text `span` covers.
+fn text(sources: &[Source], span: Span) -> String {
+    let line = sources.get(span.file as usize).and_then(|source| source.text.lines().nth(span.line as usize - 1)).unwrap_or("");
+    line.chars().skip(span.column as usize - 1).take(span.length as usize).collect()
+}
+
+fn location(sources: &[Source], span: Span) -> String {
+    let file = sources.get(span.file as usize).map_or("?", |source| source.name.as_str());
+    format!("{}:{}:{}", file, span.line, span.column)
+}
@@ -0,0 +1,259 @@
+use std::collections::HashMap;
+use std::iter::Peekable;
+use std::str::FromStr;
+use fox_bytecode::Opcode;
+use crate::tokenizer::Token;
+use crate::span::{Span, Spanned, Expansion};
+use crate::diagnostic::{Diagnostic, Diagnostics};
+
+/// How deep macros can be expanded inside other macros.
+const MAX_DEPTH: usize = 32;
+
+struct Macro {
+    params: Vec<String>,
+    body: Vec<Spanned<Token>>,
+}
+
+/// Expand macros before parsing.
+/// A macro is defined as `%name param... { body }` and called as `name arg...`,
+/// with `^param` in the body replaced by the argument.
+/// `&` labels in the body are unique to each expansion.
+/// The spans of expanded tokens refer to `expansions`, which is filled even if there are errors.
+pub fn expand(tokens: Vec<Spanned<Token>>, expansions: &mut Vec<Expansion>) -> Result<Vec<Spanned<Token>>, Diagnostics> {
+    let mut expander = Expander {
+        macros: HashMap::new(),
+        expansions,
+        diagnostics: Vec::new(),
+    };
+
+    let tokens = expander.expand(tokens, 0);
+
+    if expander.diagnostics.is_empty() {
+        Ok(tokens)
+    } else {
+        Err(expander.diagnostics)
+    }
+}
+
+type Tokens = Peekable<std::vec::IntoIter<Spanned<Token>>>;
+
+struct Expander<'a> {
+    macros: HashMap<String, Macro>,
+    expansions: &'a mut Vec<Expansion>,
+    diagnostics: Diagnostics,
+}
+
+impl Expander<'_> {
+    fn expand(&mut self, tokens: Vec<Spanned<Token>>, depth: usize) -> Vec<Spanned<Token>> {
+        let mut out = Vec::new();
+        let mut it = tokens.into_iter().peekable();
+        let mut prefixed = false;
+        let mut brackets = 0usize;
+
+        while let Some(token) = it.next() {
+            match &token.value {
+                Token::Percent => {
+                    if let Err(diagnostic) = self.define(token.span, &mut it) {
+                        self.diagnostics.push(diagnostic);
+
+                        // Skip the rest of the definition
+                        for token in it.by_ref() {
+                            if let Token::CloseBrace = token.value {
+                                break;
+                            }
+                        }
+                    }
+                },
+                Token::OpenBracket => {
+                    brackets += 1;
+                    out.push(token);
+                },
+                Token::CloseBracket => {
+                    brackets = brackets.saturating_sub(1);
+                    out.push(token);
+                },
+                // Only a call if it's not the label or number of a prefix like `;name`, or part of an expression
+                Token::IdentifierOrNumber(name) if !prefixed && brackets == 0 && self.macros.contains_key(name) => {
+                    let name = name.clone();
+                    if let Err(diagnostic) = self.call(&name, token.span, &mut it, depth, &mut out) {
+                        self.diagnostics.push(diagnostic);
+                    }
+                },
+                Token::Comment(_) => {
+                    out.push(token);
+                    continue;
+                },
+                _ => out.push(token),
+            }
+
+            prefixed = out.last().is_some_and(|token| is_prefix(&token.value));
+        }
+
+        out
+    }
+
+    fn define(&mut self, span: Span, it: &mut Tokens) -> Result<(), Diagnostic> {
+        let name = match it.next() {
+            Some(Spanned { value: Token::IdentifierOrNumber(name), .. }) => name,
+            Some(token) => return Err(Diagnostic::new("Expected a macro name", token.span)),
+            None => return Err(Diagnostic::new("Expected a macro name", span)),
+        };
+
+        if Opcode::from_str(&name).is_ok() {
+            return Err(Diagnostic::new(format!("`{}` is an instruction and can't be a macro", name), span));
+        }
+
+        if self.macros.contains_key(&name) {
+            return Err(Diagnostic::new(format!("Macro `{}` is already defined", name), span));
+        }
+
+        let mut params = Vec::new();
+        loop {
+            match it.next() {
+                Some(Spanned { value: Token::IdentifierOrNumber(param), .. }) => params.push(param),
+                Some(Spanned { value: Token::Comment(_), .. }) => (),
+                Some(Spanned { value: Token::OpenBrace, .. }) => break,
+                Some(token) => return Err(Diagnostic::new(format!("Expected `{{` to start the body of `{}`", name), token.span)),
+                None => return Err(Diagnostic::new(format!("Expected `{{` to start the body of `{}`", name), span)),
+            }
+        }
+
+        let mut body = Vec::new();
+        loop {
+            let token = match it.next() {
+                Some(token) => token,
+                None => return Err(Diagnostic::new(format!("Expected `}}` to end the body of `{}`", name), span)),
+            };
+
+            match &token.value {
+                Token::CloseBrace => break,
+                Token::Percent | Token::OpenBrace => {
+                    return Err(Diagnostic::new("Macros can't be defined inside a macro", token.span));
+                },
+                Token::Caret => {
+                    match it.peek() {
+                        Some(Spanned { value: Token::IdentifierOrNumber(param), .. }) if params.contains(param) => (),
+                        Some(Spanned { value: Token::IdentifierOrNumber(param), span }) => {
+                            return Err(Diagnostic::new(format!("`{}` has no argument `{}`", name, param), *span));
+                        },
+                        _ => return Err(Diagnostic::new("Expected an argument name", token.span)),
+                    }
+                },
+                _ => (),
+            }
+
+            body.push(token);
+        }
+
+        self.macros.insert(name, Macro {
+            params,
+            body,
+        });
+
+        Ok(())
+    }
+
+    fn call(&mut self, name: &str, span: Span, it: &mut Tokens, depth: usize, out: &mut Vec<Spanned<Token>>) -> Result<(), Diagnostic> {
+        if depth >= MAX_DEPTH {
+            return Err(Diagnostic::new(format!("Macro `{}` is expanded more than {} deep, is it recursive?", name, MAX_DEPTH), span));
+        }
+
+        let params = self.macros[name].params.len();
+        let mut args = Vec::new();
+        let mut call = span;
+
+        let plural = if params == 1 { "" } else { "s" };
+        while args.len() < params {
+            match it.peek() {
+                Some(Spanned { value: Token::Comment(_), .. }) => {
+                    it.next();
+                },
+                Some(Spanned { value: Token::IdentifierOrNumber(_) | Token::String(_) | Token::Character(_), .. }) => {
+                    let arg = it.next().unwrap();
+                    call = call.to(arg.span);
+                    args.push(arg);
+                },
+                Some(Spanned { span, .. }) => {
+                    let message = format!("`{}` takes {} argument{}, expected a label, number, string or character", name, params, plural);
+                    return Err(Diagnostic::new(message, *span));
+                },
+                None => return Err(Diagnostic::new(format!("`{}` takes {} argument{}", name, params, plural), call)),
+            }
+        }
+
+        let index = self.expansions.len() as u32;
+        self.expansions.push(Expansion {
+            name: name.to_string(),
+            call,
+        });
+
+        let mac = &self.macros[name];
+        let mut body = Vec::new();
+        let mut tokens = mac.body.iter().peekable();
+        // Between `[` and `]`, where `&` can also be AND
+        let mut brackets: usize = 0;
+
+        while let Some(token) = tokens.next() {
+            let span = Span {
+                expansion: Some(index),
+                ..token.span
+            };
+
+            match &token.value {
+                Token::Caret => {
+                    // Checked when the macro was defined
+                    if let Some(Spanned { value: Token::IdentifierOrNumber(param), .. }) = tokens.next() {
+                        let position = mac.params.iter().position(|other| other == param).unwrap();
+                        body.push(args[position].clone());
+                    }
+                },
+                Token::Ampersand => {
+                    // In an expression it's a local label where a value is expected, like `[ &end - &start ]`
+                    let local = brackets == 0 || body.last().is_none_or(|previous| expects_value(&previous.value));
+                    body.push(Spanned::new(Token::Ampersand, span));
+
+                    if !local {
+                        continue;
+                    }
+                    if let Some(Spanned { value: Token::IdentifierOrNumber(label), span: label_span }) = tokens.peek() {
+                        // Written so the name can be assembled again, like in the output of fox-dis
+                        let label = Token::IdentifierOrNumber(format!("{}-{}", label, index));
+                        body.push(Spanned::new(label, Span { expansion: Some(index), ..*label_span }));
+                        tokens.next();
+                    }
+                },
+                value => {
+                    match value {
+                        Token::OpenBracket => brackets += 1,
+                        Token::CloseBracket => brackets = usize::saturating_sub(brackets, 1),
+                        _ => (),
+                    }
+                    body.push(Spanned::new(value.clone(), span));
+                },
+            }
+        }
+
+        let expanded = self.expand(body, depth + 1);
+        out.extend(expanded);
+
+        Ok(())
+    }
+}
+
+/// Tokens in an expression that are followed by a value, instead of an operator.
+fn expects_value(token: &Token) -> bool {
+    matches!(
+        token,
+        Token::OpenBracket | Token::OpenParen | Token::Plus | Token::Minus | Token::Star | Token::Slash
+            | Token::Ampersand | Token::Pipe | Token::ShiftLeft | Token::ShiftRight
+    )
+}
+
+/// Tokens that take a label or number after them.
+fn is_prefix(token: &Token) -> bool {
+    matches!(
+        token,
+        Token::At | Token::Semicolon | Token::Pound | Token::Pipe | Token::Ampersand
+            | Token::Period | Token::Dollar | Token::Equal | Token::Colon | Token::Caret | Token::Binary(_) | Token::Star | Token::Question
+    )
+}
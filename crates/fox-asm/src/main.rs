@@ -1,29 +1,301 @@
-mod tokenizer;
-mod parser;
-mod asm;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use fox_asm::{Diagnostics, Object, Options, Output};
+
+const USAGE: &str = "\
+Usage: fox-asm [options] <input>...
+
+Assembles a .fox file into a .bin, or links .fox and .o files into one.
+
+Options:
+  -o <file>          Write the program to <file>, or to stdout if it's -
+  -c                 Assemble each input into a .o object to link later
+  -I <dir>           Look up includes in <dir> too
+  -D <name>[=value]  Define constant <name>, as 1 if there is no value
+  --sym <file>       Write the symbols to <file> instead of next to the program
+  --lines <file>     Write the line table to <file> instead of next to the program
+  --no-sym           Don't write the symbols
+  --no-lines         Don't write the line table
+  --listing          Write a listing next to the program
+  --lst <file>       Write a listing to <file>
+  -W <level>         Warnings: off, on or error to fail on them
+  -q                 Only print warnings and errors
+  -h, --help         Print this help";
+
+/// Exit code for errors in the source, or files that can't be read or written.
+const EXIT_ERROR: i32 = 1;
+/// Exit code for invalid arguments.
+const EXIT_USAGE: i32 = 2;
+
+fn fail(message: impl std::fmt::Display) -> ! {
+    eprintln!("{}", message);
+    std::process::exit(EXIT_ERROR);
+}
+
+fn usage(message: impl std::fmt::Display) -> ! {
+    eprintln!("{}\n\n{}", message, USAGE);
+    std::process::exit(EXIT_USAGE);
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Warnings {
+    Off,
+    On,
+    Error,
+}
+
+/// Where to write something, next to the program unless given.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Target {
+    Default,
+    Path(PathBuf),
+    None,
+}
+
+struct Args {
+    inputs: Vec<PathBuf>,
+    /// `-` for stdout.
+    output: Option<PathBuf>,
+    compile: bool,
+    include_paths: Vec<PathBuf>,
+    defines: Vec<(String, u32)>,
+    symbols: Target,
+    lines: Target,
+    listing: Target,
+    warnings: Warnings,
+    quiet: bool,
+}
+
+impl Args {
+    fn parse() -> Self {
+        let mut result = Args {
+            inputs: Vec::new(),
+            output: None,
+            compile: false,
+            include_paths: Vec::new(),
+            defines: Vec::new(),
+            symbols: Target::Default,
+            lines: Target::Default,
+            listing: Target::None,
+            warnings: Warnings::On,
+            quiet: false,
+        };
+
+        let mut args = std::env::args().skip(1);
+        while let Some(arg) = args.next() {
+            let mut value = |what: &str| args.next().unwrap_or_else(|| usage(format!("Expected {} after {}", what, arg)));
+
+            match arg.as_str() {
+                "-o" => result.output = Some(PathBuf::from(value("a file"))),
+                "-c" => result.compile = true,
+                "-I" => result.include_paths.push(PathBuf::from(value("a directory"))),
+                "-D" => result.defines.push(parse_define(&value("a name"))),
+                "--sym" => result.symbols = Target::Path(PathBuf::from(value("a file"))),
+                "--lines" => result.lines = Target::Path(PathBuf::from(value("a file"))),
+                "--no-sym" => result.symbols = Target::None,
+                "--no-lines" => result.lines = Target::None,
+                "--listing" => result.listing = Target::Default,
+                "--lst" => result.listing = Target::Path(PathBuf::from(value("a file"))),
+                "-W" => result.warnings = match value("a level").as_str() {
+                    "off" => Warnings::Off,
+                    "on" => Warnings::On,
+                    "error" => Warnings::Error,
+                    level => usage(format!("Unknown warning level `{}`, expected off, on or error", level)),
+                },
+                "-q" => result.quiet = true,
+                "-h" | "--help" => {
+                    println!("{}", USAGE);
+                    std::process::exit(0);
+                },
+                _ if arg.starts_with('-') && arg != "-" => usage(format!("Unknown option {}", arg)),
+                _ => result.inputs.push(PathBuf::from(arg)),
+            }
+        }
+
+        if result.inputs.is_empty() {
+            usage("Expected an input file");
+        }
+        if result.compile && result.output.is_some() && result.inputs.len() > 1 {
+            usage("-o can't name the objects of more than one input");
+        }
+        if result.listing != Target::None && (result.compile || result.is_linking()) {
+            usage("Listings can only be made when assembling a single .fox file");
+        }
+
+        result
+    }
+
+    fn is_linking(&self) -> bool {
+        !self.compile && (self.inputs.len() > 1 || is_object(&self.inputs[0]))
+    }
+
+    fn options(&self, input: &Path) -> Options {
+        Options {
+            path: input.to_path_buf(),
+            include_paths: self.include_paths.clone(),
+            listing: self.listing != Target::None,
+            defines: self.defines.clone(),
+            ..Options::new()
+        }
+    }
+
+    /// Print progress, unless quiet.
+    /// This goes to stderr, as stdout can be the program.
+    fn progress(&self, message: impl std::fmt::Display) {
+        if !self.quiet {
+            eprintln!("{}", message);
+        }
+    }
+
+    /// Print `warnings` depending on the warning level, failing if they are errors.
+    fn warnings(&self, input: &Path, warnings: &Diagnostics) {
+        match self.warnings {
+            Warnings::Off => (),
+            Warnings::On => eprint!("{}", warnings),
+            Warnings::Error if warnings.is_empty() => (),
+            Warnings::Error => {
+                eprint!("{}", warnings);
+                let plural = if warnings.len() == 1 { "" } else { "s" };
+                fail(format!("Could not assemble {}, {} warning{} treated as errors", input.display(), warnings.len(), plural));
+            },
+        }
+    }
+}
+
+/// `name` or `name=value`, with the value a number like in the source.
+fn parse_define(define: &str) -> (String, u32) {
+    let (name, value) = define.split_once('=').unwrap_or((define, "1"));
+
+    if name.is_empty() || fox_asm::parse_number(name).is_some() {
+        usage(format!("Can't define `{}`, it's not a name", name));
+    }
+
+    match fox_asm::parse_number(value) {
+        Some(Ok(value)) => (name.to_string(), value),
+        Some(Err(message)) => usage(message),
+        None => usage(format!("Can't define `{}` as `{}`, it's not a number", name, value)),
+    }
+}
+
+fn is_object(input: &Path) -> bool {
+    input.extension().is_some_and(|extension| extension == "o")
+}
 
 fn main() {
-    let args: Vec<_> = std::env::args().collect();
-    if args.len() != 2 {
-        println!("Expected 1 argument");
-        return;
+    let args = Args::parse();
+
+    if args.compile {
+        for input in &args.inputs {
+            if is_object(input) {
+                fail(format!("{} is already an object", input.display()));
+            }
+            let object = compile_object(&args, input);
+            let output = args.output.clone().unwrap_or_else(|| input.with_extension("o"));
+            write(&args, &output, object.to_string().as_bytes());
+        }
+    } else if args.is_linking() {
+        let objects: Vec<Object> = args.inputs.iter().map(|input| {
+            if is_object(input) {
+                let source = read(input);
+                Object::parse(&source).unwrap_or_else(|err| fail(format!("{}: {}", input.display(), err)))
+            } else {
+                compile_object(&args, input)
+            }
+        }).collect();
+
+        let output = match fox_asm::link(&objects) {
+            Ok(output) => output,
+            Err(diagnostics) => {
+                eprint!("{}", diagnostics);
+                let plural = if diagnostics.len() == 1 { "" } else { "s" };
+                fail(format!("Could not link {} objects, {} error{}", objects.len(), diagnostics.len(), plural));
+            },
+        };
+
+        args.progress(format!("Linked {} objects in {} bytes", objects.len(), output.data.len()));
+        write_output(&args, &output);
+    } else {
+        let input = &args.inputs[0];
+        let output = match fox_asm::assemble(&read(input), &args.options(input)) {
+            Ok(output) => output,
+            Err(diagnostics) => {
+                eprint!("{}", diagnostics);
+                let plural = if diagnostics.len() == 1 { "" } else { "s" };
+                fail(format!("Could not assemble {}, {} error{}", input.display(), diagnostics.len(), plural));
+            },
+        };
+
+        let plural = if output.short_literals == 1 { "" } else { "s" };
+        let labels = output.symbols.iter().count();
+        args.progress(format!("Assembled in {} bytes, {} labels, {} short literal{}", output.data.len(), labels, output.short_literals, plural));
+        args.warnings(input, &output.warnings);
+
+        write_output(&args, &output);
     }
+}
 
-    let input_filename = std::path::Path::new(&args[1]);
-    let output_filename = input_filename.with_extension("bin");
-    println!("Writing to {}", output_filename.display());
+/// `-` reads stdin.
+fn read(input: &Path) -> String {
+    let result = if input == Path::new("-") {
+        std::io::read_to_string(std::io::stdin())
+    } else {
+        std::fs::read_to_string(input)
+    };
 
-    let input = std::fs::read_to_string(input_filename).unwrap();
+    result.unwrap_or_else(|err| fail(format!("Could not read {}: {}", input.display(), err)))
+}
 
-    let tokens = tokenizer::tokenize(&input);
-    let ast = parser::parse(&tokens);
+/// `-` writes to stdout.
+fn write(args: &Args, path: &Path, data: &[u8]) {
+    let result = if path == Path::new("-") {
+        std::io::stdout().write_all(data).and_then(|_| std::io::stdout().flush())
+    } else {
+        args.progress(format!("Writing to {}", path.display()));
+        std::fs::write(path, data)
+    };
 
-    let mut asm = asm::Assembler::new();
-    asm.assemble(&ast);
+    if let Err(err) = result {
+        fail(format!("Could not write {}: {}", path.display(), err));
+    }
+}
 
-    //println!("Tokens: {:x?}", tokens);
-    //println!("{:x?}", ast);
-    //println!("Asm: {:x?}", asm.data());
+fn compile_object(args: &Args, input: &Path) -> Object {
+    match fox_asm::compile(&read(input), &args.options(input)) {
+        Ok((object, warnings)) => {
+            args.progress(format!("Assembled {} in {} bytes, {} labels", input.display(), object.data.len(), object.labels.len()));
+            args.warnings(input, &warnings);
+            object
+        },
+        Err(diagnostics) => {
+            eprint!("{}", diagnostics);
+            let plural = if diagnostics.len() == 1 { "" } else { "s" };
+            fail(format!("Could not assemble {}, {} error{}", input.display(), diagnostics.len(), plural));
+        },
+    }
+}
 
-    std::fs::write(output_filename, asm.data()).unwrap();
+/// Write the program, and the symbols, line table and listing that go with it.
+/// These are named after the program, which is named after the first input, unless they're given.
+/// A program written to stdout only has the ones that are given.
+fn write_output(args: &Args, output: &Output) {
+    let program = args.output.clone().unwrap_or_else(|| args.inputs[0].with_extension("bin"));
+    let stdout = program == Path::new("-");
+
+    let path = |target: &Target, extension: &str| match target {
+        Target::Default if !stdout => Some(program.with_extension(extension)),
+        Target::Path(path) => Some(path.clone()),
+        _ => None,
+    };
+
+    write(args, &program, &output.data);
+
+    if let Some(path) = path(&args.symbols, "sym") {
+        write(args, &path, output.symbols.to_string().as_bytes());
+    }
+    if let Some(path) = path(&args.lines, "lines") {
+        write(args, &path, output.lines.to_string().as_bytes());
+    }
+    if let (Some(path), Some(listing)) = (path(&args.listing, "lst"), &output.listing) {
+        write(args, &path, listing.as_bytes());
+    }
 }
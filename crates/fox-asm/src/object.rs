@@ -0,0 +1,332 @@
+use std::fmt;
+use crate::expr::{Expr, Op};
+use crate::span::{Span, Spanned};
+
+/// Address of a label in an object.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Address {
+    /// Offset from the start of the section, moves with the section when linking.
+    Relative(u32),
+    /// Outside of the section, like device ports or variables in low memory.
+    Absolute(u32),
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Size {
+    Byte,
+    Word,
+}
+
+/// Source location, by file name since objects are linked without their sources.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Location {
+    pub file: String,
+    pub line: u32,
+    pub column: u32,
+    pub length: u32,
+}
+
+#[derive(Debug, Clone)]
+pub struct Constant {
+    pub name: String,
+    /// Label local names are relative to.
+    pub scope: String,
+    pub expr: Spanned<Expr>,
+}
+
+/// A value that depends on where the section is linked, or on labels from other objects.
+#[derive(Debug, Clone)]
+pub struct Relocation {
+    /// Offset in the section to write the value to.
+    pub offset: u32,
+    pub size: Size,
+    pub scope: String,
+    pub expr: Spanned<Expr>,
+    pub location: Location,
+}
+
+/// Source location of a range of the section.
+#[derive(Debug, Clone)]
+pub struct Line {
+    pub start: u32,
+    /// Exclusive.
+    pub end: u32,
+    pub location: Location,
+}
+
+/// A relocatable object, written by `fox-asm -c` and linked into a rom.
+/// It has a single `code` section, placed after the sections of the objects before it.
+///
+/// Objects are text, one record per line, after a header with the version of the format.
+/// `section` gives the name and size of the section the `data` records after it fill in.
+/// Version 1 only has the `code` section, other sections need a new version.
+///
+///
+/// ```text
+/// fox-object 1
+/// section code 0000000e
+/// data 10000000001018
+/// label 00000000 print
+/// label-absolute 10000004 console-write
+/// constant size print #10
+/// file 0 lib/print.fox
+/// export print 0:1:2:5
+/// relocation 00000001 word print 0:3:2:12 &loop
+/// line 00000000 00000005 0:3:2
+/// ```
+///
+/// Expressions are written in postfix, with `#` numbers, `@` names and `&` local names.
+#[derive(Debug, Clone, Default)]
+pub struct Object {
+    pub data: Vec<u8>,
+    pub labels: Vec<(String, Address)>,
+    pub constants: Vec<Constant>,
+    pub exports: Vec<(String, Location)>,
+    pub relocations: Vec<Relocation>,
+    pub lines: Vec<Line>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    pub line: usize,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid object on line {}", self.line)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+const MAGIC: &str = "fox-object";
+const VERSION: u32 = 1;
+
+/// Name of the section with the bytes of the object.
+const CODE: &str = "code";
+
+/// Bytes per `data` line.
+const DATA_LINE: usize = 32;
+
+impl Object {
+    pub fn parse(source: &str) -> Result<Self, ParseError> {
+        let mut object = Object::default();
+        let mut files = Vec::new();
+        let mut lines = source.lines().enumerate();
+        // Line and size of the section the data is for
+        let mut section: Option<(usize, u32)> = None;
+
+        let header = lines.next().and_then(|(_, line)| line.split_once(' '));
+        match header {
+            Some((MAGIC, version)) if version.parse() == Ok(VERSION) => (),
+            _ => return Err(ParseError { line: 1 }),
+        }
+
+        for (index, line) in lines {
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let error = ParseError { line: index + 1 };
+            let (kind, rest) = line.split_once(' ').ok_or(error)?;
+            let mut fields = rest.split(' ');
+            let mut field = || fields.next().ok_or(error);
+            let hex = |str: &str| u32::from_str_radix(str, 16).map_err(|_| error);
+
+            match kind {
+                "section" => {
+                    let name = field()?;
+                    let size = hex(field()?)?;
+                    if name != CODE || section.is_some() {
+                        return Err(error);
+                    }
+                    section = Some((index, size));
+                    object.data.reserve(size as _);
+                },
+                "data" => {
+                    if section.is_none() {
+                        return Err(error);
+                    }
+                    let data = field()?;
+                    for index in (0..data.len()).step_by(2) {
+                        let byte = data.get(index..index + 2).and_then(|byte| u8::from_str_radix(byte, 16).ok()).ok_or(error)?;
+                        object.data.push(byte);
+                    }
+                },
+                "label" => {
+                    let addr = hex(field()?)?;
+                    object.labels.push((field()?.to_string(), Address::Relative(addr)));
+                },
+                "label-absolute" => {
+                    let addr = hex(field()?)?;
+                    object.labels.push((field()?.to_string(), Address::Absolute(addr)));
+                },
+                "constant" => {
+                    let name = field()?.to_string();
+                    let scope = field()?.to_string();
+                    let expr = from_postfix(fields, Span::default()).ok_or(error)?;
+                    object.constants.push(Constant { name, scope, expr });
+                },
+                "export" => {
+                    let name = field()?.to_string();
+                    let location = parse_location(field()?, &files).ok_or(error)?;
+                    object.exports.push((name, location));
+                },
+                "file" => {
+                    let _index = field()?;
+                    files.push(fields.collect::<Vec<_>>().join(" "));
+                },
+                "relocation" => {
+                    let offset = hex(field()?)?;
+                    let size = match field()? {
+                        "byte" => Size::Byte,
+                        "word" => Size::Word,
+                        _ => return Err(error),
+                    };
+                    let scope = field()?.to_string();
+                    let location = parse_location(field()?, &files).ok_or(error)?;
+                    let expr = from_postfix(fields, Span::default()).ok_or(error)?;
+                    object.relocations.push(Relocation { offset, size, scope, expr, location });
+                },
+                "line" => {
+                    let start = hex(field()?)?;
+                    let end = hex(field()?)?;
+                    let location = parse_location(field()?, &files).ok_or(error)?;
+                    object.lines.push(Line { start, end, location });
+                },
+                _ => return Err(error),
+            }
+        }
+
+        if let Some((index, size)) = section {
+            if object.data.len() != size as usize {
+                return Err(ParseError { line: index + 1 });
+            }
+        }
+
+        Ok(object)
+    }
+}
+
+impl fmt::Display for Object {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{} {}", MAGIC, VERSION)?;
+        writeln!(f, "section {} {:08x}", CODE, self.data.len())?;
+
+        for line in self.data.chunks(DATA_LINE) {
+            let hex: String = line.iter().map(|byte| format!("{:02x}", byte)).collect();
+            writeln!(f, "data {}", hex)?;
+        }
+
+        for (label, addr) in &self.labels {
+            match addr {
+                Address::Relative(addr) => writeln!(f, "label {:08x} {}", addr, label)?,
+                Address::Absolute(addr) => writeln!(f, "label-absolute {:08x} {}", addr, label)?,
+            }
+        }
+
+        for constant in &self.constants {
+            writeln!(f, "constant {} {} {}", constant.name, constant.scope, to_postfix(&constant.expr))?;
+        }
+
+        // Files are numbered in the order they are first used
+        let mut files: Vec<&str> = Vec::new();
+        let locations = self.exports.iter().map(|(_, location)| location)
+            .chain(self.relocations.iter().map(|relocation| &relocation.location))
+            .chain(self.lines.iter().map(|line| &line.location));
+        for location in locations {
+            if !files.contains(&location.file.as_str()) {
+                writeln!(f, "file {} {}", files.len(), location.file)?;
+                files.push(&location.file);
+            }
+        }
+        let file = |location: &Location| files.iter().position(|file| *file == location.file).unwrap();
+
+        for (name, location) in &self.exports {
+            writeln!(f, "export {} {}:{}:{}:{}", name, file(location), location.line, location.column, location.length)?;
+        }
+
+        for relocation in &self.relocations {
+            let size = match relocation.size {
+                Size::Byte => "byte",
+                Size::Word => "word",
+            };
+            let location = &relocation.location;
+            writeln!(
+                f,
+                "relocation {:08x} {} {} {}:{}:{}:{} {}",
+                relocation.offset, size, relocation.scope,
+                file(location), location.line, location.column, location.length,
+                to_postfix(&relocation.expr),
+            )?;
+        }
+
+        for line in &self.lines {
+            let location = &line.location;
+            writeln!(f, "line {:08x} {:08x} {}:{}:{}", line.start, line.end, file(location), location.line, location.column)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// `file:line:column` or `file:line:column:length`, with `file` an index into `files`.
+fn parse_location(str: &str, files: &[String]) -> Option<Location> {
+    let mut parts = str.split(':').map(|part| part.parse::<u32>().ok());
+    let file = files.get(parts.next()?? as usize)?.clone();
+    let line = parts.next()??;
+    let column = parts.next()??;
+    let length = parts.next().unwrap_or(Some(1))?;
+
+    Some(Location { file, line, column, length })
+}
+
+fn to_postfix(expr: &Spanned<Expr>) -> String {
+    match &expr.value {
+        Expr::Number(value) => format!("#{:x}", value),
+        Expr::Name(name) => format!("@{}", name),
+        Expr::Local(name) => format!("&{}", name),
+        Expr::Negate(value) => format!("{} neg", to_postfix(value)),
+        Expr::Binary(op, a, b) => format!("{} {} {}", to_postfix(a), to_postfix(b), op.symbol()),
+    }
+}
+
+/// Expression from postfix `items`, every part of it covers `span`.
+fn from_postfix<'a>(items: impl Iterator<Item = &'a str>, span: Span) -> Option<Spanned<Expr>> {
+    let mut stack = Vec::new();
+
+    for item in items {
+        let expr = if let Some(number) = item.strip_prefix('#') {
+            Expr::Number(u32::from_str_radix(number, 16).ok()?)
+        } else if let Some(name) = item.strip_prefix('@') {
+            Expr::Name(name.to_string())
+        } else if let Some(name) = item.strip_prefix('&').filter(|name| !name.is_empty()) {
+            Expr::Local(name.to_string())
+        } else if item == "neg" {
+            Expr::Negate(Box::new(stack.pop()?))
+        } else {
+            let op = Op::from_symbol(item)?;
+            let b = stack.pop()?;
+            let a = stack.pop()?;
+            Expr::Binary(op, Box::new(a), Box::new(b))
+        };
+
+        stack.push(Spanned::new(expr, span));
+    }
+
+    match (stack.pop(), stack.is_empty()) {
+        (Some(expr), true) => Some(expr),
+        _ => None,
+    }
+}
+
+/// `expr` with every part of it covering `span`.
+pub fn respan(expr: &Spanned<Expr>, span: Span) -> Spanned<Expr> {
+    let value = match &expr.value {
+        Expr::Negate(value) => Expr::Negate(Box::new(respan(value, span))),
+        Expr::Binary(op, a, b) => Expr::Binary(*op, Box::new(respan(a, span)), Box::new(respan(b, span))),
+        value => value.clone(),
+    };
+
+    Spanned::new(value, span)
+}
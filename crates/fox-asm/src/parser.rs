@@ -1,99 +1,316 @@
+use std::iter::Peekable;
 use crate::tokenizer::Token;
+use crate::span::{Span, Spanned};
+use crate::diagnostic::{Diagnostic, Diagnostics};
+use crate::expr::{self, Expr};
 use fox_bytecode::Opcode;
 
 #[derive(Debug)]
 pub enum Stmt {
-    OriginAbsolute(u32),
-    OriginRelative(u32),
-    LiteralWord(u32),
+    OriginAbsolute(Spanned<Expr>),
+    OriginRelative(Spanned<Expr>),
+    LiteralWord(Spanned<Expr>),
     LabelAbsolute(String),
     LocalLabelAbsolute(String),
     ReferenceAbsolute(String),
     LocalReferenceAbsolute(String),
     Operation(Opcode),
-    String(String),
-    RawByte(u8),
-    RawWord(u32),
+    String(Vec<u8>),
+    RawByte(Spanned<Expr>),
+    RawWord(Spanned<Expr>),
     RawReferenceAbsolute(String),
+    RawLocalReferenceAbsolute(String),
+    /// Bytes of a binary file, labeled with `label` and `label/size`.
+    Binary(String, Vec<u8>),
+    /// `*name value` defines a name for a value that isn't an address.
+    Constant(String, Spanned<Expr>),
+    /// `?relax mode` for the rest of the file.
+    Relax(Relax),
+    /// `?export name` makes a label or constant available to other objects when linking.
+    Export(String),
+    /// `?struct name { field size ... }` declares the layout of records.
+    Struct(String, Vec<Field>),
+    /// `?record struct label { field value ... }` emits a record of `struct`, fields without a value are 0.
+    Record {
+        kind: Spanned<String>,
+        label: String,
+        values: Vec<(Spanned<String>, Spanned<Expr>)>,
+    },
+    /// Declared effect of calling the label before it.
+    StackEffect(StackEffect),
 }
 
-pub type Ast = Vec<Stmt>;
+/// A field of a `?struct`, with its size in bytes.
+#[derive(Debug, Clone)]
+pub struct Field {
+    pub name: Spanned<String>,
+    pub size: u32,
+}
+
+/// A `( a b -- c )` comment, only the number of values on each side matters.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct StackEffect {
+    pub inputs: usize,
+    pub outputs: usize,
+}
+
+impl StackEffect {
+    pub fn parse(comment: &str) -> Option<Self> {
+        let (inputs, outputs) = comment.split_once("--")?;
+
+        Some(Self {
+            inputs: inputs.split_whitespace().count(),
+            outputs: outputs.split_whitespace().count(),
+        })
+    }
+}
+
+impl std::fmt::Display for StackEffect {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "( {} -- {} )", self.inputs, self.outputs)
+    }
+}
+
+/// Which literals are written as `LITB` when their value fits in a byte.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum Relax {
+    /// Always `LITW`, so the layout doesn't depend on values.
+    Off,
+    /// Literals that don't depend on labels, like `#10` or `#[ size * 2 ]`.
+    #[default]
+    Numbers,
+    /// Label references like `;label` as well, if their address fits.
+    Labels,
+}
 
-fn parse_identifier<'a>(it: &mut impl Iterator<Item=&'a Token>) -> &'a str {
-    if let Some(Token::IdentifierOrNumber(str)) = it.next() {
-        return str;
+pub type Ast = Vec<Spanned<Stmt>>;
+
+type Tokens<'a> = Peekable<std::slice::Iter<'a, Spanned<Token>>>;
+
+/// Identifier following the prefix at `prefix`.
+/// Anything else is left for the main loop to parse.
+fn parse_identifier<'a>(it: &mut Tokens<'a>, prefix: Span) -> Result<Spanned<&'a str>, Diagnostic> {
+    if let Some(Spanned { value: Token::IdentifierOrNumber(str), span }) = it.peek() {
+        let span = *span;
+        it.next();
+        Ok(Spanned::new(str, span))
     } else {
-        panic!("Invalid value");
+        let span = it.peek().map_or(prefix, |token| token.span);
+        Err(Diagnostic::new("Expected a label or number", span))
     }
 }
 
-fn parse_number<'a>(it: &mut impl Iterator<Item=&'a Token>) -> u32 {
-    let str = parse_identifier(it);
-    u32::from_str_radix(&str, 16).unwrap()
+/// Items between `{` and `}` after `prefix`, each starting with a name parsed further by `item`.
+/// Returns the items and the span of the `}`.
+fn parse_block<T>(
+    it: &mut Tokens,
+    prefix: Span,
+    mut item: impl FnMut(&mut Tokens, Spanned<String>) -> Result<T, Diagnostic>,
+) -> Result<(Vec<T>, Span), Diagnostic> {
+    let mut items = Vec::new();
+
+    while let Some(Spanned { value: Token::Comment(_), .. }) = it.peek() {
+        it.next();
+    }
+    match it.next() {
+        Some(Spanned { value: Token::OpenBrace, .. }) => (),
+        Some(token) => return Err(Diagnostic::new("Expected `{`", token.span)),
+        None => return Err(Diagnostic::new("Expected `{`", prefix)),
+    }
+
+    loop {
+        match it.next() {
+            Some(Spanned { value: Token::Comment(_), .. }) => (),
+            Some(Spanned { value: Token::CloseBrace, span }) => return Ok((items, *span)),
+            Some(Spanned { value: Token::IdentifierOrNumber(name), span }) => {
+                match item(it, Spanned::new(name.to_string(), *span)) {
+                    Ok(value) => items.push(value),
+                    Err(diagnostic) => return Err(skip_block(it, diagnostic)),
+                }
+            },
+            Some(token) => return Err(skip_block(it, Diagnostic::new("Expected a field name or `}`", token.span))),
+            None => return Err(Diagnostic::new("Expected `}`", prefix)),
+        }
+    }
 }
 
-pub fn parse(tokens: &[Token]) -> Ast {
+/// Skip the rest of a block after `diagnostic`, so its `}` isn't reported as well.
+fn skip_block(it: &mut Tokens, diagnostic: Diagnostic) -> Diagnostic {
+    for token in it.by_ref() {
+        if let Token::CloseBrace = token.value {
+            break;
+        }
+    }
+    diagnostic
+}
+
+fn parse_stmt(token: &Spanned<Token>, it: &mut Tokens) -> Result<Spanned<Stmt>, Diagnostic> {
+    let span = token.span;
+    let (stmt, end) = match &token.value {
+        Token::At => {
+            let str = parse_identifier(it, span)?;
+            (Stmt::LabelAbsolute(str.value.to_string()), str.span)
+        },
+        Token::Semicolon => {
+            if let Some(Spanned { value: Token::Ampersand, .. }) = it.peek() {
+                it.next(); // Eat Ampersand
+                let str = parse_identifier(it, span)?;
+                (Stmt::LocalReferenceAbsolute(str.value.to_string()), str.span)
+            } else {
+                let str = parse_identifier(it, span)?;
+                (Stmt::ReferenceAbsolute(str.value.to_string()), str.span)
+            }
+        },
+        Token::Colon => {
+            if let Some(Spanned { value: Token::Ampersand, .. }) = it.peek() {
+                it.next(); // Eat Ampersand
+                let str = parse_identifier(it, span)?;
+                (Stmt::RawLocalReferenceAbsolute(str.value.to_string()), str.span)
+            } else {
+                let str = parse_identifier(it, span)?;
+                (Stmt::RawReferenceAbsolute(str.value.to_string()), str.span)
+            }
+        },
+        Token::Pound => {
+            let value = expr::parse_value(it, span)?;
+            let end = value.span;
+            (Stmt::LiteralWord(value), end)
+        },
+        Token::Pipe => {
+            let value = expr::parse_value(it, span)?;
+            let end = value.span;
+            (Stmt::OriginAbsolute(value), end)
+        },
+        Token::Ampersand => {
+            let str = parse_identifier(it, span)?;
+            (Stmt::LocalLabelAbsolute(str.value.to_string()), str.span)
+        },
+        Token::Period => {
+            let value = expr::parse_value(it, span)?;
+            let end = value.span;
+            (Stmt::RawByte(value), end)
+        },
+        Token::Equal => {
+            let value = expr::parse_value(it, span)?;
+            let end = value.span;
+            (Stmt::RawWord(value), end)
+        },
+        Token::Question => {
+            let directive = parse_identifier(it, span)?;
+            match directive.value {
+                "relax" => {
+                    let mode = parse_identifier(it, directive.span)?;
+                    let relax = match mode.value {
+                        "off" => Relax::Off,
+                        "numbers" => Relax::Numbers,
+                        "labels" => Relax::Labels,
+                        _ => return Err(Diagnostic::new("Expected `off`, `numbers` or `labels` after `?relax`", mode.span)),
+                    };
+                    (Stmt::Relax(relax), mode.span)
+                },
+                "export" => {
+                    let name = parse_identifier(it, directive.span)?;
+                    (Stmt::Export(name.value.to_string()), name.span)
+                },
+                "struct" => {
+                    let name = parse_identifier(it, directive.span)?;
+                    let (fields, end) = parse_block(it, name.span, |it, field| {
+                        let message = format!("Expected the size of `{}` in bytes", field.value);
+                        let size = parse_identifier(it, field.span).map_err(|err| Diagnostic::new(message.as_str(), err.span))?;
+                        match expr::parse_number(size.value) {
+                            Some(Ok(size)) if size > 0 => Ok(Field { name: field, size }),
+                            _ => Err(Diagnostic::new(message, size.span)),
+                        }
+                    })?;
+                    (Stmt::Struct(name.value.to_string(), fields), end)
+                },
+                "record" => {
+                    let kind = parse_identifier(it, directive.span)?;
+                    let label = parse_identifier(it, kind.span)?;
+                    let (values, end) = parse_block(it, label.span, |it, field| {
+                        let value = expr::parse_value(it, field.span)?;
+                        Ok((field, value))
+                    })?;
+                    let kind = Spanned::new(kind.value.to_string(), kind.span);
+                    (Stmt::Record { kind, label: label.value.to_string(), values }, end)
+                },
+                _ => return Err(Diagnostic::new(format!("Unknown directive `{}`", directive.value), directive.span)),
+            }
+        },
+        Token::Star => {
+            let name = parse_identifier(it, span)?;
+            if expr::parse_number(name.value).is_some() {
+                let message = format!("`{}` is a number and can't be a constant", name.value);
+                return Err(Diagnostic::new(message, name.span));
+            }
+            let value = expr::parse_value(it, span)?;
+            let end = value.span;
+            (Stmt::Constant(name.value.to_string(), value), end)
+        },
+        Token::IdentifierOrNumber(str) => {
+            use std::str::FromStr;
+
+            let op = Opcode::from_str(str)
+                .map_err(|_| Diagnostic::new(format!("Unknown instruction `{}`", str), span))?;
+            (Stmt::Operation(op), span)
+        },
+        Token::String(value) => {
+            (Stmt::String(value.clone()), span)
+        },
+        Token::Dollar => {
+            let value = expr::parse_value(it, span)?;
+            let end = value.span;
+            (Stmt::OriginRelative(value), end)
+        },
+        Token::Binary(data) => {
+            let str = parse_identifier(it, span)?;
+            (Stmt::Binary(str.value.to_string(), data.clone()), str.span)
+        },
+        Token::Comment(_) => unreachable!("Comments are handled by parse"),
+        Token::Include(_) | Token::IncludeBinary(_) => unreachable!("Includes are handled by include::load"),
+        Token::Caret => return Err(Diagnostic::new("Arguments can only be used in a macro body", span)),
+        Token::Percent | Token::OpenBrace | Token::CloseBrace => {
+            return Err(Diagnostic::new("Unexpected macro definition", span));
+        },
+        Token::OpenBracket => return Err(Diagnostic::new("Expressions can only follow `#`, `.`, `=`, `|`, `$` or `*name`", span)),
+        Token::CloseBracket | Token::OpenParen | Token::CloseParen | Token::Plus | Token::Minus
+            | Token::Slash | Token::ShiftLeft | Token::ShiftRight => {
+            return Err(Diagnostic::new("Operators can only be used between `[` and `]`", span));
+        },
+        Token::Character(_) => return Err(Diagnostic::new("Characters are numbers, use them like `#'a'` or `.'a'`", span)),
+        Token::UnterminatedString => return Err(Diagnostic::new("Unterminated string", span)),
+        Token::Invalid(message) => return Err(Diagnostic::new(message.as_str(), span)),
+        Token::Unknown(x) => return Err(Diagnostic::new(format!("Unexpected character `{}`", x), span)),
+    };
+
+    Ok(Spanned::new(stmt, span.to(end)))
+}
+
+/// Parse all statements, reporting every statement that could not be parsed.
+pub fn parse(tokens: &[Spanned<Token>]) -> Result<Ast, Diagnostics> {
     let mut ast = Vec::new();
+    let mut diagnostics = Vec::new();
     let mut it = tokens.iter().peekable();
 
     while let Some(token) = it.next() {
-        match token {
-            Token::At => {
-                let str = parse_identifier(&mut it);
-                ast.push(Stmt::LabelAbsolute(str.to_string()));
-            },
-            Token::Semicolon => {
-                if let Some(Token::Ampersand) = it.peek() {
-                    it.next(); // Eat Ampersand
-                    let str = parse_identifier(&mut it);
-                    ast.push(Stmt::LocalReferenceAbsolute(str.to_string()));
-                } else {
-                    let str = parse_identifier(&mut it);
-                    ast.push(Stmt::ReferenceAbsolute(str.to_string()));
-                }
-            },
-            Token::Colon => {
-                let str = parse_identifier(&mut it);
-                ast.push(Stmt::RawReferenceAbsolute(str.to_string()));
-            },
-            Token::Pound => {
-                let number = parse_number(&mut it);
-                ast.push(Stmt::LiteralWord(number));
-            },
-            Token::Pipe => {
-                let number = parse_number(&mut it);
-                ast.push(Stmt::OriginAbsolute(number));
-            },
-            Token::Ampersand => {
-                let str = parse_identifier(&mut it);
-                ast.push(Stmt::LocalLabelAbsolute(str.to_string()));
-            },
-            Token::Period => {
-                let number = parse_number(&mut it);
-                ast.push(Stmt::RawByte(number as _));
-            },
-            Token::Equal => {
-                let number = parse_number(&mut it);
-                ast.push(Stmt::RawWord(number));
-            },
-            Token::IdentifierOrNumber(str) => {
-                use std::str::FromStr;
+        if let Token::Comment(comment) = &token.value {
+            // A stack effect right after a label declares the effect of calling it
+            let label = matches!(ast.last(), Some(Spanned { value: Stmt::LabelAbsolute(_) | Stmt::LocalLabelAbsolute(_), .. }));
+            if let Some(effect) = StackEffect::parse(comment).filter(|_| label) {
+                ast.push(Spanned::new(Stmt::StackEffect(effect), token.span));
+            }
+            continue;
+        }
 
-                //TODO rework this error handling here
-                let op = Opcode::from_str(str).expect(&format!("Could not parse {}", str));
-                ast.push(Stmt::Operation(op));
-            },
-            Token::String(value) => {
-                ast.push(Stmt::String(value.to_string()));
-            },
-            Token::Dollar => {
-                let number = parse_number(&mut it);
-                ast.push(Stmt::OriginRelative(number));
-            },
-            Token::UnterminatedString => todo!(),
-            Token::Unknown(x) => todo!("{}", x),
+        match parse_stmt(token, &mut it) {
+            Ok(stmt) => ast.push(stmt),
+            Err(diagnostic) => diagnostics.push(diagnostic),
         }
     }
 
-    ast
+    if diagnostics.is_empty() {
+        Ok(ast)
+    } else {
+        Err(diagnostics)
+    }
 }
@@ -0,0 +1,69 @@
+/// Position in the source, both `line` and `column` start at 1.
+/// `length` is the number of characters covered on that line, used to underline errors.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Span {
+    /// Index of the source file.
+    pub file: u32,
+    pub line: u32,
+    pub column: u32,
+    pub length: u32,
+    /// Index of the macro expansion this came from, if any.
+    pub expansion: Option<u32>,
+}
+
+impl Default for Span {
+    fn default() -> Self {
+        Self {
+            file: 0,
+            line: 1,
+            column: 1,
+            length: 1,
+            expansion: None,
+        }
+    }
+}
+
+impl Span {
+    /// Span from the start of `self` to the end of `other`.
+    /// Spans on different lines or from different expansions only cover `self`.
+    pub fn to(self, other: Span) -> Span {
+        let same = self.file == other.file && self.line == other.line && self.expansion == other.expansion;
+        if !same || other.column < self.column {
+            return self;
+        }
+
+        Span {
+            length: other.column + other.length - self.column,
+            ..self
+        }
+    }
+}
+
+/// An assembled file, `Span::file` is an index into a list of these.
+#[derive(Debug, Clone)]
+pub struct Source {
+    pub name: String,
+    pub text: String,
+}
+
+/// A macro call, tokens from the macro body point back to it.
+#[derive(Debug, Clone)]
+pub struct Expansion {
+    pub name: String,
+    pub call: Span,
+}
+
+#[derive(Debug, Clone)]
+pub struct Spanned<T> {
+    pub value: T,
+    pub span: Span,
+}
+
+impl<T> Spanned<T> {
+    pub fn new(value: T, span: Span) -> Self {
+        Self {
+            value,
+            span,
+        }
+    }
+}
@@ -1,6 +1,7 @@
 use std::{iter::Peekable, str::Chars};
+use crate::span::{Span, Spanned};
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum Token {
     At,
     Semicolon,
@@ -11,31 +12,86 @@ pub enum Token {
     Dollar,
     Equal,
     Colon,
+    Percent,
+    Caret,
+    Question,
+    OpenBrace,
+    CloseBrace,
+    OpenBracket,
+    CloseBracket,
+    /// Only inside brackets, elsewhere parentheses are comments.
+    OpenParen,
+    CloseParen,
+    Plus,
+    Minus,
+    Star,
+    Slash,
+    ShiftLeft,
+    ShiftRight,
 
     IdentifierOrNumber(String),
-    String(String),
+    /// Bytes of a string with escapes replaced, `z"..."` strings end in a 0 byte.
+    String(Vec<u8>),
+    /// `'c'`, the codepoint of the character.
+    Character(u32),
+    /// Text between parentheses, used for stack effects.
+    Comment(String),
+    /// `~path` to include another file.
+    Include(String),
+    /// `!path` to include the bytes of a binary file.
+    IncludeBinary(String),
+    /// Bytes of a binary file, replaces `IncludeBinary` once the file is read.
+    Binary(Vec<u8>),
 
     UnterminatedString,
+    /// A string or character that can't be read, with the reason.
+    Invalid(String),
     Unknown(char),
 }
 
 struct Scanner<'a> {
-    it: Peekable<Chars<'a>>
+    it: Peekable<Chars<'a>>,
+    span: Span,
 }
 
 impl<'a> Scanner<'a> {
-    fn new(buf: &str) -> Scanner {
+    fn new(buf: &str, file: u32) -> Scanner<'_> {
         Scanner {
             it: buf.chars().peekable(),
+            span: Span {
+                file,
+                ..Span::default()
+            },
         }
     }
 
+    /// Position of the next character.
+    fn span(&self) -> Span {
+        self.span
+    }
+
     fn peek(&mut self) -> Option<&char> {
         self.it.peek()
     }
 
+    /// The character after the next one.
+    fn peek_second(&self) -> Option<char> {
+        let mut it = self.it.clone();
+        it.next();
+        it.next()
+    }
+
     fn next(&mut self) -> Option<char> {
-        self.it.next()
+        let ch = self.it.next()?;
+
+        if ch == '\n' {
+            self.span.line += 1;
+            self.span.column = 1;
+        } else {
+            self.span.column += 1;
+        }
+
+        Some(ch)
     }
 
     fn consume_while<F>(&mut self, x: F) -> Vec<char>
@@ -57,12 +113,18 @@ impl<'a> Scanner<'a> {
 
 struct Lexer<'a> {
     it: Scanner<'a>,
+    /// Number of open brackets, expressions use parentheses for grouping.
+    brackets: usize,
+    /// The previous token was `.`, so a number follows and isn't split into fields.
+    raw: bool,
 }
 
 impl<'a> Lexer<'a> {
-    fn new(buf: &str) -> Lexer {
+    fn new(buf: &str, file: u32) -> Lexer<'_> {
         Lexer {
-            it: Scanner::new(buf),
+            it: Scanner::new(buf, file),
+            brackets: 0,
+            raw: false,
         }
     }
 
@@ -77,12 +139,48 @@ impl<'a> Lexer<'a> {
             '=' => Some(Token::Equal),
             ':' => Some(Token::Colon),
             '.' => Some(Token::Period),
+            '%' => Some(Token::Percent),
+            '^' => Some(Token::Caret),
+            '?' => Some(Token::Question),
+            '{' => Some(Token::OpenBrace),
+            '}' => Some(Token::CloseBrace),
+            '[' => {
+                self.brackets += 1;
+                Some(Token::OpenBracket)
+            },
+            ']' => {
+                self.brackets = self.brackets.saturating_sub(1);
+                Some(Token::CloseBracket)
+            },
+            '(' if self.brackets > 0 => Some(Token::OpenParen),
+            ')' if self.brackets > 0 => Some(Token::CloseParen),
+            '+' => Some(Token::Plus),
+            '-' => Some(Token::Minus),
+            '*' => Some(Token::Star),
+            '/' => Some(Token::Slash),
+            '<' if self.it.peek() == Some(&'<') => {
+                self.it.next();
+                Some(Token::ShiftLeft)
+            },
+            '>' if self.it.peek() == Some(&'>') => {
+                self.it.next();
+                Some(Token::ShiftRight)
+            },
+            '~' => {
+                let path: String = self.it.consume_while(|ch| !ch.is_whitespace()).into_iter().collect();
+                Some(Token::Include(path))
+            },
+            '!' => {
+                let path: String = self.it.consume_while(|ch| !ch.is_whitespace()).into_iter().collect();
+                Some(Token::IncludeBinary(path))
+            },
             ' ' => None,
             '\n' => None,
             '\r' => None,
             '\t' => None,
             '(' => {
                 let mut level = 1;
+                let mut comment = String::new();
                 while let Some(ch) = self.it.next() {
                     match ch {
                         '(' => level += 1,
@@ -94,49 +192,138 @@ impl<'a> Lexer<'a> {
                         },
                         _ => (),
                     }
+                    comment.push(ch);
                 }
-                None
+                Some(Token::Comment(comment))
+            },
+            '"' => Some(self.string(false)),
+            '\'' => Some(self.character()),
+            'z' if self.it.peek() == Some(&'"') => {
+                self.it.next();
+                Some(self.string(true))
             },
-            '"' => {
-                let string: String = self.it.consume_while(|ch| ch != '"').into_iter().collect();
-                // Skip last "
-                match self.it.next() {
-                    None => Some(Token::UnterminatedString),
-                    _ => Some(Token::String(string)),
-                }
-            }
             x if x.is_ascii_alphanumeric() => self.identifier(Some(x)),
             c => Some(Token::Unknown(c)),
         }
     }
 
+    /// String after the opening `"`.
+    fn string(&mut self, nul: bool) -> Token {
+        let mut bytes = Vec::new();
+        let mut invalid = None;
+
+        loop {
+            match self.it.next() {
+                None => return Token::UnterminatedString,
+                Some('"') => break,
+                Some('\\') => match self.escape() {
+                    Ok(byte) => bytes.push(byte),
+                    Err(message) => invalid = invalid.or(Some(message)),
+                },
+                Some(ch) => bytes.extend_from_slice(ch.encode_utf8(&mut [0; 4]).as_bytes()),
+            }
+        }
+
+        if nul {
+            bytes.push(0);
+        }
+
+        match invalid {
+            Some(message) => Token::Invalid(message),
+            None => Token::String(bytes),
+        }
+    }
+
+    /// Character after the opening `'`.
+    fn character(&mut self) -> Token {
+        let value = match self.it.next() {
+            Some('\\') => self.escape().map(u32::from),
+            Some('\'') | None => return Token::Invalid("Expected a character after `'`".to_string()),
+            Some(ch) => Ok(ch as u32),
+        };
+
+        if self.it.peek() != Some(&'\'') {
+            // Skip the rest of the character, like `'ab'`
+            self.it.consume_while(|ch| ch != '\'' && !ch.is_whitespace());
+            if self.it.peek() == Some(&'\'') {
+                self.it.next();
+            }
+            return Token::Invalid("Expected `'` to end the character, it can only be one character".to_string());
+        }
+        self.it.next();
+
+        match value {
+            Ok(value) => Token::Character(value),
+            Err(message) => Token::Invalid(message),
+        }
+    }
+
+    /// Byte of the escape sequence after a `\`.
+    fn escape(&mut self) -> Result<u8, String> {
+        match self.it.next() {
+            Some('n') => Ok(b'\n'),
+            Some('r') => Ok(b'\r'),
+            Some('t') => Ok(b'\t'),
+            Some('0') => Ok(0),
+            Some('\\') => Ok(b'\\'),
+            Some('"') => Ok(b'"'),
+            Some('\'') => Ok(b'\''),
+            Some('x') => {
+                let mut digits = String::new();
+                while digits.len() < 2 && self.it.peek().is_some_and(char::is_ascii_hexdigit) {
+                    digits.extend(self.it.next());
+                }
+                match digits.len() {
+                    2 => Ok(u8::from_str_radix(&digits, 16).unwrap()),
+                    _ => Err(format!("Invalid escape `\\x{}`, expected 2 hexadecimal digits", digits)),
+                }
+            },
+            Some(ch) => Err(format!("Invalid escape `\\{}`", ch)),
+            None => Err("Expected an escape after `\\`".to_string()),
+        }
+    }
+
     fn identifier(&mut self, ch: Option<char>) -> Option<Token> {
         let mut identifier = String::new();
         if let Some(ch) = ch {
             identifier.push(ch);
         }
 
-        let rest: String = self
-            .it
-            .consume_while(|a| a.is_ascii_alphanumeric() || a == '_' || a == '-' || a == '/')
-            .into_iter()
-            .collect();
-        identifier.push_str(rest.as_str());
+        loop {
+            let rest: String = self
+                .it
+                .consume_while(|a| a.is_ascii_alphanumeric() || a == '_' || a == '-' || a == '/')
+                .into_iter()
+                .collect();
+            identifier.push_str(rest.as_str());
+
+            // Fields like `player.x`, but raw bytes like `.ff.ff` stay separate numbers
+            let field = self.it.peek_second().is_some_and(|ch| ch.is_ascii_alphanumeric() || ch == '_');
+            if self.raw || self.it.peek() != Some(&'.') || !field {
+                break;
+            }
+            self.it.next();
+            identifier.push('.');
+        }
 
         Some(Token::IdentifierOrNumber(identifier))
     }
 
-    fn tokenize(&mut self) -> Vec<Token> {
+    fn tokenize(&mut self) -> Vec<Spanned<Token>> {
         let mut tokens = Vec::new();
 
         loop {
+            let span = self.it.span();
             let ch = match self.it.next() {
                 None => break,
                 Some(c) => c,
             };
 
             if let Some(token) = self.match_token(ch) {
-                tokens.push(token);
+                self.raw = matches!(token, Token::Period);
+                let end = self.it.span();
+                let length = if end.line == span.line { end.column - span.column } else { 1 };
+                tokens.push(Spanned::new(token, Span { length, ..span }));
             }
         }
 
@@ -144,7 +331,8 @@ impl<'a> Lexer<'a> {
     }
 }
 
-pub fn tokenize(buf: &str) -> Vec<Token> {
-    let mut lexer = Lexer::new(buf);
+/// Tokenize the source of `file`, an index into the list of sources.
+pub fn tokenize(buf: &str, file: u32) -> Vec<Spanned<Token>> {
+    let mut lexer = Lexer::new(buf, file);
     lexer.tokenize()
 }
@@ -0,0 +1,483 @@
+use std::collections::{HashMap, HashSet};
+use fox_bytecode::Opcode;
+use fox_bytecode::disasm::Instruction;
+use fox_bytecode::memory::RESET_VECTOR;
+use crate::asm::{Assembler, Declaration};
+use crate::diagnostic::{Diagnostic, Diagnostics};
+use crate::parser::StackEffect;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Value {
+    Known(u32),
+    Unknown,
+}
+
+/// Data stack depth and the values on top of it that are known.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct State {
+    /// Relative to the start, negative once a routine reads its inputs.
+    depth: i32,
+    /// Values on top of the stack, anything below them is unknown.
+    values: Vec<Value>,
+    /// Values pushed with `RPUSH` that have not been popped.
+    rdepth: i32,
+}
+
+impl State {
+    fn new() -> Self {
+        Self {
+            depth: 0,
+            values: Vec::new(),
+            rdepth: 0,
+        }
+    }
+
+    fn push(&mut self, value: Value) {
+        self.depth += 1;
+        self.values.push(value);
+    }
+
+    /// Pop `count` values, with the top of the stack last.
+    fn pop(&mut self, count: usize, mode: &Mode, lowest: &mut i32) -> Result<Vec<Value>, String> {
+        let depth = self.depth - count as i32;
+
+        match mode {
+            Mode::Entry if depth < 0 => {
+                return Err(format!("Stack underflow, needs {} but only {} on the stack", count, self.depth));
+            },
+            Mode::Routine(Some(declaration)) if depth < -(declaration.effect.inputs as i32) => {
+                return Err(format!("Reads below the declared inputs of `{}` {}", declaration.label, declaration.effect));
+            },
+            _ => (),
+        }
+
+        *lowest = (*lowest).min(depth);
+        self.depth = depth;
+
+        let mut values = vec![Value::Unknown; count];
+        for value in values.iter_mut().rev() {
+            *value = self.values.pop().unwrap_or(Value::Unknown);
+        }
+
+        Ok(values)
+    }
+
+    fn peek(&self, n: usize) -> Value {
+        match self.values.len().checked_sub(n + 1) {
+            Some(index) => self.values[index],
+            None => Value::Unknown,
+        }
+    }
+
+    /// Same depths, keeping only values that are known in both.
+    fn merge(&self, other: &State) -> State {
+        let length = self.values.len().min(other.values.len());
+        let ours = &self.values[self.values.len() - length..];
+        let theirs = &other.values[other.values.len() - length..];
+
+        let values = ours
+            .iter()
+            .zip(theirs)
+            .map(|(a, b)| if a == b { *a } else { Value::Unknown })
+            .collect();
+
+        State {
+            values,
+            ..self.clone()
+        }
+    }
+}
+
+enum Mode<'a> {
+    /// `on-reset` or a vector, these start with empty stacks.
+    Entry,
+    /// A label that is called, the stacks start with the caller's values.
+    Routine(Option<&'a Declaration>),
+}
+
+/// What to do after an instruction.
+enum Flow {
+    Next,
+    Jump(u32),
+    End,
+}
+
+/// Check the stack usage of the program by following every path from `on-reset`,
+/// labels stored into `*-vector` ports, and labels with a declared stack effect.
+/// Problems are reported as warnings, since not every path can be followed.
+pub fn verify(asm: &Assembler) -> Diagnostics {
+    let mut verifier = Verifier {
+        asm,
+        vectors: asm
+            .labels()
+            .iter()
+            .filter(|(label, _)| label.ends_with("-vector"))
+            .map(|(_, addr)| *addr)
+            .collect(),
+        declarations: asm
+            .declarations()
+            .iter()
+            .map(|declaration| (declaration.addr, declaration))
+            .collect(),
+        entries: Vec::new(),
+        routines: HashMap::new(),
+        diagnostics: Vec::new(),
+    };
+
+    if !asm.data().is_empty() {
+        verifier.entries.push((RESET_VECTOR, RESET_VECTOR));
+    }
+
+    let mut index = 0;
+    while let Some((addr, from)) = verifier.entries.get(index).copied() {
+        verifier.walk(addr, from, Mode::Entry);
+        index += 1;
+    }
+
+    // Declared effects are checked even if nothing calls them
+    for declaration in asm.declarations() {
+        verifier.routine(declaration.addr);
+    }
+
+    let mut diagnostics: Diagnostics = Vec::new();
+    for diagnostic in verifier.diagnostics {
+        if !diagnostics.contains(&diagnostic) {
+            diagnostics.push(diagnostic);
+        }
+    }
+
+    diagnostics
+}
+
+struct Verifier<'a> {
+    asm: &'a Assembler,
+    /// Addresses of `*-vector` ports.
+    vectors: HashSet<u32>,
+    declarations: HashMap<u32, &'a Declaration>,
+    /// Entry points and the instruction that set them up.
+    entries: Vec<(u32, u32)>,
+    /// Effect of each called routine, `None` if it doesn't return or is still being checked.
+    routines: HashMap<u32, Option<StackEffect>>,
+    diagnostics: Diagnostics,
+}
+
+impl<'a> Verifier<'a> {
+    fn warn(&mut self, addr: u32, message: String) {
+        let span = self.asm.span_at(addr).unwrap_or_default();
+        self.diagnostics.push(Diagnostic::warning(message, span));
+    }
+
+    fn decode(&self, addr: u32) -> Option<Instruction> {
+        let offset = addr.checked_sub(RESET_VECTOR)? as usize;
+        Instruction::decode(self.asm.data().get(offset..)?)
+    }
+
+    /// Effect of calling `addr`, checking the routine the first time it is called.
+    fn routine(&mut self, addr: u32) -> Option<StackEffect> {
+        let declaration = self.declarations.get(&addr).copied();
+
+        let effect = match self.routines.get(&addr) {
+            Some(effect) => *effect,
+            None => {
+                // Recursive calls without a declaration can't be followed
+                self.routines.insert(addr, None);
+
+                let effect = self.walk(addr, addr, Mode::Routine(declaration));
+                self.routines.insert(addr, effect);
+                effect
+            },
+        };
+
+        declaration.map(|declaration| declaration.effect).or(effect)
+    }
+
+    /// Follow every path from `start`, returning the effect if it is a routine that returns.
+    fn walk(&mut self, start: u32, from: u32, mode: Mode) -> Option<StackEffect> {
+        let mut visited: HashMap<u32, State> = HashMap::new();
+        let mut paths = vec![(start, from, State::new())];
+        let mut lowest = 0;
+        let mut returns: Option<i32> = None;
+
+        while let Some((mut addr, mut from, mut state)) = paths.pop() {
+            loop {
+                match visited.get(&addr) {
+                    Some(old) if old.depth != state.depth || old.rdepth != state.rdepth => {
+                        let message = format!("Branches merge with different stack depths ({} and {})", old.depth, state.depth);
+                        self.warn(from, message);
+                        break;
+                    },
+                    Some(old) => {
+                        let merged = old.merge(&state);
+                        if merged == *old {
+                            break;
+                        }
+                        state = merged;
+                    },
+                    None => (),
+                }
+                visited.insert(addr, state.clone());
+
+                let instruction = match self.decode(addr) {
+                    Some(instruction) => instruction,
+                    None => {
+                        self.warn(from, format!("Execution continues at 0x{:08x}, which is not an instruction", addr));
+                        break;
+                    },
+                };
+
+                let flow = self.execute(addr, instruction, &mut state, &mode, &mut lowest, &mut paths, &mut returns);
+                match flow {
+                    Ok(Flow::Next) => {
+                        from = addr;
+                        addr += instruction.size() as u32;
+                    },
+                    Ok(Flow::Jump(target)) => {
+                        from = addr;
+                        addr = target;
+                    },
+                    Ok(Flow::End) => break,
+                    Err(message) => {
+                        self.warn(addr, message);
+                        break;
+                    },
+                }
+            }
+        }
+
+        let returns = returns?;
+        let inputs = -lowest;
+        let effect = StackEffect {
+            inputs: inputs as usize,
+            outputs: (returns + inputs) as usize,
+        };
+
+        if let Mode::Routine(Some(declaration)) = mode {
+            let declared = declaration.effect.outputs as i32 - declaration.effect.inputs as i32;
+            if declared != returns {
+                let message = format!("`{}` is declared {} but has an effect of {}", declaration.label, declaration.effect, effect);
+                self.diagnostics.push(Diagnostic::warning(message, declaration.span));
+            }
+        }
+
+        Some(effect)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn execute(
+        &mut self,
+        addr: u32,
+        instruction: Instruction,
+        state: &mut State,
+        mode: &Mode,
+        lowest: &mut i32,
+        paths: &mut Vec<(u32, u32, State)>,
+        returns: &mut Option<i32>,
+    ) -> Result<Flow, String> {
+        let info = instruction.opcode.info();
+
+        match instruction.opcode {
+            Opcode::Halt => return Ok(Flow::End),
+            Opcode::LitW | Opcode::LitB => {
+                state.push(Value::Known(instruction.operand.unwrap_or(0)));
+            },
+            Opcode::Dup => {
+                let [a] = pop(state, mode, lowest)?;
+                state.push(a);
+                state.push(a);
+            },
+            Opcode::Swap => {
+                let [a, b] = pop(state, mode, lowest)?;
+                state.push(b);
+                state.push(a);
+            },
+            Opcode::Over => {
+                let [a, b] = pop(state, mode, lowest)?;
+                state.push(a);
+                state.push(b);
+                state.push(a);
+            },
+            Opcode::Rot => {
+                let [a, b, c] = pop(state, mode, lowest)?;
+                state.push(b);
+                state.push(c);
+                state.push(a);
+            },
+            Opcode::Pick => {
+                let [n] = pop(state, mode, lowest)?;
+                let value = match n {
+                    // The stack only holds 256 values
+                    Value::Known(n) if n < 256 => {
+                        // Make sure the picked value exists, then put everything back
+                        let values = state.pop(n as usize + 1, mode, lowest)?;
+                        for value in values {
+                            state.push(value);
+                        }
+                        state.peek(n as usize)
+                    },
+                    _ => Value::Unknown,
+                };
+                state.push(value);
+            },
+            Opcode::Sw => {
+                let [value, port] = pop(state, mode, lowest)?;
+                if let (Value::Known(value), Value::Known(port)) = (value, port) {
+                    if self.vectors.contains(&port) && !self.entries.iter().any(|(entry, _)| *entry == value) {
+                        self.entries.push((value, addr));
+                    }
+                }
+            },
+            Opcode::Jmp => {
+                return match pop(state, mode, lowest)? {
+                    [Value::Known(target)] => Ok(Flow::Jump(target)),
+                    _ => Ok(Flow::End),
+                };
+            },
+            Opcode::Jz | Opcode::Jnz => {
+                if let [_, Value::Known(target)] = pop(state, mode, lowest)? {
+                    paths.push((target, addr, state.clone()));
+                }
+            },
+            Opcode::Call => {
+                let [target] = pop(state, mode, lowest)?;
+                let effect = match target {
+                    Value::Known(target) => self.routine(target),
+                    Value::Unknown => None,
+                };
+
+                // Unknown routines and ones that don't return end the path
+                let effect = match effect {
+                    Some(effect) => effect,
+                    None => return Ok(Flow::End),
+                };
+
+                state.pop(effect.inputs, mode, lowest)?;
+                for _ in 0..effect.outputs {
+                    state.push(Value::Unknown);
+                }
+            },
+            Opcode::Ret => {
+                if state.rdepth > 0 {
+                    return Err(format!("RET while the return stack still holds {} from RPUSH", state.rdepth));
+                }
+
+                match mode {
+                    Mode::Entry => return Err("RET without a matching CALL".to_string()),
+                    Mode::Routine(_) => {
+                        match *returns {
+                            Some(depth) if depth != state.depth => {
+                                return Err(format!("Returns with a stack depth of {}, but also returns with {}", state.depth, depth));
+                            },
+                            _ => *returns = Some(state.depth),
+                        }
+                        return Ok(Flow::End);
+                    },
+                }
+            },
+            Opcode::Rpush => {
+                state.pop(1, mode, lowest)?;
+                state.rdepth += 1;
+            },
+            Opcode::Rpop | Opcode::Rpeek | Opcode::Rdrop => {
+                if state.rdepth == 0 {
+                    return match mode {
+                        Mode::Entry => Err("Return stack underflow".to_string()),
+                        Mode::Routine(_) => Err(format!("{} of the return address, there is no matching RPUSH", info.mnemonic)),
+                    };
+                }
+
+                state.rdepth -= info.return_inputs as i32;
+                state.rdepth += info.return_outputs as i32;
+                for _ in 0..info.outputs {
+                    state.push(Value::Unknown);
+                }
+            },
+            _ => {
+                state.pop(info.inputs, mode, lowest)?;
+                for _ in 0..info.outputs {
+                    state.push(Value::Unknown);
+                }
+            },
+        }
+
+        Ok(Flow::Next)
+    }
+}
+
+/// Pop a fixed number of values.
+fn pop<const N: usize>(state: &mut State, mode: &Mode, lowest: &mut i32) -> Result<[Value; N], String> {
+    let values = state.pop(N, mode, lowest)?;
+    Ok(values.try_into().unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{assemble, Options, Severity};
+
+    fn warnings(source: &str) -> Vec<String> {
+        let output = match assemble(source, &Options::new()) {
+            Ok(output) => output,
+            Err(diagnostics) => panic!("{}", diagnostics),
+        };
+        assert!(output.warnings.iter().all(|warning| warning.severity == Severity::Warning));
+
+        output.warnings.iter().map(|warning| warning.message.clone()).collect()
+    }
+
+    #[test]
+    fn balanced_program() {
+        assert!(warnings("|0100 @on-reset #01 #02 ADD ;&done JZ &done HALT").is_empty());
+    }
+
+    #[test]
+    fn underflow() {
+        assert_eq!(warnings("|0100 @on-reset #01 ADD HALT"), ["Stack underflow, needs 2 but only 1 on the stack"]);
+        assert_eq!(warnings("|0100 @on-reset DROP HALT"), ["Stack underflow, needs 1 but only 0 on the stack"]);
+    }
+
+    #[test]
+    fn underflow_in_vector() {
+        let source = "
+            |10000000 @console-vector
+            |0100 @on-reset ;on-console ;console-vector SW HALT
+            @on-console SWAP HALT
+        ";
+        assert_eq!(warnings(source), ["Stack underflow, needs 2 but only 0 on the stack"]);
+    }
+
+    #[test]
+    fn branches_merge_with_different_depths() {
+        let source = "|0100 @on-reset #00 ;&skip JZ #02 &skip HALT";
+        assert_eq!(warnings(source), ["Branches merge with different stack depths (1 and 0)"]);
+    }
+
+    #[test]
+    fn declared_effect() {
+        let source = "
+            |0100 @on-reset #01 ;double CALL DROP HALT
+            @double ( a -- b ) DUP ADD ADD RET
+        ";
+        assert_eq!(warnings(source), ["Reads below the declared inputs of `double` ( 1 -- 1 )"]);
+
+        let source = "
+            |0100 @on-reset #01 ;double CALL DROP HALT
+            @double ( a -- b ) DUP DUP ADD RET
+        ";
+        assert_eq!(warnings(source), ["`double` is declared ( 1 -- 1 ) but has an effect of ( 1 -- 2 )"]);
+    }
+
+    #[test]
+    fn unbalanced_return_stack() {
+        assert_eq!(warnings("|0100 @on-reset RET"), ["RET without a matching CALL"]);
+        assert_eq!(warnings("|0100 @on-reset ;routine CALL HALT @routine #01 RPUSH RET"), ["RET while the return stack still holds 1 from RPUSH"]);
+    }
+
+    #[test]
+    fn skipped_when_disabled() {
+        let options = Options {
+            verify: false,
+            ..Options::new()
+        };
+        let output = assemble("|0100 @on-reset DROP HALT", &options).unwrap();
+        assert!(output.warnings.is_empty());
+    }
+}
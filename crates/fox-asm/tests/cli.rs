@@ -0,0 +1,103 @@
+use fox_bytecode::*;
+use std::path::PathBuf;
+use std::process::{Command, Output};
+
+/// Write `source` to a file of its own, named after the test.
+fn source_file(name: &str, source: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("fox-asm-cli-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join(format!("{}.fox", name));
+    std::fs::write(&path, source).unwrap();
+    path
+}
+
+/// Assemble `source` to stdout with `args`.
+fn fox_asm(name: &str, source: &str, args: &[&str]) -> Output {
+    let path = source_file(name, source);
+    Command::new(env!("CARGO_BIN_EXE_fox-asm"))
+        .args(args)
+        .args(["-o", "-"])
+        .arg(&path)
+        .output()
+        .unwrap()
+}
+
+fn stderr(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stderr).into_owned()
+}
+
+const UNDERFLOW: &str = "|0100 @on-reset #01 ADD HALT";
+const LEVEL: &str = "|0100 #level HALT";
+
+#[test]
+fn stack_warnings_are_printed() {
+    let output = fox_asm("stack-warnings-are-printed", UNDERFLOW, &["-q"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stderr(&output).contains("Stack underflow"));
+    assert!(!output.stdout.is_empty());
+}
+
+#[test]
+fn stack_warnings_fail_as_errors() {
+    let output = fox_asm("stack-warnings-fail-as-errors", UNDERFLOW, &["-q", "-W", "error"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(stderr(&output).contains("Stack underflow"));
+    assert!(stderr(&output).contains("1 warning treated as errors"));
+    assert!(output.stdout.is_empty());
+}
+
+#[test]
+fn stack_warnings_can_be_turned_off() {
+    let output = fox_asm("stack-warnings-can-be-turned-off", UNDERFLOW, &["-q", "-W", "off"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(!stderr(&output).contains("Stack underflow"));
+}
+
+#[test]
+fn other_warnings_fail_as_errors() {
+    let output = fox_asm("other-warnings-fail-as-errors", "|0100 HALT =01 |0101 .02", &["-q", "-W", "error"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(stderr(&output).contains("Overwrites 00000101..00000102"));
+    assert!(stderr(&output).contains("1 warning treated as errors"));
+}
+
+#[test]
+fn defines() {
+    let output = fox_asm("defines-value", LEVEL, &["-D", "level=2a"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(output.stdout, [OP_LITB, 0x2a, OP_HALT]);
+
+    let output = fox_asm("defines-decimal", LEVEL, &["-D", "level=0n42"]);
+    assert_eq!(output.stdout, [OP_LITB, 0x2a, OP_HALT]);
+
+    let output = fox_asm("defines-one", LEVEL, &["-D", "level"]);
+    assert_eq!(output.stdout, [OP_LITB, 0x01, OP_HALT]);
+}
+
+#[test]
+fn invalid_defines() {
+    for (define, message) in [
+        ("=3", "Can't define ``, it's not a name"),
+        ("12=3", "Can't define `12`, it's not a name"),
+        ("level=high", "Can't define `level` as `high`, it's not a number"),
+        ("level=0n4a", "Invalid decimal number `0n4a`"),
+    ] {
+        let output = fox_asm("invalid-defines", LEVEL, &["-D", define]);
+        assert_eq!(output.status.code(), Some(2), "{}", define);
+        assert!(stderr(&output).starts_with(message), "{}", stderr(&output));
+        assert!(output.stdout.is_empty());
+    }
+
+    let output = fox_asm("missing-define", LEVEL, &[]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(output.stdout.is_empty());
+}
+
+#[test]
+fn quiet_only_prints_warnings() {
+    let output = fox_asm("quiet", LEVEL, &["-D", "level"]);
+    assert!(stderr(&output).contains("Assembled in 3 bytes"));
+
+    let output = fox_asm("quiet", LEVEL, &["-q", "-D", "level"]);
+    assert_eq!(stderr(&output), "");
+}
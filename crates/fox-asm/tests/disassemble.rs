@@ -0,0 +1,54 @@
+use fox_asm::Options;
+use fox_bytecode::disasm::disassemble;
+
+/// Assemble `source`, disassemble it, and check the disassembly assembles to the same bytes.
+fn round_trip(source: &str, options: &Options) -> fox_asm::Output {
+    let name = options.path.display();
+    let output = fox_asm::assemble(source, options).unwrap_or_else(|diagnostics| panic!("{}: {}", name, diagnostics));
+    let disassembly = disassemble(&output.data, &output.symbols);
+
+    let again = fox_asm::assemble(&disassembly, &Options::new())
+        .unwrap_or_else(|diagnostics| panic!("{}: {}\n{}", name, diagnostics, disassembly));
+    assert_eq!(again.data.len(), output.data.len(), "{}", name);
+    assert_eq!(again.data, output.data, "{}", name);
+
+    output
+}
+
+#[test]
+fn macro_local_labels_round_trip() {
+    let output = round_trip(r#"
+        ~console.fox
+        |0100
+        %print addr {
+            ;^addr
+            &loop DUP LB DUP ;&done JZ ;console-write SW INC ;&loop JMP
+            &done DROP DROP
+        }
+
+        @on-reset
+        print hello
+        print hello
+        HALT
+
+        @hello z"hi"
+    "#, &Options::new());
+
+    let labels: Vec<&str> = output.symbols.iter().map(|(_, label)| label).collect();
+    assert!(labels.contains(&"on-reset/loop-0"), "{:?}", labels);
+    assert!(labels.contains(&"on-reset/done-1"), "{:?}", labels);
+}
+
+#[test]
+fn examples_round_trip() {
+    let examples = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("../../examples");
+
+    for name in ["echo.fox", "gui.fox", "switch.fox", "test.fox"] {
+        let path = examples.join(name);
+        let source = std::fs::read_to_string(&path).unwrap();
+        round_trip(&source, &Options {
+            path,
+            ..Options::new()
+        });
+    }
+}
@@ -0,0 +1,42 @@
+use fox_asm::Options;
+use fox_bytecode::*;
+
+fn options() -> Options {
+    Options {
+        verify: false,
+        ..Options::new()
+    }
+}
+
+fn assemble(source: &str) -> Vec<u8> {
+    match fox_asm::assemble(source, &options()) {
+        Ok(output) => output.data,
+        Err(diagnostics) => panic!("{}", diagnostics),
+    }
+}
+
+fn errors(source: &str) -> Vec<String> {
+    let diagnostics = fox_asm::assemble(source, &options()).unwrap_err();
+    diagnostics.iter().map(|diagnostic| diagnostic.message.clone()).collect()
+}
+
+#[test]
+fn operators_and_precedence() {
+    assert_eq!(assemble("|0100 #[ 1 + 2 * 3 ] #[ ( 1 + 2 ) * 3 ] #[ 1 << 4 | 1 ] #[ ff >> 4 & 3 ]"), [
+        OP_LITB, 0x07, OP_LITB, 0x09, OP_LITB, 0x11, OP_LITB, 0x03,
+    ]);
+    assert_eq!(assemble("|0100 =[ 0 - 1 ] =[ -2 ] =[ 1 << 1f ]"), [
+        0xff, 0xff, 0xff, 0xff, 0xfe, 0xff, 0xff, 0xff, 0x00, 0x00, 0x00, 0x80,
+    ]);
+}
+
+#[test]
+fn division_by_zero() {
+    assert_eq!(errors("|0100 #[ 1 / 0 ]"), ["Division by zero"]);
+}
+
+#[test]
+fn shift_overflows() {
+    assert_eq!(errors("|0100 #[ 1 << 20 ]"), ["Shifting by 0x20 overflows, shifts have to be less than 0x20"]);
+    assert_eq!(errors("|0100 #[ 1 >> 0n40 ]"), ["Shifting by 0x28 overflows, shifts have to be less than 0x20"]);
+}
@@ -0,0 +1,71 @@
+use fox_asm::{Options, Object, Severity};
+use fox_bytecode::*;
+
+fn options() -> Options {
+    Options {
+        verify: false,
+        ..Options::new()
+    }
+}
+
+#[test]
+fn assemble_source() {
+    let output = fox_asm::assemble("
+        |0100
+        @on-reset #10 ;data LW HALT
+        @data =DEADBEEF
+    ", &options()).unwrap();
+
+    assert_eq!(output.data, [OP_LITB, 0x10, OP_LITW, 0x09, 0x01, 0x00, 0x00, OP_LW, OP_HALT, 0xef, 0xbe, 0xad, 0xde]);
+    assert_eq!(output.symbols.lookup(0x109), Some(("data", 0)));
+    assert_eq!(output.short_literals, 1);
+    assert!(output.warnings.is_empty());
+    assert!(output.listing.is_none());
+}
+
+#[test]
+fn errors_are_diagnostics() {
+    let diagnostics = fox_asm::assemble("|0100 ;missing FOO", &options()).unwrap_err();
+
+    let messages: Vec<&str> = diagnostics.iter().map(|diagnostic| diagnostic.message.as_str()).collect();
+    assert_eq!(messages, ["Unknown instruction `FOO`"]);
+    assert!(diagnostics.iter().all(|diagnostic| diagnostic.severity == Severity::Error));
+    assert_eq!(diagnostics.iter().next().unwrap().span.column, 16);
+    assert!(diagnostics.to_string().contains("main.fox:1:16"));
+}
+
+#[test]
+fn warnings_are_returned_with_the_output() {
+    let output = fox_asm::assemble("|0100 =01 |0100 .02", &options()).unwrap();
+
+    assert_eq!(output.data, [0x02, 0x00, 0x00, 0x00]);
+    assert_eq!(output.warnings.len(), 1);
+    assert_eq!(output.warnings.iter().next().unwrap().severity, Severity::Warning);
+}
+
+#[test]
+fn defines_and_listing() {
+    let options = Options {
+        defines: vec![("size".to_string(), 0x20)],
+        listing: true,
+        ..options()
+    };
+    let output = fox_asm::assemble("|0100 #[ size * 2 ] HALT", &options).unwrap();
+
+    assert_eq!(output.data, [OP_LITB, 0x40, OP_HALT]);
+    assert!(output.listing.unwrap().contains("00000100  16 40 00"));
+}
+
+#[test]
+fn compile_and_link() {
+    let (main, warnings) = fox_asm::compile("?export main @main ;value LW HALT", &options()).unwrap();
+    assert!(warnings.is_empty());
+    let (value, _) = fox_asm::compile("?export value @value =12345678", &options()).unwrap();
+
+    // Objects survive being written out and read back
+    let value = Object::parse(&value.to_string()).unwrap();
+    let output = fox_asm::link(&[main, value]).unwrap();
+
+    assert_eq!(output.data, [OP_LITW, 0x07, 0x01, 0x00, 0x00, OP_LW, OP_HALT, 0x78, 0x56, 0x34, 0x12]);
+    assert_eq!(output.symbols.lookup(0x107), Some(("value", 0)));
+}
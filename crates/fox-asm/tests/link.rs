@@ -0,0 +1,98 @@
+use fox_asm::{Object, Options};
+use fox_bytecode::*;
+
+fn options() -> Options {
+    Options {
+        verify: false,
+        ..Options::new()
+    }
+}
+
+fn compile(source: &str) -> Object {
+    match fox_asm::compile(source, &options()) {
+        Ok((object, _)) => object,
+        Err(diagnostics) => panic!("{}", diagnostics),
+    }
+}
+
+fn link_errors(objects: &[Object]) -> Vec<String> {
+    let diagnostics = fox_asm::link(objects).unwrap_err();
+    diagnostics.iter().map(|diagnostic| diagnostic.message.clone()).collect()
+}
+
+#[test]
+fn relocations_across_objects() {
+    let main = compile("?export main ?export count @main ;print CALL #[ message + 2 ] HALT @count =00000003");
+    let lib = compile("
+        ?export print ?export message
+        @print ;count LW ;&done JMP &done RET
+        @message \"hi\" .00
+    ");
+
+    let output = fox_asm::link(&[main, lib]).unwrap();
+
+    // main at 0100, lib after it at 0110
+    assert_eq!(output.data[..0x10], [
+        OP_LITW, 0x10, 0x01, 0x00, 0x00, OP_CALL,
+        OP_LITW, 0x1f, 0x01, 0x00, 0x00, OP_HALT,
+        0x03, 0x00, 0x00, 0x00,
+    ]);
+    assert_eq!(output.data[0x10..], [
+        OP_LITW, 0x0c, 0x01, 0x00, 0x00, OP_LW,
+        OP_LITW, 0x1c, 0x01, 0x00, 0x00, OP_JMP,
+        OP_RET, b'h', b'i', 0x00,
+    ]);
+    assert_eq!(output.symbols.lookup(0x11d), Some(("message", 0)));
+    assert_eq!(output.symbols.lookup(0x11c), Some(("print/done", 0)));
+}
+
+#[test]
+fn duplicate_exports() {
+    let a = compile("?export value @value =00000001");
+    let b = compile("?export value @value =00000002");
+
+    assert_eq!(link_errors(&[a, b]), ["`value` is already exported from main.fox:1:1"]);
+}
+
+#[test]
+fn unresolved_imports() {
+    let main = compile("@main ;missing LW ;private LW HALT");
+    let lib = compile("@private =00000001");
+
+    assert_eq!(link_errors(&[main, lib]), [
+        "Unknown label `missing`",
+        "Label `private` is in another object, but isn't exported",
+    ]);
+}
+
+#[test]
+fn object_round_trip() {
+    let object = compile("
+        *size 4
+        ?export main
+        @main ;&loop JMP &loop #[ size * 2 ] ;main CALL ;other LW HALT
+        |10000000 @port
+    ");
+    let text = object.to_string();
+
+    let parsed = Object::parse(&text).unwrap();
+    assert_eq!(parsed.to_string(), text);
+    assert_eq!(parsed.data, object.data);
+
+    let other = compile("?export other @other =12345678");
+    let linked = fox_asm::link(&[object, other.clone()]).unwrap();
+    let relinked = fox_asm::link(&[parsed, other]).unwrap();
+    assert_eq!(relinked.data, linked.data);
+}
+
+#[test]
+fn invalid_objects() {
+    let error = |text: &str| Object::parse(text).unwrap_err().line;
+
+    assert_eq!(error("fox-object 2\nsection code 00000000\n"), 1);
+    assert_eq!(error("not an object\n"), 1);
+    assert_eq!(error("fox-object 1\nsection data 00000001\ndata 00\n"), 2);
+    assert_eq!(error("fox-object 1\ndata 00\n"), 2);
+    assert_eq!(error("fox-object 1\nsection code 00000002\ndata 00\n"), 2);
+    assert_eq!(error("fox-object 1\nsection code 00000001\ndata 00\nsection code 00000000\n"), 4);
+}
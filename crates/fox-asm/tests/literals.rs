@@ -0,0 +1,70 @@
+use fox_asm::Options;
+use fox_bytecode::*;
+
+fn options() -> Options {
+    Options {
+        verify: false,
+        ..Options::new()
+    }
+}
+
+fn assemble(source: &str) -> Vec<u8> {
+    match fox_asm::assemble(source, &options()) {
+        Ok(output) => output.data,
+        Err(diagnostics) => panic!("{}", diagnostics),
+    }
+}
+
+fn errors(source: &str) -> Vec<String> {
+    let diagnostics = fox_asm::assemble(source, &options()).unwrap_err();
+    diagnostics.iter().map(|diagnostic| diagnostic.message.clone()).collect()
+}
+
+#[test]
+fn number_bases() {
+    assert_eq!(assemble("|0100 .ff .0xff .0n255 .0y11111111"), [0xff, 0xff, 0xff, 0xff]);
+    assert_eq!(assemble("|0100 =0n4294967295 =0y101"), [0xff, 0xff, 0xff, 0xff, 0x05, 0x00, 0x00, 0x00]);
+}
+
+#[test]
+fn invalid_numbers() {
+    assert_eq!(errors("|0100 .0n12a"), ["Invalid decimal number `0n12a`"]);
+    assert_eq!(errors("|0100 .0y102"), ["Invalid binary number `0y102`"]);
+    assert_eq!(errors("|0100 =0n4294967296"), ["Invalid decimal number `0n4294967296`"]);
+}
+
+#[test]
+fn characters() {
+    assert_eq!(assemble("|0100 #'a' .'z' =' '"), [OP_LITB, b'a', b'z', b' ', 0x00, 0x00, 0x00]);
+    assert_eq!(assemble("|0100 .'\\n' .'\\'' .'\\x7f'"), [b'\n', b'\'', 0x7f]);
+    assert_eq!(assemble("|0100 ='€'"), 0x20acu32.to_le_bytes());
+}
+
+#[test]
+fn invalid_characters() {
+    assert_eq!(errors("|0100 .''"), ["Expected a character after `'`"]);
+    assert_eq!(errors("|0100 .'ab'"), ["Expected `'` to end the character, it can only be one character"]);
+    assert_eq!(errors("|0100 .'€'"), ["Byte out of range `20ac`"]);
+}
+
+#[test]
+fn string_escapes() {
+    assert_eq!(assemble(r#"|0100 "\n\r\t\0\\\"\'\x41\x7e""#), b"\n\r\t\0\\\"'A~");
+    assert_eq!(assemble(r#"|0100 z"a\x00b""#), b"a\0b\0");
+    assert_eq!(assemble("|0100 \"é\""), "é".as_bytes());
+}
+
+#[test]
+fn invalid_escapes() {
+    assert_eq!(errors(r#"|0100 "a\qb""#), ["Invalid escape `\\q`"]);
+    assert_eq!(errors(r#"|0100 "\x4""#), ["Invalid escape `\\x4`, expected 2 hexadecimal digits"]);
+    assert_eq!(errors(r#"|0100 "\xg0""#), ["Invalid escape `\\x`, expected 2 hexadecimal digits"]);
+    assert_eq!(errors(r#"|0100 .'\q'"#), ["Invalid escape `\\q`"]);
+}
+
+#[test]
+fn raw_local_references() {
+    assert_eq!(assemble("|0100 @table :&a :&b &a .01 &b .02"), [
+        0x08, 0x01, 0x00, 0x00, 0x09, 0x01, 0x00, 0x00, 0x01, 0x02,
+    ]);
+}
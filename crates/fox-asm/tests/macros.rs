@@ -0,0 +1,64 @@
+use fox_asm::Options;
+use fox_bytecode::*;
+
+fn assemble(source: &str) -> Vec<u8> {
+    let options = Options {
+        verify: false,
+        ..Options::new()
+    };
+
+    match fox_asm::assemble(source, &options) {
+        Ok(output) => output.data,
+        Err(diagnostics) => panic!("{}", diagnostics),
+    }
+}
+
+#[test]
+fn and_in_macro_expression() {
+    let data = assemble("
+        %mask v { #[ ^v & 0f ] }
+        |0100
+        mask 5a
+        HALT
+    ");
+
+    assert_eq!(data, [OP_LITB, 0x0a, OP_HALT]);
+}
+
+#[test]
+fn and_with_labels_in_macro_expression() {
+    let data = assemble("
+        %masked { #[ first & second ] }
+        |0100
+        masked
+        HALT
+        |0133 @first
+        |0172 @second
+    ");
+
+    assert_eq!(data, [OP_LITW, 0x32, 0x01, 0x00, 0x00, OP_HALT]);
+}
+
+#[test]
+fn local_labels_in_macro_expression() {
+    let data = assemble("
+        %size { #[ &end - &start ] &start .01 .02 .03 &end }
+        |0100
+        size size
+    ");
+
+    // Each expansion has its own labels, expressions with labels stay `LITW`
+    assert_eq!(data, [OP_LITW, 0x03, 0x00, 0x00, 0x00, 0x01, 0x02, 0x03, OP_LITW, 0x03, 0x00, 0x00, 0x00, 0x01, 0x02, 0x03]);
+}
+
+#[test]
+fn local_labels_and_and_in_macro_expression() {
+    let data = assemble("
+        %both { #[ &a & &b ] &a .ff &b }
+        |0100
+        both
+    ");
+
+    // 0105 & 0106
+    assert_eq!(data, [OP_LITW, 0x04, 0x01, 0x00, 0x00, 0xff]);
+}
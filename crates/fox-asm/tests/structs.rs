@@ -0,0 +1,73 @@
+use fox_asm::Options;
+use fox_bytecode::*;
+
+fn options() -> Options {
+    Options {
+        verify: false,
+        ..Options::new()
+    }
+}
+
+fn assemble(source: &str) -> Vec<u8> {
+    match fox_asm::assemble(source, &options()) {
+        Ok(output) => output.data,
+        Err(diagnostics) => panic!("{}", diagnostics),
+    }
+}
+
+fn errors(source: &str) -> Vec<String> {
+    let diagnostics = fox_asm::assemble(source, &options()).unwrap_err();
+    diagnostics.iter().map(|diagnostic| diagnostic.message.clone()).collect()
+}
+
+#[test]
+fn fields_of_hex_looking_records() {
+    let data = assemble("
+        ?struct point { x 4 y 4 }
+        |0100 ;bed.y :cafe.x HALT
+        |0200 ?record point bed { x 1 y 2 }
+        |0300 ?record point cafe { }
+    ");
+
+    assert_eq!(data[..10], [OP_LITW, 0x04, 0x02, 0x00, 0x00, 0x00, 0x03, 0x00, 0x00, OP_HALT]);
+    assert_eq!(data[0x100..0x108], [0x01, 0x00, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00]);
+    assert_eq!(data[0x200..], [0x00; 8]);
+}
+
+#[test]
+fn fields_in_local_labels() {
+    let data = assemble("|0100 @main ;&bed.y LW HALT &bed.y =12345678");
+
+    assert_eq!(data, [OP_LITW, 0x07, 0x01, 0x00, 0x00, OP_LW, OP_HALT, 0x78, 0x56, 0x34, 0x12]);
+}
+
+#[test]
+fn field_before_and() {
+    let data = assemble("
+        ?struct point { x 4 y 4 }
+        |0100 #[ bed.y & ff ] HALT
+        |0200 ?record point bed { }
+    ");
+
+    assert_eq!(data[..6], [OP_LITW, 0x04, 0x00, 0x00, 0x00, OP_HALT]);
+}
+
+#[test]
+fn raw_bytes_are_not_fields() {
+    assert_eq!(assemble("|0100 .ff.ff .33.33 .be.ef"), [0xff, 0xff, 0x33, 0x33, 0xbe, 0xef]);
+}
+
+#[test]
+fn struct_larger_than_memory() {
+    assert_eq!(errors("?struct big { a ffffffff }"), ["Field `a` ends past the end of memory, structs can't be larger than 0x1000000 bytes"]);
+    assert_eq!(errors("?struct big { a 800000 b 800000 c 1 }"), ["Field `c` ends past the end of memory, structs can't be larger than 0x1000000 bytes"]);
+}
+
+#[test]
+fn large_record_is_zeros() {
+    let data = assemble("?struct big { a 100000 } |0100 ?record big b { } .01");
+
+    assert_eq!(data.len(), 0x100001);
+    assert!(data[..0x100000].iter().all(|&byte| byte == 0));
+    assert_eq!(data[0x100000], 0x01);
+}
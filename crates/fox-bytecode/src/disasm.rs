@@ -0,0 +1,163 @@
+use std::fmt;
+use std::fmt::Write;
+use crate::Opcode;
+use crate::memory::RESET_VECTOR;
+use crate::symbols::Symbols;
+
+/// A decoded instruction with its inline operand.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Instruction {
+    pub opcode: Opcode,
+    pub operand: Option<u32>,
+}
+
+impl Instruction {
+    /// Decode the instruction at the start of `bytes`.
+    /// Returns `None` for unknown opcodes and operands running past the end of `bytes`.
+    pub fn decode(bytes: &[u8]) -> Option<Self> {
+        let opcode = Opcode::try_from(*bytes.first()?).ok()?;
+        let size = opcode.operand_size();
+
+        let operand = match size {
+            0 => None,
+            _ => {
+                let mut buf = [0; 4];
+                buf[..size].copy_from_slice(bytes.get(1..1 + size)?);
+                Some(u32::from_le_bytes(buf))
+            },
+        };
+
+        Some(Self {
+            opcode,
+            operand,
+        })
+    }
+
+    /// Size in bytes, including the operand.
+    pub fn size(&self) -> usize {
+        1 + self.opcode.operand_size()
+    }
+
+    /// Same as `Display`, but with `LITW` values matching a label written as `;label`.
+    pub fn format(&self, symbols: &Symbols) -> String {
+        match (self.opcode, self.operand.and_then(|value| symbols.lookup(value))) {
+            (Opcode::LitW, Some((label, 0))) => format!(";{}", label),
+            _ => self.to_string(),
+        }
+    }
+}
+
+/// Formats as assembly, `LITW` is written as `#value`.
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.opcode, self.operand) {
+            (Opcode::LitW, Some(value)) => write!(f, "#{:x}", value),
+            (Opcode::LitB, Some(value)) => write!(f, "LITB .{:02x}", value),
+            (opcode, _) => write!(f, "{}", opcode),
+        }
+    }
+}
+
+/// Disassemble a rom loaded at `RESET_VECTOR` into source that assembles back to the same bytes.
+/// `LITW` operands matching a label are written as `;label`.
+/// The source starts with `?relax off`, so small `LITW` values aren't assembled as `LITB`.
+/// Bytes that don't decode, or instructions that would run over a label or the end, are written as raw bytes.
+pub fn disassemble(data: &[u8], symbols: &Symbols) -> String {
+    let start = RESET_VECTOR;
+    let end = start + data.len() as u32;
+
+    let mut out = Output {
+        text: String::new(),
+        current_label: "on-reset".to_string(),
+    };
+
+    out.line("?relax off");
+
+    let labels: Vec<_> = symbols.iter().collect();
+    let mut next = 0;
+
+    // Labels outside of the rom are still needed by references to them, such as device ports
+    while next < labels.len() && labels[next].0 < start {
+        let addr = labels[next].0;
+        let count = labels[next..].iter().take_while(|(other, _)| *other == addr).count();
+        out.line(&format!("|{:x}", addr));
+        out.labels(&labels[next..next + count]);
+        next += count;
+    }
+
+    if next > 0 {
+        out.line(&format!("|{:x}", start));
+    }
+
+    let mut addr = start;
+    while addr <= end {
+        let count = labels[next..].iter().take_while(|(other, _)| *other == addr).count();
+        out.labels(&labels[next..next + count]);
+        next += count;
+
+        if addr == end {
+            break;
+        }
+
+        let offset = (addr - start) as usize;
+        let instruction = Instruction::decode(&data[offset..]).filter(|instruction| {
+            // Keep labels inside the operand on their own line
+            let operand_end = addr + instruction.size() as u32;
+            labels.get(next).is_none_or(|(label, _)| *label >= operand_end)
+        });
+
+        match instruction {
+            Some(instruction) => {
+                out.instruction(addr, &instruction.format(symbols));
+                addr += instruction.size() as u32;
+            },
+            None => {
+                out.instruction(addr, &format!(".{:02x}", data[offset]));
+                addr += 1;
+            },
+        }
+    }
+
+    while next < labels.len() {
+        let addr = labels[next].0;
+        let count = labels[next..].iter().take_while(|(other, _)| *other == addr).count();
+        out.line(&format!("|{:x}", addr));
+        out.labels(&labels[next..next + count]);
+        next += count;
+    }
+
+    out.text
+}
+
+struct Output {
+    text: String,
+    /// Label the assembler will prefix local labels with.
+    current_label: String,
+}
+
+impl Output {
+    fn line(&mut self, line: &str) {
+        writeln!(self.text, "{}", line).unwrap();
+    }
+
+    fn instruction(&mut self, addr: u32, text: &str) {
+        writeln!(self.text, "\t{:<24}( {:08x} )", text, addr).unwrap();
+    }
+
+    /// Define labels at the same address.
+    /// Locals of the current label go first, as a new label changes what `&` refers to.
+    fn labels(&mut self, labels: &[(u32, &str)]) {
+        let prefix = format!("{}/", self.current_label);
+        let (locals, rest): (Vec<_>, Vec<_>) = labels.iter().partition(|(_, label)| label.starts_with(&prefix));
+
+        for (_, label) in locals.into_iter().chain(rest) {
+            match label.strip_prefix(&format!("{}/", self.current_label)) {
+                Some(local) => self.line(&format!("&{}", local)),
+                None => {
+                    self.current_label = label.to_string();
+                    self.line(&format!("@{}", label));
+                },
+            }
+        }
+    }
+}
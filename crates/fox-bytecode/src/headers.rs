@@ -0,0 +1,110 @@
+use std::fmt::Write;
+use crate::memory::*;
+
+/// Names of the device headers the assembler can include.
+pub const HEADERS: &[&str] = &[
+    "console.fox",
+    "system.fox",
+    "screen.fox",
+    "file0.fox",
+    "file1.fox",
+    "mouse.fox",
+    "keyboard.fox",
+];
+
+/// Assembly source defining a label for every port of a device, e.g. `console.fox`.
+/// `screen.fox` also declares the `screen-cmd` struct for the records of the command buffer.
+/// These are generated from the constants in `memory`, so they always match the devices.
+/// Headers change the origin, so they should be included before `|0100`.
+pub fn header(name: &str) -> Option<String> {
+    let file = |prefix: &str, base: u32| {
+        ports(prefix, base, &[
+            ("vector", FILE_VECTOR),
+            ("filename", FILE_FILENAME),
+            ("length", FILE_LENGTH),
+            ("append", FILE_APPEND),
+            ("status", FILE_STATUS),
+            ("read", FILE_READ),
+            ("write", FILE_WRITE),
+        ])
+    };
+
+    let source = match name {
+        "console.fox" => ports("console", CONSOLE_BASE, &[
+            ("vector", CONSOLE_VECTOR),
+            ("write", CONSOLE_WRITE),
+            ("read", CONSOLE_READ),
+            ("error", CONSOLE_ERROR),
+        ]),
+        "system.fox" => ports("system", SYSTEM_BASE, &[
+            ("vector", SYSTEM_VECTOR),
+            ("exit", SYSTEM_EXIT),
+            ("read", SYSTEM_READ),
+            ("fault", SYSTEM_FAULT),
+            ("fault-ip", SYSTEM_FAULT_IP),
+            ("fault-operand", SYSTEM_FAULT_OPERAND),
+        ]),
+        "screen.fox" => ports("screen", 0, &[
+            ("vector", screen::VECTOR),
+            ("width", screen::WIDTH),
+            ("height", screen::HEIGHT),
+            ("cmd-length", screen::CMD_LENGTH),
+            ("cmd-addr", screen::CMD_ADDR),
+            ("zoom", screen::ZOOM),
+            ("palette-base", screen::PALETTE0),
+            ("layer0", screen::LAYER0),
+            ("layer1", screen::LAYER1),
+            ("layer2", screen::LAYER2),
+            ("layer3", screen::LAYER3),
+        ]) + &structure("screen-cmd", screen::command::SIZE, &[
+            ("x", screen::command::X),
+            ("y", screen::command::Y),
+            ("source", screen::command::SOURCE),
+            ("command", screen::command::COMMAND),
+            ("flags", screen::command::FLAGS),
+            ("color", screen::command::COLOR),
+            ("repeat", screen::command::REPEAT),
+        ]),
+        "file0.fox" => file("file0", FILE0_BASE),
+        "file1.fox" => file("file1", FILE1_BASE),
+        "mouse.fox" => ports("mouse", MOUSE_BASE, &[
+            ("vector", MOUSE_VECTOR),
+            ("x", MOUSE_X),
+            ("y", MOUSE_Y),
+            ("flags", MOUSE_FLAGS),
+            ("button", MOUSE_BUTTON),
+        ]),
+        "keyboard.fox" => ports("keyboard", KEYBOARD_BASE, &[
+            ("vector", keyboard::VECTOR),
+            ("codepoint", keyboard::CODEPOINT),
+            ("buttons", keyboard::BUTTONS),
+        ]),
+        _ => return None,
+    };
+
+    Some(source)
+}
+
+/// A line per port like `|10000004 @console-write`.
+fn ports(prefix: &str, base: u32, ports: &[(&str, u32)]) -> String {
+    let mut source = String::from("( Generated from fox_bytecode::memory )\n");
+
+    for (name, offset) in ports {
+        writeln!(source, "|{:08x} @{}-{}", base + offset, prefix, name).unwrap();
+    }
+
+    source
+}
+
+/// A `?struct` with fields at `fields`, each running up to the next one or `size`.
+fn structure(name: &str, size: u32, fields: &[(&str, u32)]) -> String {
+    let mut source = format!("?struct {} {{", name);
+
+    for (index, (field, offset)) in fields.iter().enumerate() {
+        let end = fields.get(index + 1).map_or(size, |next| next.1);
+        write!(source, " {} {:x}", field, end - offset).unwrap();
+    }
+
+    source.push_str(" }\n");
+    source
+}
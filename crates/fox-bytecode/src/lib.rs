@@ -1,4 +1,8 @@
 pub mod memory;
+pub mod symbols;
+pub mod lines;
+pub mod disasm;
+pub mod headers;
 
 pub const OP_HALT: u8 = 0x00;
 pub const OP_DBG : u8 = 0x01;
@@ -55,7 +59,7 @@ pub const OP_GET  : u8 = 0x72;
 pub const OP_SET  : u8 = 0x73;
 
 #[repr(u8)]
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum Opcode {
     Halt = OP_HALT,
     Dbg = OP_DBG,
@@ -112,66 +116,139 @@ pub enum Opcode {
     Set = OP_SET,
 }
 
+/// Static description of an instruction.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct OpcodeInfo {
+    pub opcode: Opcode,
+    /// Name as written in assembly, in uppercase.
+    pub mnemonic: &'static str,
+    /// Number of bytes following the opcode.
+    pub operand_size: usize,
+    /// Values popped from the data stack.
+    pub inputs: usize,
+    /// Values pushed to the data stack.
+    pub outputs: usize,
+    /// Values popped from the return stack.
+    pub return_inputs: usize,
+    /// Values pushed to the return stack.
+    pub return_outputs: usize,
+}
+
+impl OpcodeInfo {
+    const fn new(opcode: Opcode, mnemonic: &'static str, operand_size: usize, inputs: usize, outputs: usize, return_inputs: usize, return_outputs: usize) -> Self {
+        Self {
+            opcode,
+            mnemonic,
+            operand_size,
+            inputs,
+            outputs,
+            return_inputs,
+            return_outputs,
+        }
+    }
+}
+
+/// Every instruction, as described in `doc/cpu.md`.
+/// `PICK` reads `n` values deeper than its inputs, which can't be described statically.
+pub const OPCODES: &[OpcodeInfo] = &[
+    OpcodeInfo::new(Opcode::Halt, "HALT", 0, 0, 0, 0, 0),
+    OpcodeInfo::new(Opcode::Dbg, "DBG", 0, 0, 0, 0, 0),
+
+    OpcodeInfo::new(Opcode::LitW, "LITW", 4, 0, 1, 0, 0),
+    OpcodeInfo::new(Opcode::Dup, "DUP", 0, 1, 2, 0, 0),
+    OpcodeInfo::new(Opcode::Drop, "DROP", 0, 1, 0, 0, 0),
+    OpcodeInfo::new(Opcode::Swap, "SWAP", 0, 2, 2, 0, 0),
+    OpcodeInfo::new(Opcode::Over, "OVER", 0, 2, 3, 0, 0),
+    OpcodeInfo::new(Opcode::Rot, "ROT", 0, 3, 3, 0, 0),
+    OpcodeInfo::new(Opcode::LitB, "LITB", 1, 0, 1, 0, 0),
+    OpcodeInfo::new(Opcode::Pick, "PICK", 0, 1, 1, 0, 0),
+
+    OpcodeInfo::new(Opcode::Add, "ADD", 0, 2, 1, 0, 0),
+    OpcodeInfo::new(Opcode::Sub, "SUB", 0, 2, 1, 0, 0),
+    OpcodeInfo::new(Opcode::Mul, "MUL", 0, 2, 1, 0, 0),
+    OpcodeInfo::new(Opcode::Div, "DIV", 0, 2, 1, 0, 0),
+    OpcodeInfo::new(Opcode::And, "AND", 0, 2, 1, 0, 0),
+    OpcodeInfo::new(Opcode::Or, "OR", 0, 2, 1, 0, 0),
+    OpcodeInfo::new(Opcode::Xor, "XOR", 0, 2, 1, 0, 0),
+    OpcodeInfo::new(Opcode::Shl, "SHL", 0, 2, 1, 0, 0),
+    OpcodeInfo::new(Opcode::Shr, "SHR", 0, 2, 1, 0, 0),
+    OpcodeInfo::new(Opcode::Inc, "INC", 0, 1, 1, 0, 0),
+    OpcodeInfo::new(Opcode::Dec, "DEC", 0, 1, 1, 0, 0),
+    OpcodeInfo::new(Opcode::Sar, "SAR", 0, 2, 1, 0, 0),
+    OpcodeInfo::new(Opcode::Not, "NOT", 0, 1, 1, 0, 0),
+
+    OpcodeInfo::new(Opcode::Sw, "SW", 0, 2, 0, 0, 0),
+    OpcodeInfo::new(Opcode::Lw, "LW", 0, 1, 1, 0, 0),
+    OpcodeInfo::new(Opcode::Sb, "SB", 0, 2, 0, 0, 0),
+    OpcodeInfo::new(Opcode::Lb, "LB", 0, 1, 1, 0, 0),
+
+    OpcodeInfo::new(Opcode::Equ, "EQU", 0, 2, 1, 0, 0),
+    OpcodeInfo::new(Opcode::Neq, "NEQ", 0, 2, 1, 0, 0),
+    OpcodeInfo::new(Opcode::Lt, "LT", 0, 2, 1, 0, 0),
+    OpcodeInfo::new(Opcode::Gt, "GT", 0, 2, 1, 0, 0),
+    OpcodeInfo::new(Opcode::Lte, "LTE", 0, 2, 1, 0, 0),
+    OpcodeInfo::new(Opcode::Gte, "GTE", 0, 2, 1, 0, 0),
+
+    OpcodeInfo::new(Opcode::Jmp, "JMP", 0, 1, 0, 0, 0),
+    OpcodeInfo::new(Opcode::Jz, "JZ", 0, 2, 0, 0, 0),
+    OpcodeInfo::new(Opcode::Call, "CALL", 0, 1, 0, 0, 1),
+    OpcodeInfo::new(Opcode::Ret, "RET", 0, 0, 0, 1, 0),
+    OpcodeInfo::new(Opcode::Jnz, "JNZ", 0, 2, 0, 0, 0),
+
+    OpcodeInfo::new(Opcode::Rpush, "RPUSH", 0, 1, 0, 0, 1),
+    OpcodeInfo::new(Opcode::Rpop, "RPOP", 0, 0, 1, 1, 0),
+    OpcodeInfo::new(Opcode::Rpeek, "RPEEK", 0, 0, 1, 1, 1),
+    OpcodeInfo::new(Opcode::Rdrop, "RDROP", 0, 0, 0, 1, 0),
+
+    OpcodeInfo::new(Opcode::Begin, "BEGIN", 0, 1, 0, 0, 0),
+    OpcodeInfo::new(Opcode::End, "END", 0, 1, 0, 0, 0),
+    OpcodeInfo::new(Opcode::Get, "GET", 0, 1, 1, 0, 0),
+    OpcodeInfo::new(Opcode::Set, "SET", 0, 2, 0, 0, 0),
+];
+
+impl Opcode {
+    pub fn info(self) -> &'static OpcodeInfo {
+        OPCODES.iter().find(|info| info.opcode == self).expect("Opcode missing from OPCODES")
+    }
+
+    /// Name as written in assembly, in uppercase.
+    pub fn mnemonic(self) -> &'static str {
+        self.info().mnemonic
+    }
+
+    /// Number of bytes following the opcode, `LITW` and `LITB` have their value inline.
+    pub fn operand_size(self) -> usize {
+        self.info().operand_size
+    }
+}
+
 impl std::str::FromStr for Opcode {
     type Err = ();
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match s.to_lowercase().as_str() {
-            "halt" => Ok(Opcode::Halt),
-            "dbg" => Ok(Opcode::Dbg),
-
-            "litw" => Ok(Opcode::LitW),
-            "dup" => Ok(Opcode::Dup),
-            "drop" => Ok(Opcode::Drop),
-            "swap" => Ok(Opcode::Swap),
-            "over" => Ok(Opcode::Over),
-            "rot" => Ok(Opcode::Rot),
-            "litb" => Ok(Opcode::LitB),
-            "pick" => Ok(Opcode::Pick),
-
-            "add" => Ok(Opcode::Add),
-            "sub" => Ok(Opcode::Sub),
-            "mul" => Ok(Opcode::Mul),
-            "div" => Ok(Opcode::Div),
-            "and" => Ok(Opcode::And),
-            "or" => Ok(Opcode::Or),
-            "xor" => Ok(Opcode::Xor),
-            "shl" => Ok(Opcode::Shl),
-            "shr" => Ok(Opcode::Shr),
-            "inc" => Ok(Opcode::Inc),
-            "dec" => Ok(Opcode::Dec),
-            "sar" => Ok(Opcode::Sar),
-            "not" => Ok(Opcode::Not),
-
-            "sw" => Ok(Opcode::Sw),
-            "lw" => Ok(Opcode::Lw),
-            "sb" => Ok(Opcode::Sb),
-            "lb" => Ok(Opcode::Lb),
-
-            "equ" => Ok(Opcode::Equ),
-            "neq" => Ok(Opcode::Neq),
-            "lt" => Ok(Opcode::Lt),
-            "gt" => Ok(Opcode::Gt),
-            "lte" => Ok(Opcode::Lte),
-            "gte" => Ok(Opcode::Gte),
-
-            "jmp" => Ok(Opcode::Jmp),
-            "jz" => Ok(Opcode::Jz),
-            "call" => Ok(Opcode::Call),
-            "ret" => Ok(Opcode::Ret),
-            "jnz" => Ok(Opcode::Jnz),
-
-            "rpush" => Ok(Opcode::Rpush),
-            "rpop" => Ok(Opcode::Rpop),
-            "rpeek" => Ok(Opcode::Rpeek),
-            "rdrop" => Ok(Opcode::Rdrop),
-
-            "begin" => Ok(Opcode::Begin),
-            "end" => Ok(Opcode::End),
-            "get" => Ok(Opcode::Get),
-            "set" => Ok(Opcode::Set),
-
-            _ => Err(()),
-        }
+        OPCODES
+            .iter()
+            .find(|info| info.mnemonic.eq_ignore_ascii_case(s))
+            .map(|info| info.opcode)
+            .ok_or(())
+    }
+}
+
+impl TryFrom<u8> for Opcode {
+    /// The byte that is not an opcode.
+    type Error = u8;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        OPCODES
+            .iter()
+            .find(|info| info.opcode as u8 == value)
+            .map(|info| info.opcode)
+            .ok_or(value)
+    }
+}
+
+impl std::fmt::Display for Opcode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.mnemonic())
     }
 }
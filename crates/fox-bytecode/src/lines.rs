@@ -0,0 +1,110 @@
+use std::fmt;
+use std::path::Path;
+
+/// Source location of a range of emitted bytes, as written to a `.lines` file by the assembler.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LineEntry {
+    pub start: u32,
+    /// Exclusive.
+    pub end: u32,
+    pub file: String,
+    pub line: u32,
+    pub column: u32,
+}
+
+impl fmt::Display for LineEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}:{}", self.file, self.line, self.column)
+    }
+}
+
+/// Maps addresses back to source lines.
+/// Each line is a hexadecimal start and end address, the line and column and the file, e.g.
+/// `00000100 00000105 5:1 examples/test.fox`.
+#[derive(Debug, Default, Clone)]
+pub struct LineTable {
+    /// Sorted by start address.
+    entries: Vec<LineEntry>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    pub line: usize,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid line entry on line {}", self.line)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+impl LineTable {
+    pub fn new(mut entries: Vec<LineEntry>) -> Self {
+        entries.sort_by_key(|entry| entry.start);
+
+        Self {
+            entries,
+        }
+    }
+
+    pub fn parse(source: &str) -> Result<Self, ParseError> {
+        let mut entries = Vec::new();
+
+        for (index, line) in source.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let entry = Self::parse_entry(line).ok_or(ParseError { line: index + 1 })?;
+            entries.push(entry);
+        }
+
+        Ok(Self::new(entries))
+    }
+
+    fn parse_entry(line: &str) -> Option<LineEntry> {
+        let mut parts = line.splitn(4, ' ');
+        let start = u32::from_str_radix(parts.next()?, 16).ok()?;
+        let end = u32::from_str_radix(parts.next()?, 16).ok()?;
+        let (line, column) = parts.next()?.split_once(':')?;
+        let file = parts.next()?.to_string();
+
+        Some(LineEntry {
+            start,
+            end,
+            file,
+            line: line.parse().ok()?,
+            column: column.parse().ok()?,
+        })
+    }
+
+    pub fn load(path: &Path) -> std::io::Result<Self> {
+        use std::io::{Error, ErrorKind};
+
+        let source = std::fs::read_to_string(path)?;
+        Self::parse(&source).map_err(|err| Error::new(ErrorKind::InvalidData, err))
+    }
+
+    pub fn entries(&self) -> &[LineEntry] {
+        &self.entries
+    }
+
+    /// Source location of the byte at `addr`.
+    /// When ranges overlap the one starting last wins.
+    pub fn lookup(&self, addr: u32) -> Option<&LineEntry> {
+        let index = self.entries.partition_point(|entry| entry.start <= addr);
+        self.entries[..index].iter().rev().find(|entry| addr < entry.end)
+    }
+}
+
+impl fmt::Display for LineTable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for entry in &self.entries {
+            writeln!(f, "{:08x} {:08x} {}:{} {}", entry.start, entry.end, entry.line, entry.column, entry.file)?;
+        }
+
+        Ok(())
+    }
+}
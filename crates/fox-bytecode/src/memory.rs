@@ -3,6 +3,17 @@ pub const SCREEN_LAYER_LENGTH: u32 = 0x80000; // 512 Kilobytes
 
 pub const RESET_VECTOR  : u32 = 0x00000100;
 
+// --- Memory ---
+pub const MEM_SIZE   : u32 = 0x01000000; // 16 Megabytes
+/// The stack, return stack and locals are at the top of memory, in that order from the top.
+pub const STACK_SIZE : u32 = 1024; // bytes
+pub const RSTACK_SIZE: u32 = 1024; // bytes
+pub const LOCALS_SIZE: u32 = 1024; // bytes
+
+pub const STACK_BASE : u32 = MEM_SIZE - STACK_SIZE;
+pub const RSTACK_BASE: u32 = STACK_BASE - RSTACK_SIZE;
+pub const LOCALS_BASE: u32 = RSTACK_BASE - LOCALS_SIZE;
+
 // --- Devices ---
 pub const CONSOLE_BASE : u32 = 0x10000000;
 pub const SYSTEM_BASE  : u32 = 0x10010000;
@@ -21,14 +32,32 @@ pub const CONSOLE_ERROR : u32 = 0x000C;
 
 // -- SYSTEM DEVICE --
 // All of these are relative.
-/// Currently unused.
-pub const SYSTEM_VECTOR : u32 = 0x0000;
+/// Fault vector, triggered when the CPU traps.
+pub const SYSTEM_VECTOR  : u32 = 0x0000;
 /// Exit some time in the future.
-pub const SYSTEM_EXIT   : u32 = 0x0004;
+pub const SYSTEM_EXIT    : u32 = 0x0004;
 /// Read command line arguents as nul-terminated strings.
 /// Each argument is seperated by a single nul.
 /// If there are no more arguments this will return '0' continuously.
-pub const SYSTEM_READ   : u32 = 0x0008;
+pub const SYSTEM_READ    : u32 = 0x0008;
+/// Kind of the last fault, one of the `FAULT_` constants.
+pub const SYSTEM_FAULT   : u32 = 0x000C;
+/// Address of the instruction that caused the last fault.
+pub const SYSTEM_FAULT_IP: u32 = 0x0010;
+/// Opcode of an illegal opcode fault, or address of an invalid jump or access fault, `0` otherwise.
+pub const SYSTEM_FAULT_OPERAND: u32 = 0x0014;
+
+pub const FAULT_NONE                  : u32 = 0x00;
+pub const FAULT_STACK_OVERFLOW        : u32 = 0x01;
+pub const FAULT_STACK_UNDERFLOW       : u32 = 0x02;
+pub const FAULT_RETURN_STACK_OVERFLOW : u32 = 0x03;
+pub const FAULT_RETURN_STACK_UNDERFLOW: u32 = 0x04;
+pub const FAULT_LOCALS_OVERFLOW       : u32 = 0x05;
+pub const FAULT_LOCALS_UNDERFLOW      : u32 = 0x06;
+pub const FAULT_ILLEGAL_OPCODE        : u32 = 0x07;
+pub const FAULT_DIVIDE_BY_ZERO        : u32 = 0x08;
+pub const FAULT_INVALID_JUMP          : u32 = 0x09;
+pub const FAULT_INVALID_ACCESS        : u32 = 0x0A;
 
 
 // -- SCREEN DEVICE --
@@ -53,6 +82,9 @@ pub mod screen {
 
     // -- SCREEN SPRITE COMMAND --
     pub mod command {
+        /// Size of a command, commands are one after the other.
+        pub const SIZE   : u32 = 0x10;
+
         pub const X      : u32 = 0x0;
         pub const Y      : u32 = 0x4;
         pub const SOURCE : u32 = 0x8;
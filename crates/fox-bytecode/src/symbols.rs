@@ -0,0 +1,107 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+
+/// Label addresses, as written to a `.sym` file by the assembler.
+/// Each line is a hexadecimal address followed by a label, e.g. `00000100 on-reset`.
+#[derive(Debug, Default, Clone)]
+pub struct Symbols {
+    /// Sorted by address, with global labels before local labels.
+    symbols: Vec<(u32, String)>,
+    labels: HashMap<String, u32>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    pub line: usize,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid symbol on line {}", self.line)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+impl Symbols {
+    pub fn new(labels: HashMap<String, u32>) -> Self {
+        let mut symbols: Vec<_> = labels.iter().map(|(label, addr)| (*addr, label.clone())).collect();
+        symbols.sort_by_key(|(addr, label)| (*addr, label.contains('/'), label.clone()));
+
+        Self {
+            symbols,
+            labels,
+        }
+    }
+
+    pub fn parse(source: &str) -> Result<Self, ParseError> {
+        let mut labels = HashMap::new();
+
+        for (index, line) in source.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let error = ParseError { line: index + 1 };
+            let (addr, label) = line.split_once(' ').ok_or(error)?;
+            let addr = u32::from_str_radix(addr, 16).map_err(|_| error)?;
+            labels.insert(label.trim().to_string(), addr);
+        }
+
+        Ok(Self::new(labels))
+    }
+
+    pub fn load(path: &Path) -> std::io::Result<Self> {
+        use std::io::{Error, ErrorKind};
+
+        let source = std::fs::read_to_string(path)?;
+        Self::parse(&source).map_err(|err| Error::new(ErrorKind::InvalidData, err))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.symbols.is_empty()
+    }
+
+    /// All labels by address, with global labels before local labels at the same address.
+    pub fn iter(&self) -> impl Iterator<Item=(u32, &str)> + '_ {
+        self.symbols.iter().map(|(addr, label)| (*addr, label.as_str()))
+    }
+
+    /// Address of `label`.
+    pub fn resolve(&self, label: &str) -> Option<u32> {
+        self.labels.get(label).copied()
+    }
+
+    /// Closest label at or before `addr`, with the offset from it.
+    pub fn lookup(&self, addr: u32) -> Option<(&str, u32)> {
+        let index = self.symbols.partition_point(|(symbol, _)| *symbol <= addr);
+        let (symbol, _) = self.symbols.get(index.checked_sub(1)?)?;
+
+        // Prefer the first label at an address, which is a global label if there is one.
+        let first = self.symbols.partition_point(|(other, _)| other < symbol);
+        let (symbol, label) = &self.symbols[first];
+
+        Some((label, addr - symbol))
+    }
+
+    /// Format `addr` as `label+0x3`, or as plain hexadecimal if there is no label before it.
+    pub fn format(&self, addr: u32) -> String {
+        match self.lookup(addr) {
+            Some((label, 0)) => label.to_string(),
+            Some((label, offset)) => format!("{}+0x{:x}", label, offset),
+            None => format!("0x{:08x}", addr),
+        }
+    }
+}
+
+impl fmt::Display for Symbols {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (addr, label) in &self.symbols {
+            writeln!(f, "{:08x} {}", addr, label)?;
+        }
+
+        Ok(())
+    }
+}
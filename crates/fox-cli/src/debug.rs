@@ -0,0 +1,226 @@
+use fox_vm::{VirtualMachine, Machine};
+use fox_vm::debugger::{Debugger, Stop, Watchpoint};
+use fox_bytecode::memory::RESET_VECTOR;
+use crate::ConsoleMachine;
+
+const HELP: &str = "\
+Commands:
+  b, break <addr>              Break before executing addr
+  d, delete <addr>             Delete breakpoint
+  w, watch <addr> [len] [rw]   Break on reads (r) and/or writes (w), defaults to 4 bytes and rw
+  u, unwatch <addr>            Delete watchpoints starting at addr
+  l, list                      List breakpoints and watchpoints
+  s, step                      Execute one instruction
+  n, next                      Execute one instruction, stepping over calls
+  o, out                       Run until the current routine returns
+  c, continue                  Run until a breakpoint, watchpoint or halt
+  st, stack                    Print the stacks and locals
+  x <addr> [len]               Print memory, defaults to 16 bytes
+  q, quit                      Exit
+Addresses are hexadecimal or labels.
+Once halted, executing waits for console input and steps into the console vector.";
+
+/// Interactive debugger reading commands from the console.
+/// Console input shares stdin with the commands, so only input given while running reaches the rom.
+pub fn run(vm: &mut VirtualMachine, machine: &mut ConsoleMachine) -> ! {
+    let mut debugger = Debugger::new();
+    debugger.set_symbols(vm.symbols().clone());
+
+    // Enter the reset vector without executing anything
+    if let Err(fault) = vm.run_for(machine, RESET_VECTOR, 0) {
+        eprintln!("Fault: {} ({})", fault, vm.location(fault.ip));
+    }
+
+    print_location(&debugger, vm);
+
+    loop {
+        if let Some(exit) = machine.system.exit {
+            std::process::exit(exit as _);
+        }
+
+        eprint!("(fox) ");
+        let line = machine.console.read_line();
+        let mut words = line.split_whitespace();
+
+        let command = match words.next() {
+            Some(command) => command,
+            None => continue,
+        };
+        let args: Vec<_> = words.collect();
+
+        match command {
+            "b" | "break" => {
+                if let Some(addr) = parse_addr(&debugger, args.first()) {
+                    debugger.add_breakpoint(addr);
+                }
+            },
+            "d" | "delete" => {
+                if let Some(addr) = parse_addr(&debugger, args.first()) {
+                    if !debugger.remove_breakpoint(addr) {
+                        eprintln!("No breakpoint at 0x{:08x}", addr);
+                    }
+                }
+            },
+            "w" | "watch" => {
+                if let Some(watchpoint) = parse_watchpoint(&debugger, &args) {
+                    debugger.add_watchpoint(watchpoint);
+                }
+            },
+            "u" | "unwatch" => {
+                if let Some(addr) = parse_addr(&debugger, args.first()) {
+                    if !debugger.remove_watchpoint(addr) {
+                        eprintln!("No watchpoint at 0x{:08x}", addr);
+                    }
+                }
+            },
+            "l" | "list" => {
+                for addr in debugger.breakpoints() {
+                    eprintln!("break 0x{:08x} ({})", addr, debugger.symbols().format(addr));
+                }
+
+                for watchpoint in debugger.watchpoints() {
+                    let read = if watchpoint.read { "r" } else { "" };
+                    let write = if watchpoint.write { "w" } else { "" };
+                    eprintln!("watch 0x{:08x} {:x} {}{}", watchpoint.addr, watchpoint.length, read, write);
+                }
+            },
+            "s" | "step" => execute(&mut debugger, vm, machine, Debugger::step),
+            "n" | "next" => execute(&mut debugger, vm, machine, Debugger::step_over),
+            "o" | "out" => execute(&mut debugger, vm, machine, Debugger::step_out),
+            "c" | "continue" => execute(&mut debugger, vm, machine, Debugger::cont),
+            "st" | "stack" => print_stacks(vm),
+            "x" => {
+                let addr = parse_addr(&debugger, args.first());
+                let length = args.get(1).map_or(Some(16), |value| u32::from_str_radix(value, 16).ok());
+
+                match (addr, length) {
+                    (Some(addr), Some(length)) => print_memory(&debugger, vm, addr, length),
+                    (_, None) => eprintln!("Invalid length"),
+                    _ => (),
+                }
+            },
+            "q" | "quit" => std::process::exit(0),
+            "h" | "help" => eprintln!("{}", HELP),
+            _ => eprintln!("Unknown command {}, try help", command),
+        }
+    }
+}
+
+/// Run a debugger command, entering the console vector first if the previous vector halted.
+fn execute<F>(debugger: &mut Debugger, vm: &mut VirtualMachine, machine: &mut ConsoleMachine, command: F)
+where
+    F: FnOnce(&mut Debugger, &mut VirtualMachine, &mut dyn Machine) -> Result<Stop, fox_vm::Fault>,
+{
+    if !vm.is_running() {
+        eprintln!("Waiting for console input");
+        machine.console.read_block();
+
+        let vector = machine.console.vector;
+        if let Err(fault) = vm.run_for(machine, vector, 0) {
+            eprintln!("Fault: {} ({})", fault, vm.location(fault.ip));
+            return;
+        }
+    }
+
+    match command(debugger, vm, machine) {
+        Ok(Stop::Stepped) => (),
+        Ok(stop) => eprintln!("Stopped: {}", stop),
+        Err(fault) => {
+            eprintln!("Fault: {} ({})", fault, vm.location(fault.ip));
+            return;
+        },
+    }
+
+    print_location(debugger, vm);
+}
+
+fn parse_addr(debugger: &Debugger, value: Option<&&str>) -> Option<u32> {
+    let value = match value {
+        Some(value) => value,
+        None => {
+            eprintln!("Missing address");
+            return None;
+        },
+    };
+
+    let addr = debugger.resolve(value);
+    if addr.is_none() {
+        eprintln!("Unknown address {}", value);
+    }
+
+    addr
+}
+
+fn parse_watchpoint(debugger: &Debugger, args: &[&str]) -> Option<Watchpoint> {
+    let addr = parse_addr(debugger, args.first())?;
+
+    let length = match args.get(1) {
+        Some(value) => match u32::from_str_radix(value, 16) {
+            Ok(length) => length,
+            Err(_) => {
+                eprintln!("Invalid length {}", value);
+                return None;
+            },
+        },
+        None => 4,
+    };
+
+    let mode = args.get(2).copied().unwrap_or("rw");
+
+    Some(Watchpoint {
+        addr,
+        length,
+        read: mode.contains('r'),
+        write: mode.contains('w'),
+    })
+}
+
+fn print_location(debugger: &Debugger, vm: &mut VirtualMachine) {
+    if !vm.is_running() {
+        eprintln!("Halted");
+        return;
+    }
+
+    let ip = vm.cpu().ip();
+    match debugger.instruction(vm, ip) {
+        Some(instruction) => eprintln!("0x{:08x} ({}): {}", ip, vm.location(ip), instruction.format(debugger.symbols())),
+        None => match debugger.read_memory(vm, ip, 1) {
+            Some(byte) => eprintln!("0x{:08x} ({}): .{:02x}", ip, vm.location(ip), byte[0]),
+            None => eprintln!("0x{:08x} ({}): outside of memory", ip, vm.location(ip)),
+        },
+    }
+}
+
+fn print_stacks(vm: &mut VirtualMachine) {
+    let cpu = vm.cpu();
+
+    let print = |name: &str, values: Vec<u32>| {
+        eprint!("{}: ", name);
+        for value in values {
+            eprint!("0x{:08x} ", value);
+        }
+        eprintln!();
+    };
+
+    print("SP", cpu.stack());
+    print("RP", cpu.return_stack());
+    print("LOCAL", cpu.locals());
+}
+
+fn print_memory(debugger: &Debugger, vm: &mut VirtualMachine, addr: u32, length: u32) {
+    let data = match debugger.read_memory(vm, addr, length) {
+        Some(data) => data,
+        None => {
+            eprintln!("Can only print memory, not devices");
+            return;
+        },
+    };
+
+    for (index, row) in data.chunks(16).enumerate() {
+        eprint!("0x{:08x}:", addr as usize + index * 16);
+        for value in row {
+            eprint!(" {:02x}", value);
+        }
+        eprintln!();
+    }
+}
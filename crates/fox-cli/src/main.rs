@@ -1,5 +1,9 @@
-use fox_vm::{VirtualMachine, Machine, DirectMemoryAccess};
+mod debug;
+
+use fox_vm::{VirtualMachine, Machine, DirectMemoryAccess, Fault};
 use fox_bytecode::memory::*;
+use fox_bytecode::symbols::Symbols;
+use fox_bytecode::lines::LineTable;
 use fox_vm::device::{Device, match_device, FileDevice, SystemDevice, ConsoleDevice};
 
 struct ConsoleMachine {
@@ -17,20 +21,22 @@ impl ConsoleMachine {
         (FILE1_BASE  , DEVICE_LENGTH), // 3
     ];
 
-    fn device(&mut self, num: u32) -> &mut dyn Device {
-        match num {
+    fn device(&mut self, num: u32) -> Option<&mut dyn Device> {
+        let device: &mut dyn Device = match num {
             0 => &mut self.system,
             1 => &mut self.console,
             2 => &mut self.file0,
             3 => &mut self.file1,
-            _ => unimplemented!(),
-        }
+            _ => return None,
+        };
+
+        Some(device)
     }
 
-    pub fn new() -> Self {
+    pub fn new(args: impl Iterator<Item=String>) -> Self {
         Self {
             console: ConsoleDevice::new(),
-            system: SystemDevice::new(),
+            system: SystemDevice::with_args(args),
             file0: FileDevice::new(FILE0_BASE),
             file1: FileDevice::new(FILE1_BASE),
         }
@@ -38,44 +44,74 @@ impl ConsoleMachine {
 }
 
 impl Machine for ConsoleMachine {
-    fn write_u32(&mut self, addr: u32, value: u32, dma: DirectMemoryAccess<'_>) {
-        let device = self.device(match_device(Self::DEVICES, addr));
+    fn write_u32(&mut self, addr: u32, value: u32, dma: DirectMemoryAccess<'_>) -> Option<()> {
+        let device = self.device(match_device(Self::DEVICES, addr))?;
         device.write_u32(addr, value, dma)
     }
 
-    fn read_u32(&mut self, addr: u32, dma: DirectMemoryAccess<'_>) -> u32 {
-        let device = self.device(match_device(Self::DEVICES, addr));
+    fn read_u32(&mut self, addr: u32, dma: DirectMemoryAccess<'_>) -> Option<u32> {
+        let device = self.device(match_device(Self::DEVICES, addr))?;
         device.read_u32(addr, dma)
     }
 
-    fn write_u8(&mut self, addr: u32, value: u8, dma: DirectMemoryAccess<'_>) {
-        let device = self.device(match_device(Self::DEVICES, addr));
+    fn write_u8(&mut self, addr: u32, value: u8, dma: DirectMemoryAccess<'_>) -> Option<()> {
+        let device = self.device(match_device(Self::DEVICES, addr))?;
         device.write_u8(addr, value, dma)
     }
 
-    fn read_u8(&mut self, addr: u32, dma: DirectMemoryAccess<'_>) -> u8 {
-        let device = self.device(match_device(Self::DEVICES, addr));
+    fn read_u8(&mut self, addr: u32, dma: DirectMemoryAccess<'_>) -> Option<u8> {
+        let device = self.device(match_device(Self::DEVICES, addr))?;
         device.read_u8(addr, dma)
     }
+
+    fn fault(&mut self, fault: Fault) -> u32 {
+        self.system.fault(fault)
+    }
 }
 
-fn main() {
-    let args: Vec<_> = std::env::args().collect();
-    if args.len() < 2 {
-        eprintln!("Must have at least 1 argument");
-        return;
+fn run(vm: &mut VirtualMachine, machine: &mut ConsoleMachine, vector: u32) {
+    if let Err(fault) = vm.run(machine, vector) {
+        // The rom already asked to exit, so whatever comes after doesn't matter.
+        if let Some(exit) = machine.system.exit {
+            std::process::exit(exit as _);
+        }
+
+        eprintln!("Fault: {} ({})", fault, vm.location(fault.ip));
+        std::process::exit(1);
     }
+}
+
+fn main() {
+    let mut args = std::env::args().skip(1).peekable();
+    let debug = args.next_if_eq("--debug").is_some();
 
-    let mut machine = ConsoleMachine::new();
+    let rom = match args.next() {
+        Some(rom) => rom,
+        None => {
+            eprintln!("Must have at least 1 argument");
+            return;
+        },
+    };
+
+    // Remaining arguments are passed on to the rom
+    let mut machine = ConsoleMachine::new(args);
     let mut vm = VirtualMachine::new();
 
     // Load rom
     {
-        let data = std::fs::read(&args[1]).unwrap();
+        let data = std::fs::read(&rom).unwrap();
         vm.load(&data);
+
+        let path = std::path::Path::new(&rom);
+        vm.set_symbols(Symbols::load(&path.with_extension("sym")).unwrap_or_default());
+        vm.set_lines(LineTable::load(&path.with_extension("lines")).unwrap_or_default());
+    }
+
+    if debug {
+        debug::run(&mut vm, &mut machine);
     }
 
-    vm.run(&mut machine, RESET_VECTOR);
+    run(&mut vm, &mut machine, RESET_VECTOR);
 
     loop {
         if let Some(exit) = machine.system.exit {
@@ -84,9 +120,7 @@ fn main() {
 
         if machine.console.read_block() {
             let vector = machine.console.vector;
-            if vector != 0 {
-                vm.run(&mut machine, vector);
-            }
+            run(&mut vm, &mut machine, vector);
         }
     }
 }
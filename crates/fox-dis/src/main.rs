@@ -0,0 +1,24 @@
+use std::path::Path;
+use fox_bytecode::symbols::Symbols;
+
+fn main() {
+    let args: Vec<_> = std::env::args().collect();
+    if args.len() != 2 {
+        eprintln!("Usage: fox-dis <rom>");
+        std::process::exit(1);
+    }
+
+    let rom = Path::new(&args[1]);
+    let data = match std::fs::read(rom) {
+        Ok(data) => data,
+        Err(err) => {
+            eprintln!("Could not read {}: {}", rom.display(), err);
+            std::process::exit(1);
+        },
+    };
+
+    // Symbols are optional, without them everything is a plain number
+    let symbols = Symbols::load(&rom.with_extension("sym")).unwrap_or_default();
+
+    print!("{}", fox_bytecode::disasm::disassemble(&data, &symbols));
+}
@@ -0,0 +1,263 @@
+use std::fmt::{self, Write};
+use std::io::Read;
+
+pub use fox_vm::device::screen::PALETTE;
+
+/// Width and height of a tile in pixels.
+pub const TILE_SIZE: u32 = 8;
+/// Most tiles a single sprite command can draw in each direction, the `REPEAT` nibbles hold one less.
+pub const MAX_TILES: u32 = 16;
+
+#[derive(Debug)]
+pub enum Error {
+    Png(png::DecodingError),
+    Size(String),
+    Palette(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Png(err) => write!(f, "Could not decode png: {}", err),
+            Error::Size(message) => write!(f, "{}", message),
+            Error::Palette(message) => write!(f, "Invalid palette: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<png::DecodingError> for Error {
+    fn from(err: png::DecodingError) -> Self {
+        Error::Png(err)
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Format {
+    /// 1 bit per pixel, 8 bytes per tile. Pixels closer to `fg` than `bg` are set.
+    Sprite1 { fg: u8, bg: u8 },
+    /// 4 bits per pixel, 32 bytes per tile. Every pixel is the closest palette entry.
+    Sprite4,
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Format::Sprite1 { fg, bg } => write!(f, "1bpp, color .{:x}{:x}", fg, bg),
+            Format::Sprite4 => write!(f, "4bpp"),
+        }
+    }
+}
+
+/// A decoded image, one RGBA value per pixel.
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<[u8; 4]>,
+}
+
+impl Image {
+    pub fn read_png<R: Read>(input: R) -> Result<Self, Error> {
+        let mut decoder = png::Decoder::new(input);
+        decoder.set_transformations(png::Transformations::normalize_to_color8());
+
+        let mut reader = decoder.read_info()?;
+        let mut buf = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut buf)?;
+
+        let pixels = buf[..info.buffer_size()]
+            .chunks_exact(info.color_type.samples())
+            .map(|pixel| match *pixel {
+                [l] => [l, l, l, 0xFF],
+                [l, a] => [l, l, l, a],
+                [r, g, b] => [r, g, b, 0xFF],
+                [r, g, b, a] => [r, g, b, a],
+                _ => unreachable!("Normalized to at most 4 samples"),
+            })
+            .collect();
+
+        Ok(Self {
+            width: info.width,
+            height: info.height,
+            pixels,
+        })
+    }
+
+    fn pixel(&self, x: u32, y: u32) -> [u8; 4] {
+        self.pixels[(y * self.width + x) as usize]
+    }
+}
+
+pub struct Options {
+    pub format: Format,
+    pub palette: [u32; 16],
+    /// Size of each sprite in tiles, the whole image if not set.
+    pub size: Option<(u32, u32)>,
+}
+
+impl Options {
+    pub fn new() -> Self {
+        Self {
+            format: Format::Sprite4,
+            palette: PALETTE,
+            size: None,
+        }
+    }
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Tiles of a sprite, in the order a single sprite command draws them:
+/// left to right, then top to bottom.
+pub struct Sprite {
+    /// Size in tiles.
+    pub width: u32,
+    pub height: u32,
+    pub data: Vec<u8>,
+}
+
+impl Sprite {
+    /// Value of the `W & H` byte of the command drawing this sprite.
+    pub fn repeat(&self) -> u8 {
+        (((self.width - 1) << 4) | (self.height - 1)) as u8
+    }
+}
+
+/// Cut `image` into sprites of `options.size` tiles, left to right then top to bottom.
+pub fn convert(image: &Image, options: &Options) -> Result<Vec<Sprite>, Error> {
+    if !image.width.is_multiple_of(TILE_SIZE) || !image.height.is_multiple_of(TILE_SIZE) {
+        return Err(Error::Size(format!("Image is {}x{} pixels, not a multiple of 8x8 tiles", image.width, image.height)));
+    }
+
+    let (width, height) = options.size.unwrap_or((image.width / TILE_SIZE, image.height / TILE_SIZE));
+    if width == 0 || height == 0 || width > MAX_TILES || height > MAX_TILES {
+        return Err(Error::Size(format!("Sprites of {}x{} tiles can't be drawn, they can be at most 16x16 tiles", width, height)));
+    }
+
+    let (sprite_width, sprite_height) = (width * TILE_SIZE, height * TILE_SIZE);
+    if !image.width.is_multiple_of(sprite_width) || !image.height.is_multiple_of(sprite_height) {
+        let message = format!("Image is {}x{} pixels, not a multiple of {}x{} sprites", image.width, image.height, sprite_width, sprite_height);
+        return Err(Error::Size(message));
+    }
+
+    let mut sprites = Vec::new();
+    for sprite_y in (0..image.height).step_by(sprite_height as _) {
+        for sprite_x in (0..image.width).step_by(sprite_width as _) {
+            let mut data = Vec::new();
+            for tile_y in 0..height {
+                for tile_x in 0..width {
+                    let x = sprite_x + tile_x * TILE_SIZE;
+                    let y = sprite_y + tile_y * TILE_SIZE;
+                    encode_tile(image, x, y, options, &mut data);
+                }
+            }
+
+            sprites.push(Sprite {
+                width,
+                height,
+                data,
+            });
+        }
+    }
+
+    Ok(sprites)
+}
+
+/// Encode the tile at `x`, `y` the way `Sprite::read_1bpp` and `Sprite::read_4bpp` decode it.
+fn encode_tile(image: &Image, x: u32, y: u32, options: &Options, out: &mut Vec<u8>) {
+    for y in y..y + TILE_SIZE {
+        let row = (x..x + TILE_SIZE).map(|x| image.pixel(x, y));
+
+        match options.format {
+            Format::Sprite1 { fg, bg } => {
+                let palette = [options.palette[bg as usize], options.palette[fg as usize]];
+                let byte = row.fold(0, |byte, pixel| {
+                    let bit = if is_transparent(pixel) { 0 } else { nearest(&palette, pixel) };
+                    (byte << 1) | bit
+                });
+                out.push(byte);
+            },
+            Format::Sprite4 => {
+                let colors: Vec<u8> = row
+                    .map(|pixel| if is_transparent(pixel) { 0 } else { nearest(&options.palette, pixel) })
+                    .collect();
+                out.extend(colors.chunks(2).map(|pair| (pair[0] << 4) | pair[1]));
+            },
+        }
+    }
+}
+
+/// Mostly transparent pixels become color 0, which skip clear doesn't draw, or the `bg` bit in 1bpp.
+fn is_transparent([_, _, _, a]: [u8; 4]) -> bool {
+    a < 0x80
+}
+
+/// Index of the palette entry closest to `pixel`.
+pub fn nearest(palette: &[u32], [r, g, b, _]: [u8; 4]) -> u8 {
+    let distance = |color: u32| {
+        let [_, cr, cg, cb] = color.to_be_bytes();
+        let d = |a: u8, b: u8| (a as i32 - b as i32).pow(2);
+        d(r, cr) + d(g, cg) + d(b, cb)
+    };
+
+    (0..palette.len())
+        .min_by_key(|index| distance(palette[*index]))
+        .unwrap_or(0) as u8
+}
+
+/// Palette in the `.hex` format of lospec.com, one `rrggbb` color per line.
+pub fn parse_palette(text: &str) -> Result<[u32; 16], Error> {
+    let colors = text
+        .lines()
+        .map(|line| line.trim().trim_start_matches('#'))
+        .filter(|line| !line.is_empty())
+        .map(|line| u32::from_str_radix(line, 16).map_err(|_| Error::Palette(format!("`{}` is not a color", line))))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    colors
+        .try_into()
+        .map_err(|colors: Vec<u32>| Error::Palette(format!("Expected 16 colors, found {}", colors.len())))
+}
+
+/// Number and size of `sprites`, like `2 sprites of 2x2 tiles, W & H .11`.
+pub fn describe(sprites: &[Sprite]) -> String {
+    let plural = if sprites.len() == 1 { "" } else { "s" };
+    match sprites.first() {
+        Some(sprite) => format!(
+            "{} sprite{} of {}x{} tiles, W & H .{:02x}",
+            sprites.len(), plural, sprite.width, sprite.height, sprite.repeat(),
+        ),
+        None => "0 sprites".to_string(),
+    }
+}
+
+/// All sprites after each other, for including with `!path`.
+pub fn to_bytes(sprites: &[Sprite]) -> Vec<u8> {
+    sprites.iter().flat_map(|sprite| sprite.data.iter().copied()).collect()
+}
+
+/// Assembly source defining `@label` at the sprites, and `&0`, `&1`... at each of them if there is more than one.
+pub fn to_source(label: &str, sprites: &[Sprite], format: Format) -> String {
+    let mut source = String::new();
+
+    writeln!(source, "( {}, {} )", format, describe(sprites)).unwrap();
+    writeln!(source, "@{}", label).unwrap();
+
+    for (index, sprite) in sprites.iter().enumerate() {
+        if sprites.len() > 1 {
+            writeln!(source, "&{}", index).unwrap();
+        }
+
+        for line in sprite.data.chunks(8) {
+            let bytes: Vec<String> = line.iter().map(|byte| format!(".{:02x}", byte)).collect();
+            writeln!(source, "\t{}", bytes.join(" ")).unwrap();
+        }
+    }
+
+    source
+}
@@ -0,0 +1,79 @@
+use std::path::{Path, PathBuf};
+use fox_sprite::{Format, Image, Options};
+
+const USAGE: &str = "Usage: fox-sprite [--1bpp <fg> <bg> | --4bpp] [--size <w>x<h>] [--palette <file.hex>] [-o <output>] <input.png>";
+
+fn fail(message: impl std::fmt::Display) -> ! {
+    eprintln!("{}", message);
+    std::process::exit(1);
+}
+
+fn parse_color(value: Option<String>) -> u8 {
+    match value.as_deref().map(|value| u8::from_str_radix(value, 16)) {
+        Some(Ok(color)) if color < 16 => color,
+        _ => fail("Expected a palette index from 0 to f"),
+    }
+}
+
+fn parse_size(value: Option<String>) -> (u32, u32) {
+    let size = value.as_deref()
+        .and_then(|value| value.split_once('x'))
+        .and_then(|(w, h)| Some((w.parse().ok()?, h.parse().ok()?)));
+
+    match size {
+        Some(size) => size,
+        None => fail("Expected a size in tiles like 2x2"),
+    }
+}
+
+fn main() {
+    let mut options = Options::new();
+    let mut input = None;
+    let mut output = None;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--1bpp" => {
+                let fg = parse_color(args.next());
+                let bg = parse_color(args.next());
+                options.format = Format::Sprite1 { fg, bg };
+            },
+            "--4bpp" => options.format = Format::Sprite4,
+            "--size" => options.size = Some(parse_size(args.next())),
+            "--palette" => {
+                let path = args.next().unwrap_or_else(|| fail("Expected a palette file after --palette"));
+                let text = std::fs::read_to_string(&path).unwrap_or_else(|err| fail(format!("Could not read {}: {}", path, err)));
+                options.palette = fox_sprite::parse_palette(&text).unwrap_or_else(|err| fail(format!("{}: {}", path, err)));
+            },
+            "-o" => output = Some(PathBuf::from(args.next().unwrap_or_else(|| fail("Expected a file after -o")))),
+            _ if input.is_none() => input = Some(PathBuf::from(arg)),
+            _ => fail(USAGE),
+        }
+    }
+
+    let input = input.unwrap_or_else(|| fail(USAGE));
+    let output = output.unwrap_or_else(|| input.with_extension("bin"));
+
+    let file = std::fs::File::open(&input).unwrap_or_else(|err| fail(format!("Could not read {}: {}", input.display(), err)));
+    let image = Image::read_png(std::io::BufReader::new(file)).unwrap_or_else(|err| fail(format!("{}: {}", input.display(), err)));
+    let sprites = fox_sprite::convert(&image, &options).unwrap_or_else(|err| fail(format!("{}: {}", input.display(), err)));
+
+    let data = if output.extension().is_some_and(|extension| extension == "fox") {
+        let label = output.file_stem().map(|stem| stem.to_string_lossy()).unwrap_or_default();
+        fox_sprite::to_source(&label, &sprites, options.format).into_bytes()
+    } else {
+        fox_sprite::to_bytes(&sprites)
+    };
+
+    write(&output, &data);
+
+    println!("Converted {}, {}", fox_sprite::describe(&sprites), options.format);
+}
+
+fn write(path: &Path, data: &[u8]) {
+    if let Err(err) = std::fs::write(path, data) {
+        fail(format!("Could not write {}: {}", path.display(), err));
+    }
+    println!("Writing to {}", path.display());
+}
@@ -0,0 +1,105 @@
+use fox_bytecode::memory::screen::{self, command};
+use fox_sprite::{convert, nearest, Format, Image, Options, PALETTE};
+use fox_vm::VirtualMachine;
+use fox_vm::device::{Device, ScreenDevice};
+use fox_vm::device::screen::Display;
+
+const TRANSPARENT: [u8; 4] = [0xFF, 0xFF, 0xFF, 0x00];
+
+struct NoDisplay;
+
+impl Display for NoDisplay {
+    fn resize(&mut self, width: u32, height: u32, zoom: u32) -> (u32, u32, u32) {
+        (width, height, zoom)
+    }
+
+    fn render(&mut self, _buffer: &[u8], _palette: &[u32; 16]) {}
+}
+
+fn rgba(color: u32) -> [u8; 4] {
+    let [_, r, g, b] = color.to_be_bytes();
+    [r, g, b, 0xFF]
+}
+
+/// An 8x8 image with the color `pixel(x, y)` at each pixel.
+fn tile(pixel: impl Fn(u32, u32) -> [u8; 4]) -> Image {
+    let pixels = (0..8).flat_map(|y| (0..8).map(move |x| (x, y))).map(|(x, y)| pixel(x, y)).collect();
+    Image { width: 8, height: 8, pixels }
+}
+
+/// Draw `data` with the screen device, and return the colors of the 8x8 pixels it drew.
+fn draw(data: &[u8], cmd: u8, color: u8) -> Vec<u8> {
+    let mut vm = VirtualMachine::new();
+    let mut screen = ScreenDevice::new(NoDisplay);
+
+    vm.dma().write(0x2000, data);
+    vm.dma().write_u32(0x1000 + command::SOURCE, 0x2000);
+    vm.dma().write_u8(0x1000 + command::COMMAND, cmd << 4);
+    vm.dma().write_u8(0x1000 + command::COLOR, color);
+    screen.write_u32(screen::CMD_LENGTH, 1, vm.dma()).unwrap();
+    screen.write_u32(screen::CMD_ADDR, 0x1000, vm.dma()).unwrap();
+
+    let (width, _) = screen.size();
+    (0..8).flat_map(|y| (0..8).map(move |x| (x, y))).map(|(x, y)| {
+        let byte = screen.read_u8(screen::LAYER0 + y * (width / 2) + x / 2, vm.dma()).unwrap();
+        if x & 1 == 0 { byte >> 4 } else { byte & 0xF }
+    }).collect()
+}
+
+#[test]
+fn palette_colors_match_themselves() {
+    for (index, color) in PALETTE.iter().enumerate() {
+        assert_eq!(nearest(&PALETTE, rgba(*color)), index as u8);
+    }
+}
+
+#[test]
+fn colors_match_the_closest_entry() {
+    let palette = [0x000000, 0xff0000, 0x00ff00, 0xffffff];
+    assert_eq!(nearest(&palette, [0x20, 0x10, 0x10, 0xFF]), 0);
+    assert_eq!(nearest(&palette, [0xc0, 0x30, 0x20, 0xFF]), 1);
+    assert_eq!(nearest(&palette, [0x40, 0xe0, 0x50, 0xFF]), 2);
+    assert_eq!(nearest(&palette, [0xd0, 0xd0, 0xc0, 0xFF]), 3);
+}
+
+#[test]
+fn tile_4bpp_round_trips() {
+    let color = |x: u32, y: u32| ((x + y * 3) % 16) as u8;
+    let image = tile(|x, y| if (x, y) == (7, 7) { TRANSPARENT } else { rgba(PALETTE[color(x, y) as usize]) });
+    let sprites = convert(&image, &Options::new()).unwrap();
+
+    // Two pixels per byte, left one in the high nibble
+    let data = &sprites[0].data;
+    assert_eq!(data.len(), 32);
+    assert_eq!(data[0], 0x01);
+    assert_eq!(data[4], 0x34);
+    assert_eq!(data[31], color(6, 7) << 4);
+
+    let expected: Vec<u8> = (0..64).map(|i| if i == 63 { 0 } else { color(i % 8, i / 8) }).collect();
+    assert_eq!(draw(data, command::COMMAND_SPRITE4, 0), expected);
+}
+
+#[test]
+fn tile_1bpp_round_trips() {
+    let (fg, bg) = (0x0c, 0x03);
+    let options = Options {
+        format: Format::Sprite1 { fg, bg },
+        ..Options::new()
+    };
+
+    // Columns of foreground, background and transparent pixels
+    let image = tile(|x, _| match x % 3 {
+        0 => rgba(PALETTE[fg as usize]),
+        1 => rgba(PALETTE[bg as usize]),
+        _ => TRANSPARENT,
+    });
+    let sprites = convert(&image, &options).unwrap();
+
+    // One bit per pixel, left one in the high bit, set for the foreground
+    let data = &sprites[0].data;
+    assert_eq!(data, &[0b1001_0010; 8]);
+
+    // Transparent pixels are the background, not color 0
+    let expected: Vec<u8> = (0..64).map(|i| if i % 8 % 3 == 0 { fg } else { bg }).collect();
+    assert_eq!(draw(data, command::COMMAND_SPRITE1, (fg << 4) | bg), expected);
+}
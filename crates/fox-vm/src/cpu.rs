@@ -0,0 +1,122 @@
+use crate::{VirtualMachine, FaultKind, MEM_SIZE, SP_OFFSET, SP_SIZE, RP_OFFSET, RP_SIZE, LOCAL_OFFSET, LOCAL_SIZE};
+
+/// Public way of inspecting and changing the CPU registers and stacks.
+/// Use this through the `cpu()` method.
+/// Stacks are listed bottom first, so the last value is the top of the stack.
+pub struct CpuState<'a> {
+    vm: &'a mut VirtualMachine,
+}
+
+impl<'a> CpuState<'a> {
+    pub(crate) fn new(vm: &'a mut VirtualMachine) -> Self {
+        Self {
+            vm,
+        }
+    }
+
+    pub fn ip(&self) -> u32 {
+        self.vm.ip
+    }
+
+    pub fn set_ip(&mut self, ip: u32) -> Result<(), FaultKind> {
+        if ip as usize >= MEM_SIZE {
+            return Err(FaultKind::InvalidJump(ip));
+        }
+
+        self.vm.ip = ip;
+        Ok(())
+    }
+
+    pub fn stack(&self) -> Vec<u32> {
+        self.read_range(SP_OFFSET, self.vm.sp)
+    }
+
+    pub fn set_stack(&mut self, values: &[u32]) -> Result<(), FaultKind> {
+        if values.len() * 4 > SP_SIZE {
+            return Err(FaultKind::StackOverflow);
+        }
+
+        self.vm.sp = self.write_range(SP_OFFSET, values);
+        Ok(())
+    }
+
+    pub fn stack_depth(&self) -> usize {
+        (self.vm.sp - SP_OFFSET) / 4
+    }
+
+    /// Value `n` down from the top of the stack, same as `PICK`.
+    pub fn peek(&self, n: u32) -> Result<u32, FaultKind> {
+        self.vm.peekn(n)
+    }
+
+    pub fn push(&mut self, value: u32) -> Result<(), FaultKind> {
+        self.vm.push(value)
+    }
+
+    pub fn pop(&mut self) -> Result<u32, FaultKind> {
+        self.vm.pop()
+    }
+
+    pub fn return_stack(&self) -> Vec<u32> {
+        self.read_range(RP_OFFSET, self.vm.rp)
+    }
+
+    pub fn set_return_stack(&mut self, values: &[u32]) -> Result<(), FaultKind> {
+        if values.len() * 4 > RP_SIZE {
+            return Err(FaultKind::ReturnStackOverflow);
+        }
+
+        self.vm.rp = self.write_range(RP_OFFSET, values);
+        Ok(())
+    }
+
+    pub fn return_depth(&self) -> usize {
+        (self.vm.rp - RP_OFFSET) / 4
+    }
+
+    pub fn rpush(&mut self, value: u32) -> Result<(), FaultKind> {
+        self.vm.rpush(value)
+    }
+
+    pub fn rpop(&mut self) -> Result<u32, FaultKind> {
+        self.vm.rpop()
+    }
+
+    /// All allocated local variables.
+    /// The last value is local `0` of the current frame.
+    pub fn locals(&self) -> Vec<u32> {
+        self.read_range(LOCAL_OFFSET, self.vm.local)
+    }
+
+    pub fn set_locals(&mut self, values: &[u32]) -> Result<(), FaultKind> {
+        if values.len() * 4 > LOCAL_SIZE {
+            return Err(FaultKind::LocalsOverflow);
+        }
+
+        self.vm.local = self.write_range(LOCAL_OFFSET, values);
+        Ok(())
+    }
+
+    /// Same as `GET`.
+    pub fn local(&self, index: u32) -> Result<u32, FaultKind> {
+        self.vm.lget(index)
+    }
+
+    /// Same as `SET`.
+    pub fn set_local(&mut self, index: u32, value: u32) -> Result<(), FaultKind> {
+        self.vm.lset(index, value)
+    }
+
+    fn read_range(&self, start: usize, end: usize) -> Vec<u32> {
+        (start..end).step_by(4).map(|addr| self.vm.mem_u32(addr)).collect()
+    }
+
+    /// Returns the end of the written range.
+    fn write_range(&mut self, start: usize, values: &[u32]) -> usize {
+        for (index, value) in values.iter().enumerate() {
+            self.vm.set_mem_u32(start + index * 4, *value);
+        }
+
+        start + values.len() * 4
+    }
+}
@@ -0,0 +1,272 @@
+use std::collections::BTreeSet;
+use std::fmt;
+use fox_bytecode::*;
+use fox_bytecode::symbols::Symbols;
+use fox_bytecode::disasm::Instruction;
+use crate::{VirtualMachine, Machine, Fault, Halt, MEM_SIZE};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Access {
+    Read,
+    Write,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Watchpoint {
+    pub addr: u32,
+    pub length: u32,
+    pub read: bool,
+    pub write: bool,
+}
+
+impl Watchpoint {
+    fn matches(&self, addr: u32, length: u32, access: Access) -> bool {
+        let enabled = match access {
+            Access::Read => self.read,
+            Access::Write => self.write,
+        };
+
+        let start = self.addr as u64;
+        let end = start + self.length as u64;
+        let access_start = addr as u64;
+        let access_end = access_start + length as u64;
+
+        enabled && access_start < end && start < access_end
+    }
+}
+
+/// Reason the debugger handed back control.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Stop {
+    /// Executed the requested instructions.
+    Stepped,
+    /// A `HALT` instruction was executed, or there was nothing to execute.
+    Halted,
+    /// About to execute the instruction at a breakpoint.
+    Breakpoint(u32),
+    /// The instruction at `ip` accessed a watched address.
+    Watchpoint { ip: u32, addr: u32, access: Access },
+}
+
+impl fmt::Display for Stop {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Stop::Stepped => write!(f, "stepped"),
+            Stop::Halted => write!(f, "halted"),
+            Stop::Breakpoint(addr) => write!(f, "breakpoint at 0x{:08x}", addr),
+            Stop::Watchpoint { ip, addr, access: Access::Read } => write!(f, "read of 0x{:08x} at 0x{:08x}", addr, ip),
+            Stop::Watchpoint { ip, addr, access: Access::Write } => write!(f, "write of 0x{:08x} at 0x{:08x}", addr, ip),
+        }
+    }
+}
+
+/// Drives a `VirtualMachine` one instruction at a time, stopping on breakpoints and watchpoints.
+/// Start a vector with `VirtualMachine::run_for` with a budget of `0` before stepping into it.
+pub struct Debugger {
+    breakpoints: BTreeSet<u32>,
+    watchpoints: Vec<Watchpoint>,
+    symbols: Symbols,
+}
+
+impl Default for Debugger {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Debugger {
+    pub fn new() -> Self {
+        Self {
+            breakpoints: BTreeSet::new(),
+            watchpoints: Vec::new(),
+            symbols: Symbols::default(),
+        }
+    }
+
+    pub fn symbols(&self) -> &Symbols {
+        &self.symbols
+    }
+
+    pub fn set_symbols(&mut self, symbols: Symbols) {
+        self.symbols = symbols;
+    }
+
+    /// Resolve a label or hexadecimal address.
+    pub fn resolve(&self, value: &str) -> Option<u32> {
+        if let Some(addr) = self.symbols.resolve(value) {
+            return Some(addr);
+        }
+
+        let value = value.trim_start_matches("0x");
+        u32::from_str_radix(value, 16).ok()
+    }
+
+    pub fn add_breakpoint(&mut self, addr: u32) {
+        self.breakpoints.insert(addr);
+    }
+
+    pub fn remove_breakpoint(&mut self, addr: u32) -> bool {
+        self.breakpoints.remove(&addr)
+    }
+
+    pub fn breakpoints(&self) -> impl Iterator<Item=u32> + '_ {
+        self.breakpoints.iter().copied()
+    }
+
+    pub fn add_watchpoint(&mut self, watchpoint: Watchpoint) {
+        self.watchpoints.push(watchpoint);
+    }
+
+    /// Remove all watchpoints starting at `addr`.
+    pub fn remove_watchpoint(&mut self, addr: u32) -> bool {
+        let length = self.watchpoints.len();
+        self.watchpoints.retain(|watchpoint| watchpoint.addr != addr);
+        length != self.watchpoints.len()
+    }
+
+    pub fn watchpoints(&self) -> &[Watchpoint] {
+        &self.watchpoints
+    }
+
+    /// Read directly attached memory, returning `None` if the range leaves it.
+    /// Device addresses are not read since reading them can have side effects.
+    pub fn read_memory(&self, vm: &mut VirtualMachine, addr: u32, length: u32) -> Option<Vec<u8>> {
+        let end = addr as usize + length as usize;
+        if end > MEM_SIZE {
+            return None;
+        }
+
+        let mut buf = vec![0; length as usize];
+        vm.dma().read(addr, &mut buf);
+        Some(buf)
+    }
+
+    /// Byte at `addr`, `None` outside of directly attached memory.
+    fn opcode(&self, vm: &mut VirtualMachine, addr: u32) -> Option<u8> {
+        self.read_memory(vm, addr, 1).map(|bytes| bytes[0])
+    }
+
+    /// Decode the instruction at `addr`, using the operand size from `OPCODES`.
+    pub fn instruction(&self, vm: &mut VirtualMachine, addr: u32) -> Option<Instruction> {
+        let op = self.opcode(vm, addr)?;
+        let size = Opcode::try_from(op).ok()?.operand_size();
+        let bytes = self.read_memory(vm, addr, 1 + size as u32)?;
+        Instruction::decode(&bytes)
+    }
+
+    /// Execute a single instruction.
+    pub fn step(&mut self, vm: &mut VirtualMachine, machine: &mut dyn Machine) -> Result<Stop, Fault> {
+        if !vm.is_running() {
+            return Ok(Stop::Halted);
+        }
+
+        let watch = self.check_watchpoints(vm);
+
+        if vm.step(machine)? == Halt::Halted {
+            return Ok(Stop::Halted);
+        }
+
+        Ok(watch.unwrap_or(Stop::Stepped))
+    }
+
+    /// Execute a single instruction, running called routines until they return.
+    pub fn step_over(&mut self, vm: &mut VirtualMachine, machine: &mut dyn Machine) -> Result<Stop, Fault> {
+        let ip = vm.cpu().ip();
+        if !vm.is_running() || self.opcode(vm, ip) != Some(OP_CALL) {
+            return self.step(vm, machine);
+        }
+
+        let depth = vm.cpu().return_depth();
+        let next = ip + 1;
+
+        let stop = self.step(vm, machine)?;
+        if stop != Stop::Stepped {
+            return Ok(stop);
+        }
+
+        self.run_until(vm, machine, |vm| vm.cpu().ip() == next && vm.cpu().return_depth() == depth)
+    }
+
+    /// Run until the current routine returns.
+    pub fn step_out(&mut self, vm: &mut VirtualMachine, machine: &mut dyn Machine) -> Result<Stop, Fault> {
+        let depth = vm.cpu().return_depth();
+        let mut returned = false;
+
+        loop {
+            if returned {
+                return Ok(Stop::Stepped);
+            }
+
+            let ip = vm.cpu().ip();
+            returned = vm.is_running() && self.opcode(vm, ip) == Some(OP_RET) && depth > 0 && vm.cpu().return_depth() == depth;
+
+            let stop = self.step(vm, machine)?;
+            if stop != Stop::Stepped {
+                return Ok(stop);
+            }
+
+            if let Some(stop) = self.check_breakpoint(vm) {
+                return Ok(stop);
+            }
+        }
+    }
+
+    /// Run until a breakpoint, watchpoint or `HALT`.
+    pub fn cont(&mut self, vm: &mut VirtualMachine, machine: &mut dyn Machine) -> Result<Stop, Fault> {
+        let stop = self.step(vm, machine)?;
+        if stop != Stop::Stepped {
+            return Ok(stop);
+        }
+
+        self.run_until(vm, machine, |_| false)
+    }
+
+    fn run_until(&mut self, vm: &mut VirtualMachine, machine: &mut dyn Machine, done: impl Fn(&mut VirtualMachine) -> bool) -> Result<Stop, Fault> {
+        loop {
+            if done(vm) {
+                return Ok(Stop::Stepped);
+            }
+
+            if let Some(stop) = self.check_breakpoint(vm) {
+                return Ok(stop);
+            }
+
+            let stop = self.step(vm, machine)?;
+            if stop != Stop::Stepped {
+                return Ok(stop);
+            }
+        }
+    }
+
+    fn check_breakpoint(&self, vm: &mut VirtualMachine) -> Option<Stop> {
+        let ip = vm.cpu().ip();
+        if vm.is_running() && self.breakpoints.contains(&ip) {
+            Some(Stop::Breakpoint(ip))
+        } else {
+            None
+        }
+    }
+
+    /// Decode the instruction at `ip` to find out which address it is about to access.
+    fn check_watchpoints(&self, vm: &mut VirtualMachine) -> Option<Stop> {
+        if self.watchpoints.is_empty() {
+            return None;
+        }
+
+        let ip = vm.cpu().ip();
+        let (length, access) = match self.opcode(vm, ip)? {
+            OP_LW => (4, Access::Read),
+            OP_LB => (1, Access::Read),
+            OP_SW => (4, Access::Write),
+            OP_SB => (1, Access::Write),
+            _ => return None,
+        };
+
+        let addr = vm.cpu().peek(0).ok()?;
+
+        self.watchpoints
+            .iter()
+            .any(|watchpoint| watchpoint.matches(addr, length, access))
+            .then_some(Stop::Watchpoint { ip, addr, access })
+    }
+}
@@ -1,4 +1,6 @@
 use super::Device;
+use crate::snapshot::*;
+use std::io::{self, Read, Write};
 use fox_bytecode::memory::*;
 use std::sync::mpsc::Receiver;
 
@@ -29,6 +31,12 @@ pub struct ConsoleDevice {
     receiver: Receiver<u8>,
 }
 
+impl Default for ConsoleDevice {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl ConsoleDevice {
     pub fn new() -> Self {
         Self {
@@ -57,12 +65,28 @@ impl ConsoleDevice {
         }
     }
 
+    /// Read a line from stdin, without the line ending.
+    /// The bytes read will not be passed on to the vector.
+    pub fn read_line(&mut self) -> String {
+        let mut line = Vec::new();
+
+        loop {
+            match self.receiver.recv().unwrap() {
+                b'\n' => break,
+                b'\r' => (),
+                value => line.push(value),
+            }
+        }
+
+        String::from_utf8_lossy(&line).into_owned()
+    }
+
     fn write_stdout(&mut self, value: u8) {
         use std::io::Write;
 
         let mut stream = std::io::stdout();
 
-        stream.write(&[value as u8]).unwrap();
+        stream.write_all(&[value]).unwrap();
         stream.flush().unwrap();
     }
 
@@ -71,49 +95,73 @@ impl ConsoleDevice {
 
         let mut stream = std::io::stderr();
 
-        stream.write(&[value as u8]).unwrap();
+        stream.write_all(&[value]).unwrap();
         stream.flush().unwrap();
     }
 }
 
 impl Device for ConsoleDevice {
-    fn read_u8(&mut self, addr: u32, _dma: crate::DirectMemoryAccess<'_>) -> u8 {
+    fn read_u8(&mut self, addr: u32, _dma: crate::DirectMemoryAccess<'_>) -> Option<u8> {
         let addr = addr - CONSOLE_BASE;
 
-        match addr {
+        let value = match addr {
             CONSOLE_READ => self.read,
-            _ => unimplemented!(),
-        }
+            _ => return None,
+        };
+
+        Some(value)
     }
 
-    fn write_u8(&mut self, addr: u32, value: u8, _dma: crate::DirectMemoryAccess<'_>) {
+    fn write_u8(&mut self, addr: u32, value: u8, _dma: crate::DirectMemoryAccess<'_>) -> Option<()> {
         let addr = addr - CONSOLE_BASE;
 
         match addr {
             CONSOLE_WRITE => self.write_stdout(value),
             CONSOLE_ERROR => self.write_stderr(value),
-            _ => unimplemented!(),
+            _ => return None,
         }
+
+        Some(())
     }
 
-    fn read_u32(&mut self, addr: u32, _dma: crate::DirectMemoryAccess<'_>) -> u32 {
+    fn read_u32(&mut self, addr: u32, _dma: crate::DirectMemoryAccess<'_>) -> Option<u32> {
         let addr = addr - CONSOLE_BASE;
 
-        match addr {
+        let value = match addr {
             CONSOLE_VECTOR => self.vector,
             CONSOLE_READ => self.read as _,
-            _ => unimplemented!(),
-        }
+            _ => return None,
+        };
+
+        Some(value)
     }
 
-    fn write_u32(&mut self, addr: u32, value: u32, _dma: crate::DirectMemoryAccess<'_>) {
+    fn write_u32(&mut self, addr: u32, value: u32, _dma: crate::DirectMemoryAccess<'_>) -> Option<()> {
         let addr = addr - CONSOLE_BASE;
 
         match addr {
             CONSOLE_VECTOR => self.vector = value,
             CONSOLE_WRITE => self.write_stdout(value as _),
             CONSOLE_ERROR => self.write_stderr(value as _),
-            _ => unimplemented!(),
+            _ => return None,
         }
+
+        Some(())
+    }
+}
+
+impl Snapshot for ConsoleDevice {
+    fn save(&self, out: &mut dyn Write) -> io::Result<()> {
+        write_u32(out, self.vector)?;
+        write_u32(out, self.read as _)
+    }
+
+    fn restore(&mut self, input: &mut dyn Read) -> io::Result<()> {
+        let vector = read_u32(input)?;
+        let read = u8::try_from(read_u32(input)?).map_err(|_| invalid_data("Invalid console read"))?;
+
+        self.vector = vector;
+        self.read = read;
+        Ok(())
     }
 }
@@ -1,6 +1,7 @@
 use std::fs::File;
-use std::io::{Read, Write};
+use std::io::{self, Read, Write};
 use super::Device;
+use crate::snapshot::*;
 use fox_bytecode::memory::*;
 
 #[derive(PartialEq)]
@@ -93,26 +94,18 @@ impl FileDevice {
 }
 
 impl Device for FileDevice {
-    fn read_u8(&mut self, addr: u32, _dma: crate::DirectMemoryAccess<'_>) -> u8 {
-        let addr = addr - self.base;
-
-        match addr {
-            _ => unimplemented!(),
-        }
+    fn read_u8(&mut self, _addr: u32, _dma: crate::DirectMemoryAccess<'_>) -> Option<u8> {
+        None
     }
 
-    fn write_u8(&mut self, addr: u32, _value: u8, _dma: crate::DirectMemoryAccess<'_>) {
-        let addr = addr - self.base;
-
-        match addr {
-            _ => unimplemented!(),
-        }
+    fn write_u8(&mut self, _addr: u32, _value: u8, _dma: crate::DirectMemoryAccess<'_>) -> Option<()> {
+        None
     }
 
-    fn read_u32(&mut self, addr: u32, _dma: crate::DirectMemoryAccess<'_>) -> u32 {
+    fn read_u32(&mut self, addr: u32, _dma: crate::DirectMemoryAccess<'_>) -> Option<u32> {
         let addr = addr - self.base;
 
-        match addr {
+        let value = match addr {
             FILE_VECTOR => 0,
             FILE_FILENAME => 0,
             FILE_LENGTH => self.length,
@@ -120,11 +113,13 @@ impl Device for FileDevice {
             FILE_STATUS => self.status,
             FILE_READ => 0,
             FILE_WRITE => 0,
-            _ => unimplemented!(),
-        }
+            _ => return None,
+        };
+
+        Some(value)
     }
 
-    fn write_u32(&mut self, addr: u32, value: u32, mut dma: crate::DirectMemoryAccess<'_>) {
+    fn write_u32(&mut self, addr: u32, value: u32, mut dma: crate::DirectMemoryAccess<'_>) -> Option<()> {
         let addr = addr - self.base;
 
         match addr {
@@ -138,11 +133,7 @@ impl Device for FileDevice {
                 self.buffer.resize(value as _, 0);
             },
             FILE_APPEND => {
-                self.append = if value == 0x01 {
-                    true
-                } else {
-                    false
-                };
+                self.append = value == 0x01;
             }
             FILE_STATUS => (),
             FILE_READ => {
@@ -153,7 +144,41 @@ impl Device for FileDevice {
                 dma.read(value, &mut self.buffer);
                 self.write_from_buffer();
             },
-            _ => unimplemented!(),
+            _ => return None,
         }
+
+        Some(())
+    }
+}
+
+/// Open files are not part of the snapshot, they are reopened on the next read or write.
+impl Snapshot for FileDevice {
+    fn save(&self, out: &mut dyn Write) -> io::Result<()> {
+        write_str(out, &self.filename)?;
+        write_bool(out, self.mode == Mode::Write)?;
+        write_bool(out, self.append)?;
+        write_u32(out, self.length)?;
+        write_u32(out, self.status)
+    }
+
+    fn restore(&mut self, input: &mut dyn Read) -> io::Result<()> {
+        // Both are copied from and to memory, so they can't be larger
+        let filename = read_str(input, MEM_SIZE as _)?;
+        let mode = if read_bool(input)? { Mode::Write } else { Mode::Read };
+        let append = read_bool(input)?;
+        let length = read_u32(input)?;
+        let status = read_u32(input)?;
+        if length > MEM_SIZE {
+            return Err(invalid_data("Invalid file length"));
+        }
+
+        self.file = None;
+        self.filename = filename;
+        self.mode = mode;
+        self.append = append;
+        self.length = length;
+        self.status = status;
+        self.buffer.resize(length as _, 0);
+        Ok(())
     }
 }
@@ -1,5 +1,7 @@
 use super::Device;
 use crate::DirectMemoryAccess;
+use crate::snapshot::*;
+use std::io::{self, Read, Write};
 use fox_bytecode::memory::{KEYBOARD_BASE, keyboard::*};
 
 pub enum Key {
@@ -26,6 +28,12 @@ pub struct KeyboardDevice {
     codepoint: u32,
 }
 
+impl Default for KeyboardDevice {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl KeyboardDevice {
     pub fn new() -> Self {
         Self {
@@ -51,41 +59,56 @@ impl KeyboardDevice {
 }
 
 impl Device for KeyboardDevice {
-    fn read_u8(&mut self, addr: u32, _dma: DirectMemoryAccess<'_>) -> u8 {
-        let addr = addr - KEYBOARD_BASE;
-
-        match addr {
-            _ => unimplemented!(),
-        }
+    fn read_u8(&mut self, _addr: u32, _dma: DirectMemoryAccess<'_>) -> Option<u8> {
+        None
     }
 
-    fn write_u8(&mut self, addr: u32, _value: u8, _dma: DirectMemoryAccess<'_>) {
-        let addr = addr - KEYBOARD_BASE;
-
-        match addr {
-            _ => unimplemented!(),
-        }
+    fn write_u8(&mut self, _addr: u32, _value: u8, _dma: DirectMemoryAccess<'_>) -> Option<()> {
+        None
     }
 
-    fn read_u32(&mut self, addr: u32, _dma: DirectMemoryAccess<'_>) -> u32 {
+    fn read_u32(&mut self, addr: u32, _dma: DirectMemoryAccess<'_>) -> Option<u32> {
         let addr = addr - KEYBOARD_BASE;
 
-        match addr {
+        let value = match addr {
             VECTOR => self.vector,
             CODEPOINT => self.codepoint,
             BUTTONS => self.buttons,
-            _ => unimplemented!(),
-        }
+            _ => return None,
+        };
+
+        Some(value)
     }
 
-    fn write_u32(&mut self, addr: u32, value: u32, _dma: DirectMemoryAccess<'_>) {
+    fn write_u32(&mut self, addr: u32, value: u32, _dma: DirectMemoryAccess<'_>) -> Option<()> {
         let addr = addr - KEYBOARD_BASE;
 
         match addr {
             VECTOR => self.vector = value,
             CODEPOINT => (),
             BUTTONS => (),
-            _ => unimplemented!(),
+            _ => return None,
         }
+
+        Some(())
+    }
+}
+
+impl Snapshot for KeyboardDevice {
+    fn save(&self, out: &mut dyn Write) -> io::Result<()> {
+        write_u32(out, self.vector)?;
+        write_u32(out, self.buttons)?;
+        write_u32(out, self.codepoint)
+    }
+
+    fn restore(&mut self, input: &mut dyn Read) -> io::Result<()> {
+        let vector = read_u32(input)?;
+        let buttons = read_u32(input)?;
+        let codepoint = read_u32(input)?;
+
+        self.vector = vector;
+        self.buttons = buttons;
+        self.codepoint = codepoint;
+        Ok(())
     }
 }
@@ -13,12 +13,13 @@ pub use mouse::MouseDevice;
 
 use crate::DirectMemoryAccess;
 
+/// Reads and writes return `None` for ports the device doesn't have.
 pub trait Device {
-    fn read_u8(&mut self, addr: u32, dma: DirectMemoryAccess<'_>) -> u8;
-    fn write_u8(&mut self, addr: u32, value: u8, dma: DirectMemoryAccess<'_>);
+    fn read_u8(&mut self, addr: u32, dma: DirectMemoryAccess<'_>) -> Option<u8>;
+    fn write_u8(&mut self, addr: u32, value: u8, dma: DirectMemoryAccess<'_>) -> Option<()>;
 
-    fn read_u32(&mut self, addr: u32, dma: DirectMemoryAccess<'_>) -> u32;
-    fn write_u32(&mut self, addr: u32, value: u32, dma: DirectMemoryAccess<'_>);
+    fn read_u32(&mut self, addr: u32, dma: DirectMemoryAccess<'_>) -> Option<u32>;
+    fn write_u32(&mut self, addr: u32, value: u32, dma: DirectMemoryAccess<'_>) -> Option<()>;
 }
 
 pub const fn match_device<const T: usize>(ranges: [(u32, u32); T], addr: u32) -> u32 {
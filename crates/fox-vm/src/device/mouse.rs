@@ -1,4 +1,6 @@
 use super::Device;
+use crate::snapshot::*;
+use std::io::{self, Read, Write};
 use fox_bytecode::memory::*;
 
 pub struct MouseDevice {
@@ -11,6 +13,12 @@ pub struct MouseDevice {
     middle: bool,
 }
 
+impl Default for MouseDevice {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl MouseDevice {
     pub fn new() -> Self {
         Self {
@@ -76,41 +84,68 @@ impl MouseDevice {
 }
 
 impl Device for MouseDevice {
-    fn read_u8(&mut self, addr: u32, _dma: crate::DirectMemoryAccess<'_>) -> u8 {
-        let addr = addr - MOUSE_BASE;
-
-        match addr {
-            _ => unimplemented!(),
-        }
+    fn read_u8(&mut self, _addr: u32, _dma: crate::DirectMemoryAccess<'_>) -> Option<u8> {
+        None
     }
 
-    fn write_u8(&mut self, addr: u32, _value: u8, _dma: crate::DirectMemoryAccess<'_>) {
-        let addr = addr - MOUSE_BASE;
-
-        match addr {
-            _ => unimplemented!(),
-        }
+    fn write_u8(&mut self, _addr: u32, _value: u8, _dma: crate::DirectMemoryAccess<'_>) -> Option<()> {
+        None
     }
 
-    fn read_u32(&mut self, addr: u32, _dma: crate::DirectMemoryAccess<'_>) -> u32 {
+    fn read_u32(&mut self, addr: u32, _dma: crate::DirectMemoryAccess<'_>) -> Option<u32> {
         let addr = addr - MOUSE_BASE;
 
-        match addr {
+        let value = match addr {
             MOUSE_VECTOR => self.vector,
             MOUSE_X => self.x,
             MOUSE_Y => self.y,
             MOUSE_FLAGS => self.flags(),
             MOUSE_BUTTON => self.button(),
-            _ => unimplemented!(),
-        }
+            _ => return None,
+        };
+
+        Some(value)
     }
 
-    fn write_u32(&mut self, addr: u32, value: u32, _dma: crate::DirectMemoryAccess<'_>) {
+    fn write_u32(&mut self, addr: u32, value: u32, _dma: crate::DirectMemoryAccess<'_>) -> Option<()> {
         let addr = addr - MOUSE_BASE;
 
         match addr {
             MOUSE_VECTOR => self.vector = value,
-            _ => unimplemented!(),
+            _ => return None,
         }
+
+        Some(())
+    }
+}
+
+impl Snapshot for MouseDevice {
+    fn save(&self, out: &mut dyn Write) -> io::Result<()> {
+        write_u32(out, self.vector)?;
+        write_bool(out, self.focus)?;
+        write_u32(out, self.x)?;
+        write_u32(out, self.y)?;
+        write_bool(out, self.left)?;
+        write_bool(out, self.right)?;
+        write_bool(out, self.middle)
+    }
+
+    fn restore(&mut self, input: &mut dyn Read) -> io::Result<()> {
+        let vector = read_u32(input)?;
+        let focus = read_bool(input)?;
+        let x = read_u32(input)?;
+        let y = read_u32(input)?;
+        let left = read_bool(input)?;
+        let right = read_bool(input)?;
+        let middle = read_bool(input)?;
+
+        self.vector = vector;
+        self.focus = focus;
+        self.x = x;
+        self.y = y;
+        self.left = left;
+        self.right = right;
+        self.middle = middle;
+        Ok(())
     }
 }
@@ -1,6 +1,8 @@
 use crate::DirectMemoryAccess;
-use fox_bytecode::memory::{screen, screen::command};
+use fox_bytecode::memory::{screen, screen::command, SCREEN_LAYER_LENGTH};
 use super::Device;
+use crate::snapshot::*;
+use std::io::{self, Read, Write};
 
 struct Sprite {
     data: [u8; 64],
@@ -26,7 +28,7 @@ impl Sprite {
                 (byte >> 3) & 0b1,
                 (byte >> 2) & 0b1,
                 (byte >> 1) & 0b1,
-                (byte >> 0) & 0b1,
+                byte & 0b1,
             ]
         });
 
@@ -68,8 +70,8 @@ pub trait Display {
     fn render(&mut self, buffer: &[u8], palette: &[u32; 16]);
 }
 
-// Palette from https://lospec.com/palette-list/sweetie-16
-const PALETTE: [u32; 16] = [
+/// Palette the screen starts with, from https://lospec.com/palette-list/sweetie-16
+pub const PALETTE: [u32; 16] = [
     0x1a1c2c,
     0x5d275d,
     0xb13e53,
@@ -187,23 +189,24 @@ impl<D: Display> ScreenDevice<D> {
     }
 
     fn cmd_sprite1(&mut self, addr: u32, dma: &mut DirectMemoryAccess<'_>) {
-        let mut x = dma.read_u32(addr + command::X);
+        let left = dma.read_u32(addr + command::X);
         let mut y = dma.read_u32(addr + command::Y);
         let mut source = dma.read_u32(addr + command::SOURCE);
 
-        let layer = (dma.read_u8(addr + command::COMMAND) & 0xF) as u8;
+        let layer = dma.read_u8(addr + command::COMMAND) & 0xF;
         let flags = dma.read_u8(addr + command::FLAGS);
         let color = dma.read_u8(addr + command::COLOR);
         let repeat = dma.read_u8(addr + command::REPEAT);
 
         let is_skip = flags & command::FLAGS_SKIP_CLEAR != 0;
 
-        let fg = (color >> 4) as u8;
-        let bg = (color & 0xF) as u8;
+        let fg = color >> 4;
+        let bg = color & 0xF;
         let width = 1 + (repeat >> 4);
         let height = 1 + (repeat & 0xF);
 
         for _ in 0..height {
+            let mut x = left;
             for _ in 0..width {
                 let mut sprite = Sprite::new();
                 sprite.read_1bpp(source, fg, bg, dma);
@@ -218,11 +221,11 @@ impl<D: Display> ScreenDevice<D> {
     }
 
     fn cmd_sprite4(&mut self, addr: u32, dma: &mut DirectMemoryAccess<'_>) {
-        let mut x = dma.read_u32(addr + command::X);
+        let left = dma.read_u32(addr + command::X);
         let mut y = dma.read_u32(addr + command::Y);
         let mut source = dma.read_u32(addr + command::SOURCE);
 
-        let layer = (dma.read_u8(addr + command::COMMAND) & 0xF) as u8;
+        let layer = dma.read_u8(addr + command::COMMAND) & 0xF;
         let flags = dma.read_u8(addr + command::FLAGS);
         let repeat = dma.read_u8(addr + command::REPEAT);
 
@@ -232,6 +235,7 @@ impl<D: Display> ScreenDevice<D> {
         let height = 1 + (repeat & 0xF);
 
         for _ in 0..height {
+            let mut x = left;
             for _ in 0..width {
                 let mut sprite = Sprite::new();
                 sprite.read_4bpp(source, dma);
@@ -245,19 +249,26 @@ impl<D: Display> ScreenDevice<D> {
         }
     }
 
-    fn process_command(&mut self, addr: u32, dma: &mut DirectMemoryAccess<'_>) {
-        let command = dma.read_u8(addr + command::COMMAND) >> 4;
-        match command {
+    /// Returns `None` for an unknown command or layer.
+    fn process_command(&mut self, addr: u32, dma: &mut DirectMemoryAccess<'_>) -> Option<()> {
+        let command = dma.read_u8(addr + command::COMMAND);
+        if (command & 0xF) as usize >= self.layers.len() {
+            return None;
+        }
+
+        match command >> 4 {
             command::COMMAND_CLEAR => self.cmd_clear(addr, dma),
             command::COMMAND_SPRITE1 => self.cmd_sprite1(addr, dma),
             command::COMMAND_SPRITE4 => self.cmd_sprite4(addr, dma),
-            _ => unimplemented!(),
+            _ => return None,
         }
+
+        Some(())
     }
 }
 
 impl<D: Display> Device for ScreenDevice<D> {
-    fn write_u32(&mut self, addr: u32, value: u32, mut dma: DirectMemoryAccess<'_>) {
+    fn write_u32(&mut self, addr: u32, value: u32, mut dma: DirectMemoryAccess<'_>) -> Option<()> {
         match addr {
             screen::VECTOR => {
                 self.vector = value;
@@ -275,7 +286,7 @@ impl<D: Display> Device for ScreenDevice<D> {
             },
             screen::CMD_ADDR => {
                 for index in 0..self.cmd_length {
-                    self.process_command(value + index*16, &mut dma);
+                    self.process_command(value + index * command::SIZE, &mut dma)?;
                 }
             },
             screen::ZOOM => {
@@ -286,12 +297,14 @@ impl<D: Display> Device for ScreenDevice<D> {
                 let index = addr - screen::PALETTE0;
                 self.palette[index as usize] = value;
             },
-            _ => unimplemented!(),
+            _ => return None,
         }
+
+        Some(())
     }
 
-    fn read_u32(&mut self, addr: u32, _dma: DirectMemoryAccess<'_>) -> u32 {
-        match addr {
+    fn read_u32(&mut self, addr: u32, _dma: DirectMemoryAccess<'_>) -> Option<u32> {
+        let value = match addr {
             screen::VECTOR => {
                 self.vector
             },
@@ -311,51 +324,120 @@ impl<D: Display> Device for ScreenDevice<D> {
                 let index = addr - screen::PALETTE0;
                 self.palette[index as usize]
             },
-            _ => unimplemented!(),
-        }
+            _ => return None,
+        };
+
+        Some(value)
     }
 
-    fn write_u8(&mut self, addr: u32, value: u8, _dma: DirectMemoryAccess<'_>) {
+    fn write_u8(&mut self, addr: u32, value: u8, _dma: DirectMemoryAccess<'_>) -> Option<()> {
         match addr {
             screen::LAYER0..=screen::LAYER0_TOP => {
                 let index = addr - screen::LAYER0;
-                self.layers[0][index as usize] = value;
+                *self.layers[0].get_mut(index as usize)? = value;
             },
             screen::LAYER1..=screen::LAYER1_TOP => {
                 let index = addr - screen::LAYER1;
-                self.layers[1][index as usize] = value;
+                *self.layers[1].get_mut(index as usize)? = value;
             },
             screen::LAYER2..=screen::LAYER2_TOP => {
                 let index = addr - screen::LAYER2;
-                self.layers[2][index as usize] = value;
+                *self.layers[2].get_mut(index as usize)? = value;
             },
             screen::LAYER3..=screen::LAYER3_TOP => {
                 let index = addr - screen::LAYER3;
-                self.layers[3][index as usize] = value;
+                *self.layers[3].get_mut(index as usize)? = value;
             },
-            _ => unimplemented!(),
+            _ => return None,
         }
+
+        Some(())
     }
 
-    fn read_u8(&mut self, addr: u32, _dma: DirectMemoryAccess<'_>) -> u8 {
-        match addr {
+    fn read_u8(&mut self, addr: u32, _dma: DirectMemoryAccess<'_>) -> Option<u8> {
+        let value = match addr {
             screen::LAYER0..=screen::LAYER0_TOP => {
                 let index = addr - screen::LAYER0;
-                self.layers[0][index as usize]
+                *self.layers[0].get(index as usize)?
             },
             screen::LAYER1..=screen::LAYER1_TOP => {
                 let index = addr - screen::LAYER1;
-                self.layers[1][index as usize]
+                *self.layers[1].get(index as usize)?
             },
             screen::LAYER2..=screen::LAYER2_TOP => {
                 let index = addr - screen::LAYER2;
-                self.layers[2][index as usize]
+                *self.layers[2].get(index as usize)?
             },
             screen::LAYER3..=screen::LAYER3_TOP => {
                 let index = addr - screen::LAYER3;
-                self.layers[3][index as usize]
+                *self.layers[3].get(index as usize)?
             },
-            _ => unimplemented!(),
+            _ => return None,
+        };
+
+        Some(value)
+    }
+}
+
+impl<D: Display> Snapshot for ScreenDevice<D> {
+    fn save(&self, out: &mut dyn Write) -> io::Result<()> {
+        write_u32(out, self.vector)?;
+        write_u32(out, self.width)?;
+        write_u32(out, self.height)?;
+        write_u32(out, self.zoom)?;
+        write_u32(out, self.cmd_length)?;
+
+        for color in self.palette {
+            write_u32(out, color)?;
         }
+
+        for layer in &self.layers {
+            write_bytes(out, layer)?;
+        }
+
+        Ok(())
+    }
+
+    fn restore(&mut self, input: &mut dyn Read) -> io::Result<()> {
+        let vector = read_u32(input)?;
+        let width = read_u32(input)?;
+        let height = read_u32(input)?;
+        let zoom = read_u32(input)?;
+        let cmd_length = read_u32(input)?;
+
+        let mut palette = [0; 16];
+        for color in &mut palette {
+            *color = read_u32(input)?;
+        }
+
+        let mut layers = Vec::new();
+        for _ in 0..self.layers.len() {
+            layers.push(read_bytes(input, SCREEN_LAYER_LENGTH as _)?);
+        }
+
+        // Layers have to fit in their part of the address space, and the window in pixels has to fit in a u32
+        let fits = (width as u64 * height as u64) / 2 <= SCREEN_LAYER_LENGTH as u64
+            && width.checked_mul(zoom).is_some()
+            && height.checked_mul(zoom).is_some();
+        if !fits {
+            return Err(invalid_data("Invalid screen size"));
+        }
+
+        self.vector = vector;
+        self.width = width;
+        self.height = height;
+        self.zoom = zoom;
+        self.cmd_length = cmd_length;
+        self.palette = palette;
+        self.resize();
+
+        // The display might not accept the saved size, so keep the layers at the resized size.
+        for (dst, src) in self.layers.iter_mut().zip(layers) {
+            let size = dst.len();
+            *dst = src;
+            dst.resize(size, 0);
+        }
+
+        Ok(())
     }
 }
@@ -1,66 +1,133 @@
 use super::Device;
+use crate::Fault;
+use crate::snapshot::*;
+use std::io::{self, Read, Write};
 use fox_bytecode::memory::*;
 use std::vec::IntoIter;
 
-fn args() -> IntoIter<u8> {
-    let mut args: Vec<u8> = Vec::new();
+fn args(args: impl Iterator<Item=String>) -> IntoIter<u8> {
+    let mut bytes: Vec<u8> = Vec::new();
 
-    // Skip first 2 arguments since they're the name of the vm and the rom
-    for arg in std::env::args().skip(2) {
-        args.extend(arg.as_bytes());
-        args.push(0);
+    for arg in args {
+        bytes.extend(arg.as_bytes());
+        bytes.push(0);
     }
 
-    args.into_iter()
+    bytes.into_iter()
 }
 
 pub struct SystemDevice {
+    pub vector: u32,
     pub exit: Option<u32>,
     args: IntoIter<u8>,
+    fault: u32,
+    fault_ip: u32,
+    fault_operand: u32,
+}
+
+impl Default for SystemDevice {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl SystemDevice {
     pub fn new() -> Self {
+        // Skip first 2 arguments since they're the name of the vm and the rom
+        Self::with_args(std::env::args().skip(2))
+    }
+
+    pub fn with_args(args: impl Iterator<Item=String>) -> Self {
         Self {
-            args: args(),
+            vector: 0,
+            args: self::args(args),
             exit: None,
+            fault: FAULT_NONE,
+            fault_ip: 0,
+            fault_operand: 0,
         }
     }
+
+    /// Record `fault` for the guest to read, returning the fault vector.
+    pub fn fault(&mut self, fault: Fault) -> u32 {
+        self.fault = fault.kind.code();
+        self.fault_ip = fault.ip;
+        self.fault_operand = fault.kind.operand();
+        self.vector
+    }
 }
 
 impl Device for SystemDevice {
-    fn read_u8(&mut self, addr: u32, _dma: crate::DirectMemoryAccess<'_>) -> u8 {
+    fn read_u8(&mut self, addr: u32, _dma: crate::DirectMemoryAccess<'_>) -> Option<u8> {
         let addr = addr - SYSTEM_BASE;
 
-        match addr {
+        let value = match addr {
             SYSTEM_READ => self.args.next().unwrap_or(0),
-            _ => unimplemented!("0x{:08x}", addr),
-        }
-    }
+            _ => return None,
+        };
 
-    fn write_u8(&mut self, addr: u32, value: u8, _dma: crate::DirectMemoryAccess<'_>) {
-        let addr = addr - SYSTEM_BASE;
+        Some(value)
+    }
 
-        match addr {
-            _ => unimplemented!("0x{:08x}=0x{:02x}", addr, value),
-        }
+    fn write_u8(&mut self, _addr: u32, _value: u8, _dma: crate::DirectMemoryAccess<'_>) -> Option<()> {
+        None
     }
 
-    fn read_u32(&mut self, addr: u32, _dma: crate::DirectMemoryAccess<'_>) -> u32 {
+    fn read_u32(&mut self, addr: u32, _dma: crate::DirectMemoryAccess<'_>) -> Option<u32> {
         let addr = addr - SYSTEM_BASE;
 
-        match addr {
+        let value = match addr {
+            SYSTEM_VECTOR => self.vector,
             SYSTEM_READ => self.args.next().unwrap_or(0) as _,
-            _ => unimplemented!("0x{:08x}", addr),
-        }
+            SYSTEM_FAULT => self.fault,
+            SYSTEM_FAULT_IP => self.fault_ip,
+            SYSTEM_FAULT_OPERAND => self.fault_operand,
+            _ => return None,
+        };
+
+        Some(value)
     }
 
-    fn write_u32(&mut self, addr: u32, value: u32, _dma: crate::DirectMemoryAccess<'_>) {
+    fn write_u32(&mut self, addr: u32, value: u32, _dma: crate::DirectMemoryAccess<'_>) -> Option<()> {
         let addr = addr - SYSTEM_BASE;
 
         match addr {
+            SYSTEM_VECTOR => self.vector = value,
             SYSTEM_EXIT => self.exit = Some(value),
-            _ => unimplemented!("0x{:08x}=0x{:08x}", addr, value),
+            _ => return None,
         }
+
+        Some(())
+    }
+}
+
+impl Snapshot for SystemDevice {
+    fn save(&self, out: &mut dyn Write) -> io::Result<()> {
+        write_u32(out, self.vector)?;
+        write_bool(out, self.exit.is_some())?;
+        write_u32(out, self.exit.unwrap_or(0))?;
+        write_bytes(out, self.args.as_slice())?;
+        write_u32(out, self.fault)?;
+        write_u32(out, self.fault_ip)?;
+        write_u32(out, self.fault_operand)
+    }
+
+    fn restore(&mut self, input: &mut dyn Read) -> io::Result<()> {
+        let vector = read_u32(input)?;
+        let has_exit = read_bool(input)?;
+        let exit = read_u32(input)?;
+        // The rom reads the arguments into memory, so more wouldn't fit anyway
+        let args = read_bytes(input, MEM_SIZE as _)?;
+        let fault = read_u32(input)?;
+        let fault_ip = read_u32(input)?;
+        let fault_operand = read_u32(input)?;
+
+        self.vector = vector;
+        self.exit = if has_exit { Some(exit) } else { None };
+        self.args = args.into_iter();
+        self.fault = fault;
+        self.fault_ip = fault_ip;
+        self.fault_operand = fault_operand;
+        Ok(())
     }
 }
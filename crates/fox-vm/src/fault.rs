@@ -0,0 +1,128 @@
+use std::fmt;
+use fox_bytecode::memory::*;
+
+/// Reason `VirtualMachine` stopped executing without faulting.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Halt {
+    /// A `HALT` instruction was executed.
+    Halted,
+    /// The vector was `0`, so nothing was executed.
+    NoVector,
+    /// Ran out of instructions before a `HALT`, use `resume` to continue.
+    Yielded,
+}
+
+/// The kind of trap the CPU encountered.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum FaultKind {
+    StackOverflow,
+    StackUnderflow,
+    ReturnStackOverflow,
+    ReturnStackUnderflow,
+    LocalsOverflow,
+    LocalsUnderflow,
+    /// Contains the opcode that could not be decoded.
+    IllegalOpcode(u8),
+    DivideByZero,
+    /// Contains the address that was jumped to.
+    InvalidJump(u32),
+    /// Contains the address of a word access straddling the end of memory,
+    /// or of a device access nothing is mapped at.
+    InvalidAccess(u32),
+}
+
+/// A recoverable CPU fault.
+/// The stacks are left as they were when the fault occurred, unless the fault vector was entered.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Fault {
+    pub kind: FaultKind,
+    /// Address of the faulting instruction.
+    pub ip: u32,
+    /// Kind and address of the fault the fault vector was handling, if this fault happened in it.
+    pub original: Option<(FaultKind, u32)>,
+}
+
+impl Fault {
+    pub fn new(kind: FaultKind, ip: u32) -> Self {
+        Self {
+            kind,
+            ip,
+            original: None,
+        }
+    }
+}
+
+impl FaultKind {
+    /// Value reported to the guest through the system device.
+    pub fn code(&self) -> u32 {
+        match self {
+            FaultKind::StackOverflow => FAULT_STACK_OVERFLOW,
+            FaultKind::StackUnderflow => FAULT_STACK_UNDERFLOW,
+            FaultKind::ReturnStackOverflow => FAULT_RETURN_STACK_OVERFLOW,
+            FaultKind::ReturnStackUnderflow => FAULT_RETURN_STACK_UNDERFLOW,
+            FaultKind::LocalsOverflow => FAULT_LOCALS_OVERFLOW,
+            FaultKind::LocalsUnderflow => FAULT_LOCALS_UNDERFLOW,
+            FaultKind::IllegalOpcode(_) => FAULT_ILLEGAL_OPCODE,
+            FaultKind::DivideByZero => FAULT_DIVIDE_BY_ZERO,
+            FaultKind::InvalidJump(_) => FAULT_INVALID_JUMP,
+            FaultKind::InvalidAccess(_) => FAULT_INVALID_ACCESS,
+        }
+    }
+
+    /// The opcode or address the fault contains, `0` if it has none.
+    pub fn operand(&self) -> u32 {
+        match self {
+            FaultKind::IllegalOpcode(op) => *op as u32,
+            FaultKind::InvalidJump(addr) | FaultKind::InvalidAccess(addr) => *addr,
+            _ => 0,
+        }
+    }
+
+    /// Inverse of `code` and `operand`.
+    pub fn from_code(code: u32, operand: u32) -> Option<Self> {
+        let kind = match code {
+            FAULT_STACK_OVERFLOW => FaultKind::StackOverflow,
+            FAULT_STACK_UNDERFLOW => FaultKind::StackUnderflow,
+            FAULT_RETURN_STACK_OVERFLOW => FaultKind::ReturnStackOverflow,
+            FAULT_RETURN_STACK_UNDERFLOW => FaultKind::ReturnStackUnderflow,
+            FAULT_LOCALS_OVERFLOW => FaultKind::LocalsOverflow,
+            FAULT_LOCALS_UNDERFLOW => FaultKind::LocalsUnderflow,
+            FAULT_ILLEGAL_OPCODE => FaultKind::IllegalOpcode(u8::try_from(operand).ok()?),
+            FAULT_DIVIDE_BY_ZERO => FaultKind::DivideByZero,
+            FAULT_INVALID_JUMP => FaultKind::InvalidJump(operand),
+            FAULT_INVALID_ACCESS => FaultKind::InvalidAccess(operand),
+            _ => return None,
+        };
+
+        Some(kind)
+    }
+}
+
+impl fmt::Display for FaultKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FaultKind::StackOverflow => write!(f, "stack overflow"),
+            FaultKind::StackUnderflow => write!(f, "stack underflow"),
+            FaultKind::ReturnStackOverflow => write!(f, "return stack overflow"),
+            FaultKind::ReturnStackUnderflow => write!(f, "return stack underflow"),
+            FaultKind::LocalsOverflow => write!(f, "locals overflow"),
+            FaultKind::LocalsUnderflow => write!(f, "locals underflow"),
+            FaultKind::IllegalOpcode(op) => write!(f, "illegal opcode 0x{:02x}", op),
+            FaultKind::DivideByZero => write!(f, "divide by zero"),
+            FaultKind::InvalidJump(addr) => write!(f, "invalid jump to 0x{:08x}", addr),
+            FaultKind::InvalidAccess(addr) => write!(f, "invalid access at 0x{:08x}", addr),
+        }
+    }
+}
+
+impl fmt::Display for Fault {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at 0x{:08x}", self.kind, self.ip)?;
+        if let Some((kind, ip)) = self.original {
+            write!(f, ", while handling {} at 0x{:08x}", kind, ip)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for Fault {}
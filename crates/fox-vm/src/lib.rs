@@ -1,4 +1,11 @@
 pub mod device;
+pub mod snapshot;
+pub mod debugger;
+mod fault;
+mod cpu;
+
+pub use fault::{Fault, FaultKind, Halt};
+pub use cpu::CpuState;
 
 use fox_bytecode::*;
 
@@ -21,7 +28,7 @@ impl<'a> DirectMemoryAccess<'a> {
 
     pub fn read_u32(&self, addr: u32) -> u32 {
         u32::from_le_bytes([
-               self.read_u8(addr + 0),
+               self.read_u8(addr),
                self.read_u8(addr + 1),
                self.read_u8(addr + 2),
                self.read_u8(addr + 3),
@@ -31,7 +38,7 @@ impl<'a> DirectMemoryAccess<'a> {
     pub fn write_u32(&mut self, addr: u32, value: u32) {
         let [a,b,c,d] = u32::to_le_bytes(value);
 
-        self.write_u8(addr + 0, a);
+        self.write_u8(addr, a);
         self.write_u8(addr + 1, b);
         self.write_u8(addr + 2, c);
         self.write_u8(addr + 3, d);
@@ -70,51 +77,81 @@ impl<'a> DirectMemoryAccess<'a> {
     }
 }
 
+/// Reads and writes return `None` for addresses nothing is mapped at,
+/// which the CPU turns into an `InvalidAccess` fault.
 pub trait Machine {
-    fn write_u32(&mut self, addr: u32, value: u32, dma: DirectMemoryAccess<'_>);
-    fn read_u32(&mut self, addr: u32, dma: DirectMemoryAccess<'_>) -> u32; 
+    fn write_u32(&mut self, addr: u32, value: u32, dma: DirectMemoryAccess<'_>) -> Option<()>;
+    fn read_u32(&mut self, addr: u32, dma: DirectMemoryAccess<'_>) -> Option<u32>;
 
-    fn write_u8(&mut self, addr: u32, value: u8, dma: DirectMemoryAccess<'_>) {
+    fn write_u8(&mut self, addr: u32, value: u8, dma: DirectMemoryAccess<'_>) -> Option<()> {
         self.write_u32(addr, value as _, dma)
     }
-    fn read_u8(&mut self, addr: u32, dma: DirectMemoryAccess<'_>) -> u8 {
-        (self.read_u32(addr, dma) & 0xFF) as _
+    fn read_u8(&mut self, addr: u32, dma: DirectMemoryAccess<'_>) -> Option<u8> {
+        self.read_u32(addr, dma).map(|value| (value & 0xFF) as _)
+    }
+
+    /// Called when the CPU traps.
+    /// Returns the fault vector to continue at, or `0` to stop and return the fault.
+    fn fault(&mut self, _fault: Fault) -> u32 {
+        0
     }
 }
 
-use fox_bytecode::memory::RESET_VECTOR;
 
-const MEM_SIZE: usize = 16 * 1024 * 1024; // 16 Megabytes
-const SP_SIZE: usize = 1024; // bytes
-const RP_SIZE: usize = 1024; // bytes
-const LOCAL_SIZE: usize = 1024; // bytes
+use fox_bytecode::memory::{self, RESET_VECTOR};
+use fox_bytecode::symbols::Symbols;
+use fox_bytecode::lines::LineTable;
+use snapshot::*;
+use std::io::{self, Read, Write};
+use std::collections::VecDeque;
 
-const SP_OFFSET: usize = MEM_SIZE - SP_SIZE;
-const RP_OFFSET: usize = MEM_SIZE - SP_SIZE - RP_SIZE;
-const LOCAL_OFFSET: usize = MEM_SIZE - SP_SIZE - RP_SIZE - LOCAL_SIZE;
+const MEM_SIZE: usize = memory::MEM_SIZE as usize;
+const SP_SIZE: usize = memory::STACK_SIZE as usize;
+const RP_SIZE: usize = memory::RSTACK_SIZE as usize;
+const LOCAL_SIZE: usize = memory::LOCALS_SIZE as usize;
+
+const SP_OFFSET: usize = memory::STACK_BASE as usize;
+const RP_OFFSET: usize = memory::RSTACK_BASE as usize;
+const LOCAL_OFFSET: usize = memory::LOCALS_BASE as usize;
 
 pub struct VirtualMachine {
     mem: Box<[u8]>,
-    ip: *const u8,
-    sp: *mut u32,
-    rp: *mut u32,
-    local: *mut u32,
+    ip: u32,
+    sp: usize,
+    rp: usize,
+    local: usize,
+    /// Started executing a vector which hasn't halted yet.
+    running: bool,
+    /// Fault the fault vector is currently handling.
+    trapped: Option<Fault>,
+    /// Vectors signalled while another one was running, started once it halts.
+    pending: VecDeque<u32>,
+    /// Used to show labels and source lines in `DBG` output.
+    symbols: Symbols,
+    lines: LineTable,
+}
+
+impl Default for VirtualMachine {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl VirtualMachine {
     pub fn new() -> Self {
-        let mut mem = vec![0; MEM_SIZE].into_boxed_slice();
-        let ip = unsafe { mem.as_ptr().offset(RESET_VECTOR as _) };
-        let sp = unsafe { mem.as_mut_ptr().offset(SP_OFFSET as _) };
-        let rp = unsafe { mem.as_mut_ptr().offset(RP_OFFSET as _) };
-        let local = unsafe { mem.as_mut_ptr().offset(LOCAL_OFFSET as _) };
+        let mem = vec![0; MEM_SIZE].into_boxed_slice();
 
         Self {
             mem,
-            ip,
-            sp: sp as _,
-            rp: rp as _,
-            local: local as _,
+            ip: RESET_VECTOR,
+            sp: SP_OFFSET,
+            rp: RP_OFFSET,
+            local: LOCAL_OFFSET,
+            running: false,
+            trapped: None,
+            pending: VecDeque::new(),
+            symbols: Symbols::default(),
+            lines: LineTable::default(),
         }
     }
 
@@ -124,430 +161,688 @@ impl VirtualMachine {
         }
     }
 
+    pub fn cpu(&mut self) -> CpuState<'_> {
+        CpuState::new(self)
+    }
+
+    pub fn symbols(&self) -> &Symbols {
+        &self.symbols
+    }
+
+    pub fn set_symbols(&mut self, symbols: Symbols) {
+        self.symbols = symbols;
+    }
+
+    pub fn lines(&self) -> &LineTable {
+        &self.lines
+    }
+
+    pub fn set_lines(&mut self, lines: LineTable) {
+        self.lines = lines;
+    }
+
+    /// Describe `addr` using the loaded symbols and source lines, like `loop+0x1 test.fox:7:4`.
+    pub fn location(&self, addr: u32) -> String {
+        let symbol = self.symbols.format(addr);
+
+        match self.lines.lookup(addr) {
+            Some(line) => format!("{} {}", symbol, line),
+            None => symbol,
+        }
+    }
+
     pub fn load(&mut self, data: &[u8]) {
         let start = RESET_VECTOR as usize;
         let end = start + data.len();
         self.mem[start..end].copy_from_slice(data);
     }
 
-    pub fn run(&mut self, machine: &mut dyn Machine, ip: u32) {
+    /// Run from `ip` until a `HALT` instruction or a fault.
+    /// Faults are first handed to the machine's fault vector, a fault inside of that vector is
+    /// returned.
+    pub fn run(&mut self, machine: &mut dyn Machine, ip: u32) -> Result<Halt, Fault> {
         if ip == 0 {
-            return;
+            return Ok(Halt::NoVector);
         }
 
-        self.ip = unsafe { self.mem.as_ptr().offset(ip as _) };
+        self.enter(ip)?;
 
         loop {
-            match self.next_u8() {
-                OP_HALT => {
-                    break;
-                },
-                OP_DBG => {
-                    self.dump();
-                },
-
-                OP_LITW => {
-                    let number = self.next_u32();
-                    self.push(number);
-                },
-                OP_DUP => {
-                    let value = self.peek();
-                    self.push(value);
-                },
-                OP_DROP => {
-                    self.pop();
-                }
-                OP_SWAP => {
-                    let b = self.pop();
-                    let a = self.pop();
-                    self.push(b);
-                    self.push(a);
-                },
-                OP_OVER => {
-                    let b = self.pop();
-                    let a = self.peek();
-                    self.push(b);
-                    self.push(a);
-                },
-                OP_ROT => {
-                    let c = self.pop();
-                    let b = self.pop();
-                    let a = self.pop();
-                    self.push(b);
-                    self.push(c);
-                    self.push(a);
-                },
-                OP_LITB => {
-                    let number = self.next_u8();
-                    self.push(number as u32);
-                },
-                OP_PICK => {
-                    let index = self.pop();
-                    let value = self.peekn(index);
-                    self.push(value);
-                },
-
-                OP_ADD => {
-                    let b = self.pop();
-                    let a = self.pop();
-                    self.push(a.wrapping_add(b));
-                },
-                OP_SUB => {
-                    let b = self.pop();
-                    let a = self.pop();
-                    self.push(a.wrapping_sub(b));
-                },
-                OP_MUL => {
-                    let b = self.pop();
-                    let a = self.pop();
-                    self.push(a.wrapping_mul(b));
-                }
-                OP_DIV => {
-                    let b = self.pop();
-                    let a = self.pop();
-                    self.push(a.wrapping_div(b));
-                }
-                OP_AND => {
-                    let b = self.pop();
-                    let a = self.pop();
-                    self.push(a & b);
-                }
-                OP_OR => {
-                    let b = self.pop();
-                    let a = self.pop();
-                    self.push(a | b);
-                }
-                OP_XOR => {
-                    let b = self.pop();
-                    let a = self.pop();
-                    self.push(a ^ b);
-                }
-                OP_SHL => {
-                    let b = self.pop();
-                    let a = self.pop();
-                    self.push(a << b);
-                }
-                OP_SHR => {
-                    let b = self.pop();
-                    let a = self.pop();
-                    self.push(a >> b);
-                }
-                OP_INC => {
-                    let a = self.pop();
-                    self.push(a.wrapping_add(1));
-                }
-                OP_DEC => {
-                    let a = self.pop();
-                    self.push(a.wrapping_sub(1));
-                }
-                OP_SAR => {
-                    let b = self.pop() as i32;
-                    let a = self.pop() as i32;
-                    let value = a >> b;
-                    self.push(value as u32);
-                }
-                OP_NOT => {
-                    let a = self.pop();
-                    self.push(!a);
-                }
+            match self.step(machine)? {
+                Halt::Yielded => (),
+                halt => return Ok(halt),
+            }
+        }
+    }
 
-                OP_LW => {
-                    let addr = self.pop();
-                    let value = self.read_u32(addr, machine);
-                    self.push(value as _);
-                },
-                OP_SW => {
-                    let addr = self.pop();
-                    let value = self.pop();
-                    self.write_u32(addr, value, machine);
-                },
-                OP_SB => {
-                    let addr = self.pop();
-                    let value = self.pop();
-                    self.write_u8(addr, (value & 0xFF) as u8, machine);
-                }
-                OP_LB => {
-                    let addr = self.pop();
-                    let value = self.read_u8(addr, machine);
-                    self.push(value as _);
-                },
-
-                OP_EQU => {
-                    let b = self.pop();
-                    let a = self.pop();
-                    let out = if a == b { 1 } else { 0 };
-                    self.push(out);
-                },
-                OP_GT => {
-                    let b = self.pop();
-                    let a = self.pop();
-                    let out = if a > b { 1 } else { 0 };
-                    self.push(out);
-                },
-                OP_LT => {
-                    let b = self.pop();
-                    let a = self.pop();
-                    let out = if a < b { 1 } else { 0 };
-                    self.push(out);
-                },
-                OP_GTE => {
-                    let b = self.pop();
-                    let a = self.pop();
-                    let out = if a >= b { 1 } else { 0 };
-                    self.push(out);
-                },
-                OP_LTE => {
-                    let b = self.pop();
-                    let a = self.pop();
-                    let out = if a <= b { 1 } else { 0 };
-                    self.push(out);
-                },
-                OP_NEQ => {
-                    let b = self.pop();
-                    let a = self.pop();
-                    let out = if a != b { 1 } else { 0 };
-                    self.push(out);
-                },
-
-                OP_JMP => {
-                    let addr = self.pop();
-                    unsafe {
-                        self.ip = self.mem.as_mut_ptr().offset(addr as _);
-                    }
-                },
-                OP_JZ => {
-                    let addr = self.pop();
-                    let cond = self.pop();
-                    if cond == 0 {
-                        unsafe {
-                            self.ip = self.mem.as_mut_ptr().offset(addr as _);
-                        }
-                    }
-                },
-                OP_JNZ => {
-                    let addr = self.pop();
-                    let cond = self.pop();
-                    if cond != 0 {
-                        unsafe {
-                            self.ip = self.mem.as_mut_ptr().offset(addr as _);
-                        }
-                    }
-                },
-                OP_CALL => {
-                    let addr = self.pop();
-                    let ip = unsafe { self.ip.offset_from(self.mem.as_ptr()) };
-                    self.rpush(ip as _);
-                    unsafe {
-                        self.ip = self.mem.as_mut_ptr().offset(addr as _);
-                    }
-                }
-                OP_RET => {
-                    let addr = self.rpop();
-                    unsafe {
-                        self.ip = self.mem.as_mut_ptr().offset(addr as _);
-                    }
-                }
-                OP_RPUSH => {
-                    let value = self.pop();
-                    self.rpush(value);
-                }
-                OP_RPOP => {
-                    let value = self.rpop();
-                    self.push(value);
-                }
-                OP_RPEEK => {
-                    let value = self.rpeek();
-                    self.push(value);
-                }
-                OP_RDROP => {
-                    self.rpop();
-                }
-                OP_BEGIN => {
-                    let length = self.pop();
-                    self.lbegin(length);
-                },
-                OP_END => {
-                    let length = self.pop();
-                    self.lend(length);
-                },
-                OP_GET => {
-                    let addr = self.pop();
-                    self.push(self.lget(addr));
-                },
-                OP_SET => {
-                    let addr = self.pop();
-                    let value = self.pop();
-                    self.lset(addr, value);
-                },
-                x => unimplemented!("0x{:02x}", x),
+    /// Run from `ip` for at most `max_instructions` instructions.
+    /// Returns `Halt::Yielded` if it ran out, use `resume` to continue.
+    pub fn run_for(&mut self, machine: &mut dyn Machine, ip: u32, max_instructions: usize) -> Result<Halt, Fault> {
+        if ip == 0 {
+            return Ok(Halt::NoVector);
+        }
+
+        self.enter(ip)?;
+        self.resume(machine, max_instructions)
+    }
+
+    /// Continue executing from the current `ip` for at most `max_instructions` instructions.
+    pub fn resume(&mut self, machine: &mut dyn Machine, max_instructions: usize) -> Result<Halt, Fault> {
+        for _ in 0..max_instructions {
+            match self.step(machine)? {
+                Halt::Yielded => (),
+                halt => return Ok(halt),
             }
         }
+
+        Ok(Halt::Yielded)
+    }
+
+    /// Execute a single instruction at the current `ip`.
+    /// Returns `Halt::Yielded` unless the instruction was a `HALT`.
+    pub fn step(&mut self, machine: &mut dyn Machine) -> Result<Halt, Fault> {
+        let ip = self.ip;
+        match self.execute(machine) {
+            Ok(true) => {
+                self.running = false;
+                self.trapped = None;
+                Ok(Halt::Halted)
+            },
+            Ok(false) => Ok(Halt::Yielded),
+            Err(kind) => {
+                self.fault(machine, Fault::new(kind, ip))?;
+                Ok(Halt::Yielded)
+            },
+        }
+    }
+
+    /// Start `vector` once the running vector halts, see `run_pending`.
+    /// Vectors that are `0` or already waiting are ignored, devices only keep their latest event anyway.
+    pub fn signal(&mut self, vector: u32) {
+        if vector != 0 && !self.pending.contains(&vector) {
+            self.pending.push_back(vector);
+        }
     }
 
-    //TODO Add local variables?
-    fn dump(&self) {
-        unsafe {
-            let ip = self.mem.as_ptr();
-            let mut sp = self.mem.as_ptr().offset(SP_OFFSET as _) as *const u32;
-            let mut rp = self.mem.as_ptr().offset(RP_OFFSET as _) as *const u32;
+    /// Continue the running vector, then start the signalled vectors in order, each for at most `max_instructions`.
+    /// Returns `Halt::Yielded` if one of them hasn't halted yet, call this again to continue.
+    pub fn run_pending(&mut self, machine: &mut dyn Machine, max_instructions: usize) -> Result<Halt, Fault> {
+        let mut halt = Halt::NoVector;
+        if self.running {
+            halt = self.resume(machine, max_instructions)?;
+        }
 
-            eprintln!("IP: 0x{:08x}", self.ip.offset_from(ip));
-            //eprintln!("INST: 0x{:02x}", *self.ip);
+        while halt != Halt::Yielded {
+            let Some(vector) = self.pending.pop_front() else {
+                break;
+            };
+            halt = self.run_for(machine, vector, max_instructions)?;
+        }
+
+        Ok(halt)
+    }
+
+    /// Returns `true` if a vector was started with `run_for` and hasn't halted yet.
+    pub fn is_running(&self) -> bool {
+        self.running
+    }
+
+    /// Start executing a new vector, abandoning the current one if it is still running.
+    fn enter(&mut self, ip: u32) -> Result<(), Fault> {
+        self.running = false;
+        self.trapped = None;
+        self.jump(ip).map_err(|kind| Fault::new(kind, ip))?;
+        self.running = true;
+        Ok(())
+    }
 
-            eprint!("SP: ");
-            while sp < self.sp as *const u32 {
-                eprint!("0x{:08x} ", *sp);
-                sp = sp.offset(1);
+    /// Hand `fault` to the machine's fault vector, returning it if there is none.
+    /// A fault in the fault vector is returned with the fault it was handling.
+    fn fault(&mut self, machine: &mut dyn Machine, fault: Fault) -> Result<(), Fault> {
+        if let Some(original) = self.trapped.take() {
+            self.running = false;
+            return Err(Fault {
+                original: Some((original.kind, original.ip)),
+                ..fault
+            });
+        }
+
+        let vector = machine.fault(fault);
+        if vector == 0 {
+            self.running = false;
+            return Err(fault);
+        }
+
+        if let Err(kind) = self.trap(vector) {
+            self.running = false;
+            return Err(Fault {
+                original: Some((fault.kind, fault.ip)),
+                ..Fault::new(kind, fault.ip)
+            });
+        }
+        self.trapped = Some(fault);
+
+        Ok(())
+    }
+
+    /// Enter the fault vector with empty stacks, since they can't be trusted anymore.
+    fn trap(&mut self, vector: u32) -> Result<(), FaultKind> {
+        self.sp = SP_OFFSET;
+        self.rp = RP_OFFSET;
+        self.local = LOCAL_OFFSET;
+        self.jump(vector)
+    }
+
+    /// Execute a single instruction.
+    /// Returns `true` if the instruction was a `HALT`.
+    fn execute(&mut self, machine: &mut dyn Machine) -> Result<bool, FaultKind> {
+        match self.next_u8()? {
+            OP_HALT => {
+                return Ok(true);
+            },
+            OP_DBG => {
+                self.dump();
+            },
+
+            OP_LITW => {
+                let number = self.next_u32()?;
+                self.push(number)?;
+            },
+            OP_DUP => {
+                let value = self.peek()?;
+                self.push(value)?;
+            },
+            OP_DROP => {
+                self.pop()?;
+            }
+            OP_SWAP => {
+                let b = self.pop()?;
+                let a = self.pop()?;
+                self.push(b)?;
+                self.push(a)?;
+            },
+            OP_OVER => {
+                let b = self.pop()?;
+                let a = self.peek()?;
+                self.push(b)?;
+                self.push(a)?;
+            },
+            OP_ROT => {
+                let c = self.pop()?;
+                let b = self.pop()?;
+                let a = self.pop()?;
+                self.push(b)?;
+                self.push(c)?;
+                self.push(a)?;
+            },
+            OP_LITB => {
+                let number = self.next_u8()?;
+                self.push(number as u32)?;
+            },
+            OP_PICK => {
+                let index = self.pop()?;
+                let value = self.peekn(index)?;
+                self.push(value)?;
+            },
+
+            OP_ADD => {
+                let b = self.pop()?;
+                let a = self.pop()?;
+                self.push(a.wrapping_add(b))?;
+            },
+            OP_SUB => {
+                let b = self.pop()?;
+                let a = self.pop()?;
+                self.push(a.wrapping_sub(b))?;
+            },
+            OP_MUL => {
+                let b = self.pop()?;
+                let a = self.pop()?;
+                self.push(a.wrapping_mul(b))?;
+            }
+            OP_DIV => {
+                let b = self.pop()?;
+                let a = self.pop()?;
+                let value = a.checked_div(b).ok_or(FaultKind::DivideByZero)?;
+                self.push(value)?;
+            }
+            OP_AND => {
+                let b = self.pop()?;
+                let a = self.pop()?;
+                self.push(a & b)?;
+            }
+            OP_OR => {
+                let b = self.pop()?;
+                let a = self.pop()?;
+                self.push(a | b)?;
+            }
+            OP_XOR => {
+                let b = self.pop()?;
+                let a = self.pop()?;
+                self.push(a ^ b)?;
+            }
+            OP_SHL => {
+                let b = self.pop()?;
+                let a = self.pop()?;
+                self.push(a.wrapping_shl(b))?;
+            }
+            OP_SHR => {
+                let b = self.pop()?;
+                let a = self.pop()?;
+                self.push(a.wrapping_shr(b))?;
+            }
+            OP_INC => {
+                let a = self.pop()?;
+                self.push(a.wrapping_add(1))?;
+            }
+            OP_DEC => {
+                let a = self.pop()?;
+                self.push(a.wrapping_sub(1))?;
+            }
+            OP_SAR => {
+                let b = self.pop()?;
+                let a = self.pop()? as i32;
+                let value = a.wrapping_shr(b);
+                self.push(value as u32)?;
+            }
+            OP_NOT => {
+                let a = self.pop()?;
+                self.push(!a)?;
             }
-            eprintln!();
 
-            eprint!("RP: ");
-            while rp < self.rp as *const u32 {
-                eprint!("0x{:08x} ", *rp);
-                rp = rp.offset(1);
+            OP_LW => {
+                let addr = self.pop()?;
+                let value = self.read_u32(addr, machine)?;
+                self.push(value as _)?;
+            },
+            OP_SW => {
+                let addr = self.pop()?;
+                let value = self.pop()?;
+                self.write_u32(addr, value, machine)?;
+            },
+            OP_SB => {
+                let addr = self.pop()?;
+                let value = self.pop()?;
+                self.write_u8(addr, (value & 0xFF) as u8, machine)?;
             }
-            eprintln!();
+            OP_LB => {
+                let addr = self.pop()?;
+                let value = self.read_u8(addr, machine)?;
+                self.push(value as _)?;
+            },
+
+            OP_EQU => {
+                let b = self.pop()?;
+                let a = self.pop()?;
+                let out = if a == b { 1 } else { 0 };
+                self.push(out)?;
+            },
+            OP_GT => {
+                let b = self.pop()?;
+                let a = self.pop()?;
+                let out = if a > b { 1 } else { 0 };
+                self.push(out)?;
+            },
+            OP_LT => {
+                let b = self.pop()?;
+                let a = self.pop()?;
+                let out = if a < b { 1 } else { 0 };
+                self.push(out)?;
+            },
+            OP_GTE => {
+                let b = self.pop()?;
+                let a = self.pop()?;
+                let out = if a >= b { 1 } else { 0 };
+                self.push(out)?;
+            },
+            OP_LTE => {
+                let b = self.pop()?;
+                let a = self.pop()?;
+                let out = if a <= b { 1 } else { 0 };
+                self.push(out)?;
+            },
+            OP_NEQ => {
+                let b = self.pop()?;
+                let a = self.pop()?;
+                let out = if a != b { 1 } else { 0 };
+                self.push(out)?;
+            },
+
+            OP_JMP => {
+                let addr = self.pop()?;
+                self.jump(addr)?;
+            },
+            OP_JZ => {
+                let addr = self.pop()?;
+                let cond = self.pop()?;
+                if cond == 0 {
+                    self.jump(addr)?;
+                }
+            },
+            OP_JNZ => {
+                let addr = self.pop()?;
+                let cond = self.pop()?;
+                if cond != 0 {
+                    self.jump(addr)?;
+                }
+            },
+            OP_CALL => {
+                let addr = self.pop()?;
+                self.rpush(self.ip)?;
+                self.jump(addr)?;
+            }
+            OP_RET => {
+                let addr = self.rpop()?;
+                self.jump(addr)?;
+            }
+            OP_RPUSH => {
+                let value = self.pop()?;
+                self.rpush(value)?;
+            }
+            OP_RPOP => {
+                let value = self.rpop()?;
+                self.push(value)?;
+            }
+            OP_RPEEK => {
+                let value = self.rpeek()?;
+                self.push(value)?;
+            }
+            OP_RDROP => {
+                self.rpop()?;
+            }
+            OP_BEGIN => {
+                let length = self.pop()?;
+                self.lbegin(length)?;
+            },
+            OP_END => {
+                let length = self.pop()?;
+                self.lend(length)?;
+            },
+            OP_GET => {
+                let addr = self.pop()?;
+                let value = self.lget(addr)?;
+                self.push(value)?;
+            },
+            OP_SET => {
+                let addr = self.pop()?;
+                let value = self.pop()?;
+                self.lset(addr, value)?;
+            },
+            x => return Err(FaultKind::IllegalOpcode(x)),
+        }
 
-            eprintln!();
+        Ok(false)
+    }
+
+    fn dump(&mut self) {
+        let ip = self.location(self.ip);
+        let cpu = self.cpu();
+
+        eprintln!("IP: 0x{:08x} ({})", cpu.ip(), ip);
+
+        eprint!("SP: ");
+        for value in cpu.stack() {
+            eprint!("0x{:08x} ", value);
         }
+        eprintln!();
+
+        eprint!("RP: ");
+        for value in cpu.return_stack() {
+            eprint!("0x{:08x} ", value);
+        }
+        eprintln!();
+
+        eprint!("LOCAL: ");
+        for value in cpu.locals() {
+            eprint!("0x{:08x} ", value);
+        }
+        eprintln!();
+
+        eprintln!();
     }
-    fn read_u32(&mut self, addr: u32, machine: &mut dyn Machine) -> u32 {
+
+    fn mem_u32(&self, addr: usize) -> u32 {
+        u32::from_le_bytes([
+           self.mem[addr],
+           self.mem[addr + 1],
+           self.mem[addr + 2],
+           self.mem[addr + 3],
+        ])
+    }
+
+    fn set_mem_u32(&mut self, addr: usize, value: u32) {
+        let [a,b,c,d] = u32::to_le_bytes(value);
+        self.mem[addr] = a;
+        self.mem[addr + 1] = b;
+        self.mem[addr + 2] = c;
+        self.mem[addr + 3] = d;
+    }
+
+    fn read_u32(&mut self, addr: u32, machine: &mut dyn Machine) -> Result<u32, FaultKind> {
         if addr < MEM_SIZE as _ {
-            let addr = addr as usize;
-            u32::from_le_bytes([
-               self.mem[addr + 0],
-               self.mem[addr + 1],
-               self.mem[addr + 2],
-               self.mem[addr + 3],
-            ])
+            if addr as usize + 4 > MEM_SIZE {
+                return Err(FaultKind::InvalidAccess(addr));
+            }
+            Ok(self.mem_u32(addr as _))
         } else {
-            machine.read_u32(addr, self.dma())
+            machine.read_u32(addr, self.dma()).ok_or(FaultKind::InvalidAccess(addr))
         }
     }
 
-    fn write_u32(&mut self, addr: u32, value: u32, machine: &mut dyn Machine) {
+    fn write_u32(&mut self, addr: u32, value: u32, machine: &mut dyn Machine) -> Result<(), FaultKind> {
         if addr < MEM_SIZE as _ {
-            let addr = addr as usize;
-            let [a,b,c,d] = u32::to_le_bytes(value);
-            self.mem[addr + 0] = a;
-            self.mem[addr + 1] = b;
-            self.mem[addr + 2] = c;
-            self.mem[addr + 3] = d;
+            if addr as usize + 4 > MEM_SIZE {
+                return Err(FaultKind::InvalidAccess(addr));
+            }
+            self.set_mem_u32(addr as _, value);
         } else {
-            machine.write_u32(addr, value, self.dma());
+            machine.write_u32(addr, value, self.dma()).ok_or(FaultKind::InvalidAccess(addr))?;
         }
+
+        Ok(())
     }
 
-    fn write_u8(&mut self, addr: u32, value: u8, machine: &mut dyn Machine) {
+    fn write_u8(&mut self, addr: u32, value: u8, machine: &mut dyn Machine) -> Result<(), FaultKind> {
         if addr < MEM_SIZE as _ {
             let addr = addr as usize;
             self.mem[addr] = value;
         } else {
-            machine.write_u8(addr, value, self.dma());
+            machine.write_u8(addr, value, self.dma()).ok_or(FaultKind::InvalidAccess(addr))?;
         }
+
+        Ok(())
     }
 
-    fn read_u8(&mut self, addr: u32, machine: &mut dyn Machine) -> u8 {
+    fn read_u8(&mut self, addr: u32, machine: &mut dyn Machine) -> Result<u8, FaultKind> {
         if addr < MEM_SIZE as _ {
-            self.mem[addr as usize]
+            Ok(self.mem[addr as usize])
         } else {
-            machine.read_u8(addr, self.dma())
+            machine.read_u8(addr, self.dma()).ok_or(FaultKind::InvalidAccess(addr))
         }
     }
 
-    fn next_u32(&mut self) -> u32 {
-        u32::from_le_bytes([self.next_u8(), self.next_u8(), self.next_u8(), self.next_u8()])
+    fn jump(&mut self, addr: u32) -> Result<(), FaultKind> {
+        if addr as usize >= MEM_SIZE {
+            return Err(FaultKind::InvalidJump(addr));
+        }
+
+        self.ip = addr;
+        Ok(())
     }
 
-    fn next_u8(&mut self) -> u8 {
-        unsafe {
-            let value = *self.ip;
-            self.ip = self.ip.offset(1);
-            value
-        }
+    fn next_u32(&mut self) -> Result<u32, FaultKind> {
+        Ok(u32::from_le_bytes([self.next_u8()?, self.next_u8()?, self.next_u8()?, self.next_u8()?]))
     }
 
-    fn push(&mut self, value: u32) {
-        //TODO add overflow check
-        unsafe {
-            *self.sp = value;
-            self.sp = self.sp.offset(1);
+    fn next_u8(&mut self) -> Result<u8, FaultKind> {
+        // Running off the end of memory is treated as jumping out of it.
+        let value = *self.mem.get(self.ip as usize).ok_or(FaultKind::InvalidJump(self.ip))?;
+        self.ip += 1;
+        Ok(value)
+    }
+
+    fn push(&mut self, value: u32) -> Result<(), FaultKind> {
+        if self.sp + 4 > SP_OFFSET + SP_SIZE {
+            return Err(FaultKind::StackOverflow);
         }
+
+        self.set_mem_u32(self.sp, value);
+        self.sp += 4;
+        Ok(())
+    }
+
+    fn pop(&mut self) -> Result<u32, FaultKind> {
+        let value = self.peek()?;
+        self.sp -= 4;
+        Ok(value)
+    }
+
+    fn peek(&self) -> Result<u32, FaultKind> {
+        self.peekn(0)
     }
 
-    fn pop(&mut self) -> u32 {
-        //TODO Add underflow check
-        unsafe {
-            self.sp = self.sp.offset(-1);
-            *self.sp
+    fn peekn(&self, n: u32) -> Result<u32, FaultKind> {
+        let offset = (n as usize + 1) * 4;
+        if self.sp < SP_OFFSET + offset {
+            return Err(FaultKind::StackUnderflow);
         }
+
+        Ok(self.mem_u32(self.sp - offset))
     }
 
-    fn peek(&self) -> u32 {
-        //TODO Add underflow check
-        unsafe {
-            *self.sp.offset(-1)
+    fn rpush(&mut self, value: u32) -> Result<(), FaultKind> {
+        if self.rp + 4 > RP_OFFSET + RP_SIZE {
+            return Err(FaultKind::ReturnStackOverflow);
         }
+
+        self.set_mem_u32(self.rp, value);
+        self.rp += 4;
+        Ok(())
     }
 
+    fn rpop(&mut self) -> Result<u32, FaultKind> {
+        let value = self.rpeek()?;
+        self.rp -= 4;
+        Ok(value)
+    }
 
-    fn peekn(&self, n: u32) -> u32 {
-        //TODO Add underflow/overflow check
-        let offset = -1 - n as isize;
-        dbg!(offset);
-        unsafe {
-            *self.sp.offset(offset)
+    fn rpeek(&self) -> Result<u32, FaultKind> {
+        if self.rp < RP_OFFSET + 4 {
+            return Err(FaultKind::ReturnStackUnderflow);
         }
+
+        Ok(self.mem_u32(self.rp - 4))
     }
 
-    fn rpush(&mut self, value: u32) {
-        //TODO add overflow check
-        unsafe {
-            *self.rp = value;
-            self.rp = self.rp.offset(1);
+    fn lbegin(&mut self, length: u32) -> Result<(), FaultKind> {
+        let length = length as usize * 4;
+        if length > LOCAL_OFFSET + LOCAL_SIZE - self.local {
+            return Err(FaultKind::LocalsOverflow);
         }
+
+        self.local += length;
+        Ok(())
     }
 
-    fn rpop(&mut self) -> u32 {
-        //TODO Add underflow check
-        unsafe {
-            self.rp = self.rp.offset(-1);
-            *self.rp
+    fn lend(&mut self, length: u32) -> Result<(), FaultKind> {
+        let length = length as usize * 4;
+        if length > self.local - LOCAL_OFFSET {
+            return Err(FaultKind::LocalsUnderflow);
         }
+
+        self.local -= length;
+        Ok(())
     }
 
-    fn rpeek(&self) -> u32 {
-        //TODO Add underflow check
-        unsafe {
-            *self.rp.offset(-1)
+    /// Address of local variable `addr`, counting down from the top of the current frame.
+    fn local_addr(&self, addr: u32) -> Result<usize, FaultKind> {
+        let offset = (addr as usize + 1) * 4;
+        if self.local < LOCAL_OFFSET + offset {
+            return Err(FaultKind::LocalsOverflow);
         }
+
+        Ok(self.local - offset)
     }
 
+    fn lget(&self, addr: u32) -> Result<u32, FaultKind> {
+        Ok(self.mem_u32(self.local_addr(addr)?))
+    }
 
-    fn lbegin(&mut self, length: u32) {
-        unsafe {
-            self.local = self.local.offset(length as _);
-        }
+    fn lset(&mut self, addr: u32, value: u32) -> Result<(), FaultKind> {
+        let addr = self.local_addr(addr)?;
+        self.set_mem_u32(addr, value);
+        Ok(())
     }
+}
 
-    fn lend(&mut self, length: u32) {
-        let offset = -(length as isize);
-        unsafe {
-            self.local = self.local.offset(offset);
+impl Snapshot for VirtualMachine {
+    fn save(&self, out: &mut dyn Write) -> io::Result<()> {
+        write_bytes(out, &self.mem)?;
+        write_u32(out, self.ip)?;
+        write_u32(out, self.sp as _)?;
+        write_u32(out, self.rp as _)?;
+        write_u32(out, self.local as _)?;
+        write_bool(out, self.running)?;
+        write_bool(out, self.trapped.is_some())?;
+        if let Some(fault) = self.trapped {
+            write_u32(out, fault.kind.code())?;
+            write_u32(out, fault.kind.operand())?;
+            write_u32(out, fault.ip)?;
         }
-    }
 
-    fn lget(&self, addr: u32) -> u32 {
-        let offset = -1 - addr as isize;
-        unsafe {
-            *self.local.offset(offset)
+        write_u32(out, self.pending.len() as _)?;
+        for vector in &self.pending {
+            write_u32(out, *vector)?;
         }
-    }
+        Ok(())
+    }
+
+    fn restore(&mut self, input: &mut dyn Read) -> io::Result<()> {
+        let mem = read_bytes(input, MEM_SIZE)?;
+        let ip = read_u32(input)?;
+        let sp = read_u32(input)? as usize;
+        let rp = read_u32(input)? as usize;
+        let local = read_u32(input)? as usize;
+        let running = read_bool(input)?;
+        let trapped = if read_bool(input)? {
+            let code = read_u32(input)?;
+            let operand = read_u32(input)?;
+            let ip = read_u32(input)?;
+            let kind = FaultKind::from_code(code, operand).ok_or_else(|| invalid_data("Invalid fault"))?;
+            Some(Fault::new(kind, ip))
+        } else {
+            None
+        };
 
-    fn lset(&self, addr: u32, value: u32) {
-        let offset = -1 - addr as isize;
-        unsafe {
-            *self.local.offset(offset) = value;
+        let count = read_u32(input)?;
+        let mut pending = VecDeque::new();
+        for _ in 0..count {
+            pending.push_back(read_u32(input)?);
         }
+
+        // Registers are used as indices into memory, so they must be valid.
+        let valid = mem.len() == MEM_SIZE
+            && (ip as usize) < MEM_SIZE
+            && (SP_OFFSET..=SP_OFFSET + SP_SIZE).contains(&sp)
+            && (RP_OFFSET..=RP_OFFSET + RP_SIZE).contains(&rp)
+            && (LOCAL_OFFSET..=LOCAL_OFFSET + LOCAL_SIZE).contains(&local)
+            && (sp - SP_OFFSET).is_multiple_of(4)
+            && (rp - RP_OFFSET).is_multiple_of(4)
+            && (local - LOCAL_OFFSET).is_multiple_of(4);
+
+        if !valid {
+            return Err(invalid_data("Invalid CPU state"));
+        }
+
+        self.mem.copy_from_slice(&mem);
+        self.ip = ip;
+        self.sp = sp;
+        self.rp = rp;
+        self.local = local;
+        self.running = running;
+        self.trapped = trapped;
+        self.pending = pending;
+
+        Ok(())
     }
 }
@@ -0,0 +1,111 @@
+use std::io::{self, Read, Write};
+
+/// Magic bytes at the start of every save state.
+pub const MAGIC: [u8; 4] = *b"FOXS";
+/// Bump this whenever the layout of any snapshot changes.
+pub const VERSION: u32 = 4;
+
+/// Save and restore state to a binary stream.
+/// `restore` must read back exactly what `save` wrote, in the same order.
+/// Save states can be corrupt, so `restore` validates everything before changing `self`, and leaves it as it was on errors.
+pub trait Snapshot {
+    fn save(&self, out: &mut dyn Write) -> io::Result<()>;
+    fn restore(&mut self, input: &mut dyn Read) -> io::Result<()>;
+}
+
+/// Restore every one of `parts` from `input`, or none of them if any part is invalid.
+pub fn restore_all(parts: &mut [&mut dyn Snapshot], input: &mut dyn Read) -> io::Result<()> {
+    let mut backup = Vec::new();
+    for part in parts.iter() {
+        part.save(&mut backup)?;
+    }
+
+    for index in 0..parts.len() {
+        if let Err(err) = parts[index].restore(input) {
+            // What the parts saved themselves can always be restored
+            let mut backup = backup.as_slice();
+            for part in &mut parts[..=index] {
+                part.restore(&mut backup)?;
+            }
+            return Err(err);
+        }
+    }
+
+    Ok(())
+}
+
+pub fn write_header(out: &mut dyn Write) -> io::Result<()> {
+    out.write_all(&MAGIC)?;
+    write_u32(out, VERSION)
+}
+
+pub fn read_header(input: &mut dyn Read) -> io::Result<()> {
+    let mut magic = [0; 4];
+    input.read_exact(&mut magic)?;
+    if magic != MAGIC {
+        return Err(invalid_data("Not a save state"));
+    }
+
+    let version = read_u32(input)?;
+    if version != VERSION {
+        return Err(invalid_data(format!("Unsupported save state version {}", version)));
+    }
+
+    Ok(())
+}
+
+pub fn invalid_data(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+pub fn write_u32(out: &mut dyn Write, value: u32) -> io::Result<()> {
+    out.write_all(&value.to_le_bytes())
+}
+
+pub fn read_u32(input: &mut dyn Read) -> io::Result<u32> {
+    let mut buf = [0; 4];
+    input.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+pub fn write_bool(out: &mut dyn Write, value: bool) -> io::Result<()> {
+    out.write_all(&[value as u8])
+}
+
+pub fn read_bool(input: &mut dyn Read) -> io::Result<bool> {
+    let mut buf = [0; 1];
+    input.read_exact(&mut buf)?;
+    Ok(buf[0] != 0)
+}
+
+/// Write length-prefixed bytes.
+pub fn write_bytes(out: &mut dyn Write, value: &[u8]) -> io::Result<()> {
+    write_u32(out, value.len() as _)?;
+    out.write_all(value)
+}
+
+/// Read length-prefixed bytes, at most `max` of them so a corrupt length can't allocate more.
+pub fn read_bytes(input: &mut dyn Read, max: usize) -> io::Result<Vec<u8>> {
+    let length = read_u32(input)?;
+    if length as usize > max {
+        return Err(invalid_data(format!("Length {} is more than the maximum of {}", length, max)));
+    }
+
+    let mut value = Vec::new();
+    input.take(length as _).read_to_end(&mut value)?;
+
+    if value.len() != length as usize {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+
+    Ok(value)
+}
+
+pub fn write_str(out: &mut dyn Write, value: &str) -> io::Result<()> {
+    write_bytes(out, value.as_bytes())
+}
+
+/// Read a length-prefixed string of at most `max` bytes.
+pub fn read_str(input: &mut dyn Read, max: usize) -> io::Result<String> {
+    String::from_utf8(read_bytes(input, max)?).map_err(|_| invalid_data("String contains non UTF8 bytes"))
+}
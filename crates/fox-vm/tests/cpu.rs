@@ -0,0 +1,93 @@
+use fox_bytecode::*;
+use fox_bytecode::memory::{MEM_SIZE, STACK_SIZE, RSTACK_SIZE, LOCALS_SIZE};
+use fox_vm::{VirtualMachine, Machine, DirectMemoryAccess, FaultKind};
+
+struct NoDevices;
+
+impl Machine for NoDevices {
+    fn write_u32(&mut self, _addr: u32, _value: u32, _dma: DirectMemoryAccess<'_>) -> Option<()> {
+        None
+    }
+
+    fn read_u32(&mut self, _addr: u32, _dma: DirectMemoryAccess<'_>) -> Option<u32> {
+        None
+    }
+}
+
+/// One more value than fits in a stack of `size` bytes.
+fn oversize(size: u32) -> Vec<u32> {
+    (0..size / 4 + 1).collect()
+}
+
+#[test]
+fn get_and_set_round_trip() {
+    let mut vm = VirtualMachine::new();
+    let mut cpu = vm.cpu();
+
+    cpu.set_ip(0x1234).unwrap();
+    cpu.set_stack(&[1, 2, 3]).unwrap();
+    cpu.set_return_stack(&[4, 5]).unwrap();
+    cpu.set_locals(&[6, 7]).unwrap();
+
+    assert_eq!(cpu.ip(), 0x1234);
+    assert_eq!(cpu.stack(), [1, 2, 3]);
+    assert_eq!(cpu.stack_depth(), 3);
+    assert_eq!(cpu.peek(0), Ok(3));
+    assert_eq!(cpu.return_stack(), [4, 5]);
+    assert_eq!(cpu.return_depth(), 2);
+    assert_eq!(cpu.locals(), [6, 7]);
+
+    cpu.push(8).unwrap();
+    cpu.rpush(9).unwrap();
+    assert_eq!(cpu.stack(), [1, 2, 3, 8]);
+    assert_eq!(cpu.return_stack(), [4, 5, 9]);
+    assert_eq!(cpu.pop(), Ok(8));
+    assert_eq!(cpu.rpop(), Ok(9));
+}
+
+#[test]
+fn full_stacks_can_be_seeded() {
+    let mut vm = VirtualMachine::new();
+    let mut cpu = vm.cpu();
+
+    let stack: Vec<u32> = (0..STACK_SIZE / 4).collect();
+    cpu.set_stack(&stack).unwrap();
+    assert_eq!(cpu.stack(), stack);
+    assert_eq!(cpu.push(0), Err(FaultKind::StackOverflow));
+
+    let return_stack: Vec<u32> = (0..RSTACK_SIZE / 4).collect();
+    cpu.set_return_stack(&return_stack).unwrap();
+    assert_eq!(cpu.return_stack(), return_stack);
+    assert_eq!(cpu.rpush(0), Err(FaultKind::ReturnStackOverflow));
+}
+
+#[test]
+fn oversize_seeds_are_rejected() {
+    let mut vm = VirtualMachine::new();
+    let mut cpu = vm.cpu();
+    cpu.set_stack(&[1]).unwrap();
+    cpu.set_return_stack(&[2]).unwrap();
+    cpu.set_locals(&[3]).unwrap();
+    let ip = cpu.ip();
+
+    assert_eq!(cpu.set_stack(&oversize(STACK_SIZE)), Err(FaultKind::StackOverflow));
+    assert_eq!(cpu.set_return_stack(&oversize(RSTACK_SIZE)), Err(FaultKind::ReturnStackOverflow));
+    assert_eq!(cpu.set_locals(&oversize(LOCALS_SIZE)), Err(FaultKind::LocalsOverflow));
+    assert_eq!(cpu.set_ip(MEM_SIZE), Err(FaultKind::InvalidJump(MEM_SIZE)));
+
+    // Nothing was written, not even the part that fits.
+    assert_eq!(cpu.stack(), [1]);
+    assert_eq!(cpu.return_stack(), [2]);
+    assert_eq!(cpu.locals(), [3]);
+    assert_eq!(cpu.ip(), ip);
+}
+
+#[test]
+fn seeded_stack_is_used_by_a_routine() {
+    let mut vm = VirtualMachine::new();
+    vm.dma().write(0x100, &[OP_ADD, OP_HALT]);
+    vm.cpu().set_stack(&[2, 3]).unwrap();
+
+    vm.run(&mut NoDevices, 0x100).unwrap();
+    assert_eq!(vm.cpu().stack(), [5]);
+}
@@ -0,0 +1,52 @@
+use fox_bytecode::*;
+use fox_bytecode::memory::MEM_SIZE;
+use fox_vm::{VirtualMachine, Machine, DirectMemoryAccess, FaultKind};
+use fox_vm::debugger::{Debugger, Watchpoint};
+
+struct NoDevices;
+
+impl Machine for NoDevices {
+    fn write_u32(&mut self, _addr: u32, _value: u32, _dma: DirectMemoryAccess<'_>) -> Option<()> {
+        None
+    }
+
+    fn read_u32(&mut self, _addr: u32, _dma: DirectMemoryAccess<'_>) -> Option<u32> {
+        None
+    }
+}
+
+/// A vm about to run `INC` in the last byte of memory, and then off the end of it.
+fn at_end_of_memory() -> VirtualMachine {
+    let mut vm = VirtualMachine::new();
+    let last = MEM_SIZE - 1;
+    let mut code = vec![OP_LITB, 0x01, OP_LITW];
+    code.extend_from_slice(&last.to_le_bytes());
+    code.push(OP_JMP);
+    vm.dma().write(0x100, &code);
+    vm.dma().write_u8(last, OP_INC);
+
+    vm.run_for(&mut NoDevices, 0x100, 3).unwrap();
+    assert_eq!(vm.cpu().ip(), last);
+    vm
+}
+
+#[test]
+fn stepping_off_the_end_of_memory_faults() {
+    for step in [Debugger::step, Debugger::step_over, Debugger::step_out, Debugger::cont] {
+        let mut vm = at_end_of_memory();
+        let mut debugger = Debugger::new();
+        debugger.add_watchpoint(Watchpoint { addr: 0, length: 4, read: true, write: true });
+
+        let fault = step(&mut debugger, &mut vm, &mut NoDevices).and_then(|_| step(&mut debugger, &mut vm, &mut NoDevices));
+        assert_eq!(fault.unwrap_err().kind, FaultKind::InvalidJump(MEM_SIZE));
+    }
+}
+
+#[test]
+fn no_instruction_outside_of_memory() {
+    let mut vm = at_end_of_memory();
+    let debugger = Debugger::new();
+
+    assert!(debugger.instruction(&mut vm, MEM_SIZE).is_none());
+    assert!(debugger.read_memory(&mut vm, MEM_SIZE, 1).is_none());
+}
@@ -0,0 +1,129 @@
+use fox_bytecode::*;
+use fox_bytecode::memory::*;
+use fox_vm::{VirtualMachine, Machine, DirectMemoryAccess, Fault, FaultKind, Halt};
+use fox_vm::device::{Device, SystemDevice, match_device};
+use fox_bytecode::headers::header;
+
+/// Only the system device is mapped.
+struct SystemMachine {
+    system: SystemDevice,
+}
+
+impl SystemMachine {
+    const DEVICES: [(u32, u32); 1] = [
+        (SYSTEM_BASE, DEVICE_LENGTH), // 0
+    ];
+
+    fn device(&mut self, num: u32) -> Option<&mut dyn Device> {
+        let device: &mut dyn Device = match num {
+            0 => &mut self.system,
+            _ => return None,
+        };
+
+        Some(device)
+    }
+}
+
+impl Machine for SystemMachine {
+    fn write_u32(&mut self, addr: u32, value: u32, dma: DirectMemoryAccess<'_>) -> Option<()> {
+        let device = self.device(match_device(Self::DEVICES, addr))?;
+        device.write_u32(addr, value, dma)
+    }
+
+    fn read_u32(&mut self, addr: u32, dma: DirectMemoryAccess<'_>) -> Option<u32> {
+        let device = self.device(match_device(Self::DEVICES, addr))?;
+        device.read_u32(addr, dma)
+    }
+
+    fn write_u8(&mut self, addr: u32, value: u8, dma: DirectMemoryAccess<'_>) -> Option<()> {
+        let device = self.device(match_device(Self::DEVICES, addr))?;
+        device.write_u8(addr, value, dma)
+    }
+
+    fn read_u8(&mut self, addr: u32, dma: DirectMemoryAccess<'_>) -> Option<u8> {
+        let device = self.device(match_device(Self::DEVICES, addr))?;
+        device.read_u8(addr, dma)
+    }
+
+    fn fault(&mut self, fault: Fault) -> u32 {
+        self.system.fault(fault)
+    }
+}
+
+fn litw(code: &mut Vec<u8>, value: u32) {
+    code.push(OP_LITW);
+    code.extend_from_slice(&value.to_le_bytes());
+}
+
+/// Runs `op` on `addr`, with `1` below it to store.
+fn access(op: u8, addr: u32) -> Result<Halt, Fault> {
+    let mut code = vec![OP_LITB, 0x01, OP_LITW];
+    code.extend_from_slice(&addr.to_le_bytes());
+    code.extend_from_slice(&[op, OP_HALT]);
+
+    let mut vm = VirtualMachine::new();
+    let mut machine = SystemMachine { system: SystemDevice::new() };
+    vm.dma().write(0x100, &code);
+    vm.run(&mut machine, 0x100)
+}
+
+#[test]
+fn mapped_port_is_accessed() {
+    assert_eq!(access(OP_SW, SYSTEM_BASE + SYSTEM_VECTOR), Ok(Halt::Halted));
+    assert_eq!(access(OP_LB, SYSTEM_BASE + SYSTEM_READ), Ok(Halt::Halted));
+}
+
+#[test]
+fn unknown_port_faults() {
+    for op in [OP_LW, OP_SW, OP_SB] {
+        let addr = SYSTEM_BASE + 0x3C;
+        assert_eq!(access(op, addr), Err(Fault::new(FaultKind::InvalidAccess(addr), 0x107)));
+    }
+}
+
+#[test]
+fn unmapped_device_faults() {
+    for op in [OP_LW, OP_SW, OP_LB, OP_SB] {
+        let addr = CONSOLE_BASE;
+        assert_eq!(access(op, addr), Err(Fault::new(FaultKind::InvalidAccess(addr), 0x107)));
+    }
+}
+
+#[test]
+fn fault_vector_reads_the_fault_and_exits() {
+    let mut reset = Vec::new();
+    litw(&mut reset, 0x200);
+    litw(&mut reset, SYSTEM_BASE + SYSTEM_VECTOR);
+    reset.push(OP_SW);
+    litw(&mut reset, CONSOLE_BASE);
+    reset.extend_from_slice(&[OP_LW, OP_HALT]);
+
+    // Copy the fault registers to 0x3000, and exit with the kind of fault
+    let mut vector = Vec::new();
+    for (index, port) in [SYSTEM_FAULT, SYSTEM_FAULT_IP, SYSTEM_FAULT_OPERAND].into_iter().enumerate() {
+        litw(&mut vector, SYSTEM_BASE + port);
+        vector.push(OP_LW);
+        litw(&mut vector, 0x3000 + index as u32 * 4);
+        vector.push(OP_SW);
+    }
+    litw(&mut vector, SYSTEM_BASE + SYSTEM_FAULT);
+    vector.push(OP_LW);
+    litw(&mut vector, SYSTEM_BASE + SYSTEM_EXIT);
+    vector.extend_from_slice(&[OP_SW, OP_HALT]);
+
+    let mut vm = VirtualMachine::new();
+    let mut machine = SystemMachine { system: SystemDevice::new() };
+    vm.dma().write(0x100, &reset);
+    vm.dma().write(0x200, &vector);
+
+    assert_eq!(vm.run(&mut machine, 0x100), Ok(Halt::Halted));
+    assert_eq!(vm.dma().read_u32(0x3000), FAULT_INVALID_ACCESS);
+    assert_eq!(vm.dma().read_u32(0x3004), 0x110);
+    assert_eq!(vm.dma().read_u32(0x3008), CONSOLE_BASE);
+    assert_eq!(machine.system.exit, Some(FAULT_INVALID_ACCESS));
+}
+
+#[test]
+fn fault_operand_is_in_the_header() {
+    assert!(header("system.fox").unwrap().contains("|10010014 @system-fault-operand\n"));
+}
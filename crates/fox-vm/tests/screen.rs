@@ -0,0 +1,50 @@
+use fox_bytecode::memory::screen::{self, command};
+use fox_vm::VirtualMachine;
+use fox_vm::device::{Device, ScreenDevice};
+use fox_vm::device::screen::Display;
+
+struct NoDisplay;
+
+impl Display for NoDisplay {
+    fn resize(&mut self, width: u32, height: u32, zoom: u32) -> (u32, u32, u32) {
+        (width, height, zoom)
+    }
+
+    fn render(&mut self, _buffer: &[u8], _palette: &[u32; 16]) {}
+}
+
+/// Color of the pixel at `x`, `y` in layer 0, two pixels per byte with the left one on top.
+fn pixel(screen: &mut ScreenDevice<NoDisplay>, vm: &mut VirtualMachine, x: u32, y: u32) -> u8 {
+    let (width, _) = screen.size();
+    let byte = screen.read_u8(screen::LAYER0 + y * (width / 2) + x / 2, vm.dma()).unwrap();
+    if x & 1 == 0 { byte >> 4 } else { byte & 0xF }
+}
+
+#[test]
+fn repeated_sprite_draws_rows_under_each_other() {
+    let mut vm = VirtualMachine::new();
+    let mut screen = ScreenDevice::new(NoDisplay);
+
+    // 4 tiles of 4bpp, each filled with its own color
+    for tile in 0..4u8 {
+        let color = tile + 1;
+        vm.dma().write(0x2000 + tile as u32 * 32, &[(color << 4) | color; 32]);
+    }
+
+    let cmd = 0x1000;
+    vm.dma().write_u32(cmd + command::X, 16);
+    vm.dma().write_u32(cmd + command::Y, 8);
+    vm.dma().write_u32(cmd + command::SOURCE, 0x2000);
+    vm.dma().write_u8(cmd + command::COMMAND, command::COMMAND_SPRITE4 << 4);
+    vm.dma().write_u8(cmd + command::REPEAT, 0x11);
+
+    screen.write_u32(screen::CMD_LENGTH, 1, vm.dma()).unwrap();
+    screen.write_u32(screen::CMD_ADDR, cmd, vm.dma()).unwrap();
+
+    // Left to right, then top to bottom, each row starting at X
+    assert_eq!(pixel(&mut screen, &mut vm, 16, 8), 1);
+    assert_eq!(pixel(&mut screen, &mut vm, 24, 8), 2);
+    assert_eq!(pixel(&mut screen, &mut vm, 16, 16), 3);
+    assert_eq!(pixel(&mut screen, &mut vm, 31, 23), 4);
+    assert_eq!(pixel(&mut screen, &mut vm, 32, 16), 0);
+}
@@ -0,0 +1,38 @@
+use fox_vm::VirtualMachine;
+use fox_vm::device::SystemDevice;
+use fox_vm::snapshot::{self, Snapshot};
+
+fn save(part: &dyn Snapshot) -> Vec<u8> {
+    let mut out = Vec::new();
+    part.save(&mut out).unwrap();
+    out
+}
+
+#[test]
+fn huge_length_is_rejected_before_reading() {
+    let mut vm = VirtualMachine::new();
+    vm.dma().write_u8(0x100, 0x42);
+
+    // Claims 4 GB of memory, with nothing after it
+    let state = u32::MAX.to_le_bytes();
+    assert!(vm.restore(&mut state.as_slice()).is_err());
+    assert_eq!(vm.dma().read_u8(0x100), 0x42);
+}
+
+#[test]
+fn invalid_part_leaves_every_part_unchanged() {
+    let mut saved = VirtualMachine::new();
+    saved.dma().write_u8(0x100, 0x01);
+    let mut state = save(&saved);
+    // The system device after it is cut off
+    state.extend_from_slice(&[0; 3]);
+
+    let mut vm = VirtualMachine::new();
+    vm.dma().write_u8(0x100, 0x42);
+    let mut system = SystemDevice::with_args(std::iter::empty());
+    let before = save(&system);
+
+    assert!(snapshot::restore_all(&mut [&mut vm, &mut system], &mut state.as_slice()).is_err());
+    assert_eq!(vm.dma().read_u8(0x100), 0x42);
+    assert_eq!(save(&system), before);
+}
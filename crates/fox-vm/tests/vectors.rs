@@ -0,0 +1,61 @@
+use fox_bytecode::*;
+use fox_vm::{VirtualMachine, Machine, DirectMemoryAccess, Halt};
+
+struct NoDevices;
+
+impl Machine for NoDevices {
+    fn write_u32(&mut self, _addr: u32, _value: u32, _dma: DirectMemoryAccess<'_>) -> Option<()> {
+        None
+    }
+
+    fn read_u32(&mut self, _addr: u32, _dma: DirectMemoryAccess<'_>) -> Option<u32> {
+        None
+    }
+}
+
+/// A vector storing `value` at `addr` in 3 instructions, then halting.
+fn store(value: u8, addr: u32) -> Vec<u8> {
+    let mut code = vec![OP_LITB, value, OP_LITW];
+    code.extend_from_slice(&addr.to_le_bytes());
+    code.extend_from_slice(&[OP_SB, OP_HALT]);
+    code
+}
+
+#[test]
+fn vector_signalled_while_running_starts_after_it_halts() {
+    let mut vm = VirtualMachine::new();
+    let mut machine = NoDevices;
+    vm.dma().write(0x100, &store(1, 0x2000));
+    vm.dma().write(0x200, &store(2, 0x2001));
+
+    vm.signal(0x100);
+    assert_eq!(vm.run_pending(&mut machine, 2), Ok(Halt::Yielded));
+
+    // The first vector keeps running, the second waits for it
+    vm.signal(0x200);
+    assert_eq!(vm.run_pending(&mut machine, 1), Ok(Halt::Yielded));
+    assert_eq!(vm.dma().read_u8(0x2000), 1);
+    assert_eq!(vm.dma().read_u8(0x2001), 0);
+
+    assert_eq!(vm.run_pending(&mut machine, 10), Ok(Halt::Halted));
+    assert_eq!(vm.dma().read_u8(0x2001), 2);
+    assert!(!vm.is_running());
+}
+
+#[test]
+fn vector_is_only_queued_once() {
+    let mut vm = VirtualMachine::new();
+    let mut machine = NoDevices;
+    vm.dma().write(0x100, &store(1, 0x2000));
+    vm.dma().write(0x200, &[OP_LITW, 0x00, 0x20, 0x00, 0x00, OP_DUP, OP_LB, OP_INC, OP_SWAP, OP_SB, OP_HALT]);
+
+    vm.signal(0x100);
+    assert_eq!(vm.run_pending(&mut machine, 1), Ok(Halt::Yielded));
+    vm.signal(0x200);
+    vm.signal(0x200);
+    assert_eq!(vm.run_pending(&mut machine, 100), Ok(Halt::Halted));
+
+    // Set to 1 by the first vector, and incremented once by the second
+    assert_eq!(vm.dma().read_u8(0x2000), 2);
+    assert_eq!(vm.run_pending(&mut machine, 100), Ok(Halt::NoVector));
+}
@@ -1,5 +1,7 @@
-use fox_vm::{VirtualMachine, Machine, DirectMemoryAccess};
+use fox_vm::{VirtualMachine, Machine, DirectMemoryAccess, Fault};
 use fox_bytecode::memory::*;
+use fox_bytecode::symbols::Symbols;
+use fox_bytecode::lines::LineTable;
 use winit::event_loop::{EventLoop, EventLoopWindowTarget};
 use winit::event::{Event, StartCause};
 use fox_vm::device::{Device, match_device, FileDevice, SystemDevice, ConsoleDevice, ScreenDevice, MouseDevice, screen::Display, keyboard::KeyboardDevice};
@@ -8,6 +10,9 @@ use winit::window::{Window, WindowBuilder};
 use winit::dpi::LogicalSize;
 use fox_vm::device::keyboard::Key;
 use winit::event::VirtualKeyCode;
+use fox_vm::snapshot::{self, Snapshot};
+use std::io::{self, Read, Write};
+use std::path::Path;
 
 struct PixelDisplay {
     window: Option<Window>,
@@ -90,7 +95,7 @@ impl Display for PixelDisplay {
 
             let findex = index * 4;
             let [b,g,r,_] = u32::to_le_bytes(color);
-            frame[findex + 0] = r;
+            frame[findex] = r;
             frame[findex + 1] = g;
             frame[findex + 2] = b;
             frame[findex + 3] = 0xFF;
@@ -136,8 +141,8 @@ impl ScreenMachine {
         (KEYBOARD_BASE, DEVICE_LENGTH),       // 10
     ];
 
-    fn device(&mut self, num: u32) -> &mut dyn Device {
-        match num {
+    fn device(&mut self, num: u32) -> Option<&mut dyn Device> {
+        let device: &mut dyn Device = match num {
             0 => &mut self.system,
             1 => &mut self.console,
             2 => &mut self.screen,
@@ -149,8 +154,10 @@ impl ScreenMachine {
             8 => &mut self.file1,
             9 => &mut self.mouse,
             10 => &mut self.keyboard,
-            _ => unimplemented!(),
-        }
+            _ => return None,
+        };
+
+        Some(device)
     }
 
     pub fn new() -> Self {
@@ -167,25 +174,86 @@ impl ScreenMachine {
 }
 
 impl Machine for ScreenMachine {
-    fn write_u32(&mut self, addr: u32, value: u32, dma: DirectMemoryAccess<'_>) {
-        let device = self.device(match_device(Self::DEVICES, addr));
+    fn write_u32(&mut self, addr: u32, value: u32, dma: DirectMemoryAccess<'_>) -> Option<()> {
+        let device = self.device(match_device(Self::DEVICES, addr))?;
         device.write_u32(addr, value, dma)
     }
 
-    fn read_u32(&mut self, addr: u32, dma: DirectMemoryAccess<'_>) -> u32 {
-        let device = self.device(match_device(Self::DEVICES, addr));
+    fn read_u32(&mut self, addr: u32, dma: DirectMemoryAccess<'_>) -> Option<u32> {
+        let device = self.device(match_device(Self::DEVICES, addr))?;
         device.read_u32(addr, dma)
     }
 
-    fn write_u8(&mut self, addr: u32, value: u8, dma: DirectMemoryAccess<'_>) {
-        let device = self.device(match_device(Self::DEVICES, addr));
+    fn write_u8(&mut self, addr: u32, value: u8, dma: DirectMemoryAccess<'_>) -> Option<()> {
+        let device = self.device(match_device(Self::DEVICES, addr))?;
         device.write_u8(addr, value, dma)
     }
 
-    fn read_u8(&mut self, addr: u32, dma: DirectMemoryAccess<'_>) -> u8 {
-        let device = self.device(match_device(Self::DEVICES, addr));
+    fn read_u8(&mut self, addr: u32, dma: DirectMemoryAccess<'_>) -> Option<u8> {
+        let device = self.device(match_device(Self::DEVICES, addr))?;
         device.read_u8(addr, dma)
     }
+
+    fn fault(&mut self, fault: Fault) -> u32 {
+        self.system.fault(fault)
+    }
+}
+
+impl Snapshot for ScreenMachine {
+    fn save(&self, out: &mut dyn Write) -> io::Result<()> {
+        self.system.save(out)?;
+        self.console.save(out)?;
+        self.screen.save(out)?;
+        self.file0.save(out)?;
+        self.file1.save(out)?;
+        self.mouse.save(out)?;
+        self.keyboard.save(out)
+    }
+
+    fn restore(&mut self, input: &mut dyn Read) -> io::Result<()> {
+        snapshot::restore_all(&mut [
+            &mut self.system,
+            &mut self.console,
+            &mut self.screen,
+            &mut self.file0,
+            &mut self.file1,
+            &mut self.mouse,
+            &mut self.keyboard,
+        ], input)
+    }
+}
+
+fn save_state(path: &Path, vm: &VirtualMachine, machine: &ScreenMachine) -> io::Result<()> {
+    let mut out = io::BufWriter::new(std::fs::File::create(path)?);
+    snapshot::write_header(&mut out)?;
+    vm.save(&mut out)?;
+    machine.save(&mut out)?;
+    out.flush()
+}
+
+fn load_state(path: &Path, vm: &mut VirtualMachine, machine: &mut ScreenMachine) -> io::Result<()> {
+    let mut input = io::BufReader::new(std::fs::File::open(path)?);
+    snapshot::read_header(&mut input)?;
+    snapshot::restore_all(&mut [vm, machine], &mut input)
+}
+
+/// Instructions executed per event before handing control back to the event loop.
+const INSTRUCTION_BUDGET: usize = 1_000_000;
+
+/// Run `vector` once the vectors signalled before it have halted, continuing the one that is running.
+fn run(vm: &mut VirtualMachine, machine: &mut ScreenMachine, vector: u32) {
+    vm.signal(vector);
+    let result = vm.run_pending(machine, INSTRUCTION_BUDGET);
+
+    if let Err(fault) = result {
+        // The rom already asked to exit, so whatever comes after doesn't matter.
+        if let Some(exit) = machine.system.exit {
+            std::process::exit(exit as _);
+        }
+
+        eprintln!("Fault: {} ({})", fault, vm.location(fault.ip));
+        std::process::exit(1);
+    }
 }
 
 pub fn main() {
@@ -197,11 +265,16 @@ pub fn main() {
 
     let mut machine = ScreenMachine::new();
     let mut vm = VirtualMachine::new();
+    let state_path = Path::new(&args[1]).with_extension("state");
 
     // Load rom
     {
         let data = std::fs::read(&args[1]).unwrap();
         vm.load(&data);
+
+        let path = Path::new(&args[1]);
+        vm.set_symbols(Symbols::load(&path.with_extension("sym")).unwrap_or_default());
+        vm.set_lines(LineTable::load(&path.with_extension("lines")).unwrap_or_default());
     }
 
     let event_loop = EventLoop::new();
@@ -216,13 +289,11 @@ pub fn main() {
             Event::NewEvents(StartCause::Init) => {
                 machine.screen.display.init(event_loop, machine.screen.size());
 
-                vm.run(&mut machine, RESET_VECTOR);
+                run(&mut vm, &mut machine, RESET_VECTOR);
             },
             Event::RedrawRequested(_) => {
                 let vector = machine.screen.vector;
-                if vector != 0 {
-                    vm.run(&mut machine, vector);
-                }
+                run(&mut vm, &mut machine, vector);
 
                 machine.screen.render();
             },
@@ -230,26 +301,20 @@ pub fn main() {
                 machine.mouse.set_entered(false);
 
                 let vector = machine.mouse.vector;
-                if vector != 0 {
-                    vm.run(&mut machine, vector);
-                }
+                run(&mut vm, &mut machine, vector);
             },
             Event::WindowEvent { event: WindowEvent::CursorEntered { .. }, ..  } => {
                 machine.mouse.set_entered(true);
 
                 let vector = machine.mouse.vector;
-                if vector != 0 {
-                    vm.run(&mut machine, vector);
-                }
+                run(&mut vm, &mut machine, vector);
             },
             Event::WindowEvent { event: WindowEvent::CursorMoved { position, .. }, ..  } => {
                 let position = machine.screen.display.position(position);
                 machine.mouse.set_position(position);
 
                 let vector = machine.mouse.vector;
-                if vector != 0 {
-                    vm.run(&mut machine, vector);
-                }
+                run(&mut vm, &mut machine, vector);
             },
             Event::WindowEvent { event: WindowEvent::MouseInput { state, button, .. }, ..  } => {
                 let pressed = match state {
@@ -265,31 +330,37 @@ pub fn main() {
                 }
 
                 let vector = machine.mouse.vector;
-                if vector != 0 {
-                    vm.run(&mut machine, vector);
-                }
+                run(&mut vm, &mut machine, vector);
             },
             Event::WindowEvent { event: WindowEvent::ReceivedCharacter(character), .. } => {
                 machine.keyboard.on_char(character);
 
                 let vector = machine.keyboard.vector;
-                if vector != 0 {
-                    vm.run(&mut machine, vector);
-                }
+                run(&mut vm, &mut machine, vector);
             },
             Event::WindowEvent { event: WindowEvent::KeyboardInput { input, .. }, .. } => {
                 if let Some(keycode) = input.virtual_keycode {
+                    let pressed = input.state == winit::event::ElementState::Pressed;
+
+                    // Save states
+                    if pressed && keycode == VirtualKeyCode::F5 {
+                        match save_state(&state_path, &vm, &machine) {
+                            Ok(()) => eprintln!("Saved state to {}", state_path.display()),
+                            Err(err) => eprintln!("Could not save state: {}", err),
+                        }
+                    }
+                    if pressed && keycode == VirtualKeyCode::F9 {
+                        match load_state(&state_path, &mut vm, &mut machine) {
+                            Ok(()) => eprintln!("Loaded state from {}", state_path.display()),
+                            Err(err) => eprintln!("Could not load state: {}", err),
+                        }
+                    }
+
                     if let Some(key) = map_key(keycode) {
-                        let pressed = match input.state {
-                            winit::event::ElementState::Pressed => true,
-                            winit::event::ElementState::Released => false,
-                        };
                         machine.keyboard.on_key(key, pressed);
 
                         let vector = machine.keyboard.vector;
-                        if vector != 0 {
-                            vm.run(&mut machine, vector);
-                        }
+                        run(&mut vm, &mut machine, vector);
                     }
                 }
             },
@@ -298,9 +369,7 @@ pub fn main() {
 
         if machine.console.read_nonblock() {
             let vector = machine.console.vector;
-            if vector != 0 {
-                vm.run(&mut machine, vector);
-            }
+            run(&mut vm, &mut machine, vector);
         }
 
         if let Some(code) = machine.system.exit {